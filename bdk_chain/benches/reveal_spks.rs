@@ -0,0 +1,64 @@
+use bdk_chain::{
+    keychain::KeychainTxOutIndex,
+    miniscript::{Descriptor, DescriptorPublicKey},
+};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+const REVEAL_COUNT: u32 = 10_000;
+const SEQUENTIAL_REVEAL_COUNT: u32 = 5_000;
+
+fn init_txout_index(lookahead: u32) -> KeychainTxOutIndex<()> {
+    let secp = bdk_chain::bitcoin::secp256k1::Secp256k1::signing_only();
+    let (descriptor, _) = Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, "tr([73c5da0a/86'/0'/0']xprv9xgqHN7yz9MwCkxsBPN5qetuNdQSUttZNKw1dcYTV4mkaAFiBVGQziHs3NRSWMkCzvgjEe3n9xV8oYywvM8at9yRqyaZVz6TYYhX98VjsUk/0/*)").unwrap();
+
+    let mut txout_index = KeychainTxOutIndex::default();
+    txout_index.add_keychain((), descriptor);
+    txout_index.set_lookahead(&(), lookahead);
+    txout_index
+}
+
+fn reveal_one_at_a_time(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reveal_next_spk");
+    group.sample_size(10);
+
+    for lookahead in [0, 100] {
+        group.bench_function(
+            format!("{} spks, lookahead {}", SEQUENTIAL_REVEAL_COUNT, lookahead),
+            |b| {
+                b.iter_batched(
+                    || init_txout_index(lookahead),
+                    |mut txout_index| {
+                        for _ in 0..SEQUENTIAL_REVEAL_COUNT {
+                            let _ = txout_index.reveal_next_spk(&());
+                        }
+                        txout_index
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn reveal_all_at_once(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reveal_to_target");
+    group.sample_size(10);
+
+    group.bench_function(format!("{} spks", REVEAL_COUNT), |b| {
+        b.iter_batched(
+            || init_txout_index(0),
+            |mut txout_index| {
+                let _ = txout_index.reveal_to_target(&(), REVEAL_COUNT - 1);
+                txout_index
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, reveal_one_at_a_time, reveal_all_at_once);
+criterion_main!(benches);
@@ -16,7 +16,7 @@
 //! [`SpkTxOutIndex`]: crate::SpkTxOutIndex
 use crate::{
     chain_graph::{self, ChainGraph},
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     sparse_chain::ChainPosition,
     tx_graph::TxGraph,
     AsTransaction, ForEachTxOut,
@@ -84,6 +84,33 @@ impl<K: Ord> DerivationAdditions<K> {
 
         self.0.append(&mut other.0);
     }
+
+    /// Insert a single `keychain` with its new derivation `index` into self.
+    ///
+    /// Like [`append`], the index is only increased if `keychain` already exists.
+    ///
+    /// [`append`]: Self::append
+    pub fn insert(&mut self, keychain: K, index: u32) {
+        let entry = self.0.entry(keychain).or_insert(index);
+        *entry = index.max(*entry);
+    }
+
+    /// Returns the entries of `self` that have a higher index than in `other` (or that do not exist
+    /// in `other`).
+    ///
+    /// If `other` is an older state than `self`, this is the delta that was revealed in between.
+    pub fn difference(&self, other: &Self) -> Self
+    where
+        K: Clone,
+    {
+        Self(
+            self.0
+                .iter()
+                .filter(|(key, index)| other.0.get(key) < Some(index))
+                .map(|(key, index)| (key.clone(), *index))
+                .collect(),
+        )
+    }
 }
 
 impl<K> Default for DerivationAdditions<K> {
@@ -98,6 +125,79 @@ impl<K> AsRef<BTreeMap<K, u32>> for DerivationAdditions<K> {
     }
 }
 
+/// Represents changes to the keychains of a [`KeychainTxOutIndex`], including keychain removals.
+///
+/// Unlike [`DerivationAdditions`], which can only express increases of the revealed derivation
+/// index, this also records keychains that were removed so replaying persisted changes does not
+/// bring them back. Removals are applied before reveals, so a keychain that is removed and then
+/// added again is recorded in both `removed` and `revealed`.
+///
+/// [`KeychainTxOutIndex`]: crate::keychain::KeychainTxOutIndex
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(
+        crate = "serde_crate",
+        bound(
+            deserialize = "K: Ord + serde::Deserialize<'de>",
+            serialize = "K: Ord + serde::Serialize"
+        )
+    )
+)]
+#[must_use]
+pub struct KeychainDelta<K> {
+    /// The new last revealed index of each keychain.
+    pub revealed: BTreeMap<K, u32>,
+    /// Keychains that were removed.
+    pub removed: BTreeSet<K>,
+}
+
+impl<K> KeychainDelta<K> {
+    /// Returns whether the delta is empty (no changes recorded).
+    pub fn is_empty(&self) -> bool {
+        self.revealed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl<K: Ord> KeychainDelta<K> {
+    /// Appends `other` into `self` such that applying `self` afterwards has the same effect as
+    /// sequentially applying the original `self` and `other`.
+    ///
+    /// A delta removes its `removed` keychains before revealing the `revealed` indices, so a
+    /// removal in `other` discards the revealed index of the keychain in `self`, while a reveal in
+    /// `other` of a keychain removed in `self` (i.e. the keychain was added again) is kept along
+    /// with the removal. Otherwise, revealed indices are merged like [`DerivationAdditions::append`].
+    pub fn append(&mut self, other: Self) {
+        for keychain in other.removed {
+            self.revealed.remove(&keychain);
+            self.removed.insert(keychain);
+        }
+        for (keychain, index) in other.revealed {
+            let entry = self.revealed.entry(keychain).or_insert(index);
+            *entry = (*entry).max(index);
+        }
+    }
+}
+
+impl<K> Default for KeychainDelta<K> {
+    fn default() -> Self {
+        Self {
+            revealed: Default::default(),
+            removed: Default::default(),
+        }
+    }
+}
+
+impl<K> From<DerivationAdditions<K>> for KeychainDelta<K> {
+    fn from(additions: DerivationAdditions<K>) -> Self {
+        Self {
+            revealed: additions.0,
+            removed: Default::default(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 /// An update that includes the last active indexes of each keychain.
 pub struct KeychainScan<K, P, T = Transaction> {
@@ -127,7 +227,7 @@ impl<K, P, T> From<ChainGraph<P, T>> for KeychainScan<K, P, T> {
 
 /// Represents changes to a [`KeychainTracker`].
 ///
-/// This is essentially a combination of [`DerivationAdditions`] and [`chain_graph::ChangeSet`].
+/// This is essentially a combination of [`KeychainDelta`] and [`chain_graph::ChangeSet`].
 #[derive(Clone, Debug)]
 #[cfg_attr(
     feature = "serde",
@@ -142,8 +242,8 @@ impl<K, P, T> From<ChainGraph<P, T>> for KeychainScan<K, P, T> {
 )]
 #[must_use]
 pub struct KeychainChangeSet<K, P, T = Transaction> {
-    /// The changes in local keychain derivation indices
-    pub derivation_indices: DerivationAdditions<K>,
+    /// The changes in local keychain derivation indices and the keychains that were removed
+    pub derivation_indices: KeychainDelta<K>,
     /// The changes that have occurred in the blockchain
     pub chain_graph: chain_graph::ChangeSet<P, T>,
 }
@@ -167,7 +267,8 @@ impl<K, P, T> KeychainChangeSet<K, P, T> {
     /// effect as sequentially applying the original `self` and `other`.
     ///
     /// Note the derivation indices cannot be decreased so `other` will only change the derivation
-    /// index for a keychain if it's entry is higher than the one in `self`.
+    /// index for a keychain if it's entry is higher than the one in `self`, unless `other` removes
+    /// the keychain (see [`KeychainDelta::append`]).
     pub fn append(&mut self, other: KeychainChangeSet<K, P, T>)
     where
         K: Ord,
@@ -191,7 +292,16 @@ impl<K, P, T> From<chain_graph::ChangeSet<P, T>> for KeychainChangeSet<K, P, T>
 impl<K, P, T> From<DerivationAdditions<K>> for KeychainChangeSet<K, P, T> {
     fn from(additions: DerivationAdditions<K>) -> Self {
         Self {
-            derivation_indices: additions,
+            derivation_indices: additions.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl<K, P, T> From<KeychainDelta<K>> for KeychainChangeSet<K, P, T> {
+    fn from(delta: KeychainDelta<K>) -> Self {
+        Self {
+            derivation_indices: delta,
             ..Default::default()
         }
     }
@@ -240,6 +350,20 @@ impl Balance {
     pub fn total(&self) -> u64 {
         self.confirmed + self.trusted_pending + self.untrusted_pending + self.immature
     }
+
+    /// Subtract `other` from `self` in every category.
+    ///
+    /// Returns `None` if any category would underflow.
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        Some(Self {
+            immature: self.immature.checked_sub(other.immature)?,
+            trusted_pending: self.trusted_pending.checked_sub(other.trusted_pending)?,
+            untrusted_pending: self
+                .untrusted_pending
+                .checked_sub(other.untrusted_pending)?,
+            confirmed: self.confirmed.checked_sub(other.confirmed)?,
+        })
+    }
 }
 
 impl core::fmt::Display for Balance {
@@ -265,6 +389,28 @@ impl core::ops::Add for Balance {
     }
 }
 
+impl core::iter::Sum for Balance {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Balance::default(), |acc, balance| acc + balance)
+    }
+}
+
+/// Subtracts each category, saturating at zero. Use [`Balance::checked_sub`] to detect underflow.
+impl core::ops::Sub for Balance {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            immature: self.immature.saturating_sub(other.immature),
+            trusted_pending: self.trusted_pending.saturating_sub(other.trusted_pending),
+            untrusted_pending: self
+                .untrusted_pending
+                .saturating_sub(other.untrusted_pending),
+            confirmed: self.confirmed.saturating_sub(other.confirmed),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use bitcoin::Transaction;
@@ -290,24 +436,167 @@ mod test {
         lhs_di.insert(Keychain::Three, 3);
         rhs_di.insert(Keychain::Four, 4);
         let mut lhs = KeychainChangeSet {
-            derivation_indices: DerivationAdditions(lhs_di),
+            derivation_indices: DerivationAdditions(lhs_di).into(),
             chain_graph: chain_graph::ChangeSet::<TxHeight, Transaction>::default(),
         };
 
         let rhs = KeychainChangeSet {
-            derivation_indices: DerivationAdditions(rhs_di),
+            derivation_indices: DerivationAdditions(rhs_di).into(),
             chain_graph: chain_graph::ChangeSet::<TxHeight, Transaction>::default(),
         };
 
         lhs.append(rhs);
 
         // Exiting index doesn't update if new index in `other` is lower than `self`
-        assert_eq!(lhs.derivation_indices.0.get(&Keychain::One), Some(&7));
+        assert_eq!(
+            lhs.derivation_indices.revealed.get(&Keychain::One),
+            Some(&7)
+        );
         // Existing index updates if new index in `other` is higher than `self.
-        assert_eq!(lhs.derivation_indices.0.get(&Keychain::Two), Some(&5));
+        assert_eq!(
+            lhs.derivation_indices.revealed.get(&Keychain::Two),
+            Some(&5)
+        );
         // Existing index unchanged, if keychain doesn't exist in `other`
-        assert_eq!(lhs.derivation_indices.0.get(&Keychain::Three), Some(&3));
+        assert_eq!(
+            lhs.derivation_indices.revealed.get(&Keychain::Three),
+            Some(&3)
+        );
         // New keychain gets added if keychain is in `other`, but not in `self`.
-        assert_eq!(lhs.derivation_indices.0.get(&Keychain::Four), Some(&4));
+        assert_eq!(
+            lhs.derivation_indices.revealed.get(&Keychain::Four),
+            Some(&4)
+        );
+    }
+
+    #[test]
+    fn balance_sum() {
+        let balances = (1..=3).map(|i| Balance {
+            immature: i,
+            trusted_pending: 10 * i,
+            untrusted_pending: 100 * i,
+            confirmed: 1000 * i,
+        });
+        assert_eq!(
+            balances.sum::<Balance>(),
+            Balance {
+                immature: 6,
+                trusted_pending: 60,
+                untrusted_pending: 600,
+                confirmed: 6000,
+            }
+        );
+        assert_eq!(
+            core::iter::empty::<Balance>().sum::<Balance>(),
+            Balance::default()
+        );
+    }
+
+    #[test]
+    fn balance_sub() {
+        let lhs = Balance {
+            immature: 10,
+            trusted_pending: 20,
+            untrusted_pending: 30,
+            confirmed: 40,
+        };
+        let rhs = Balance {
+            immature: 1,
+            trusted_pending: 2,
+            untrusted_pending: 3,
+            confirmed: 50,
+        };
+
+        assert_eq!(
+            lhs.clone() - rhs.clone(),
+            Balance {
+                immature: 9,
+                trusted_pending: 18,
+                untrusted_pending: 27,
+                confirmed: 0,
+            }
+        );
+        assert_eq!(lhs.clone().checked_sub(rhs.clone()), None);
+
+        let rhs = Balance {
+            confirmed: 4,
+            ..rhs
+        };
+        assert_eq!(
+            lhs.clone().checked_sub(rhs.clone()),
+            Some(lhs.clone() - rhs.clone())
+        );
+        assert_eq!((lhs.clone() + rhs.clone()) - rhs, lhs);
+    }
+
+    #[test]
+    fn derivation_additions_difference() {
+        #[derive(Ord, PartialOrd, Eq, PartialEq, Clone, Debug)]
+        enum Keychain {
+            One,
+            Two,
+            Three,
+            Four,
+        }
+        let new = DerivationAdditions(
+            [(Keychain::One, 7), (Keychain::Two, 5), (Keychain::Three, 3)].into(),
+        );
+        let old = DerivationAdditions(
+            [(Keychain::One, 3), (Keychain::Two, 5), (Keychain::Four, 4)].into(),
+        );
+
+        // only keychains with a higher index (or that are new) are in the difference
+        assert_eq!(
+            new.difference(&old),
+            DerivationAdditions([(Keychain::One, 7), (Keychain::Three, 3)].into())
+        );
+        assert_eq!(
+            old.difference(&new),
+            DerivationAdditions([(Keychain::Four, 4)].into())
+        );
+        assert!(new.difference(&new).is_empty());
+
+        // appending the difference to the old state gives the new state
+        let mut appended = old.clone();
+        appended.append(new.difference(&old));
+        assert_eq!(appended.difference(&old), new.difference(&old));
+    }
+
+    #[test]
+    fn insert_derivation_additions() {
+        let mut additions = DerivationAdditions::<u8>::default();
+        additions.insert(0, 5);
+        additions.insert(1, 2);
+        additions.insert(0, 3);
+        assert_eq!(additions, DerivationAdditions([(0, 5), (1, 2)].into()));
+
+        additions.insert(1, 4);
+        let mut appended = DerivationAdditions([(0, 5), (1, 2)].into());
+        appended.append(DerivationAdditions([(1, 4)].into()));
+        assert_eq!(additions, appended);
+    }
+
+    #[test]
+    fn append_keychain_delta() {
+        let mut delta = KeychainDelta::<u8>::from(DerivationAdditions([(0, 5), (1, 2)].into()));
+
+        // removing a keychain discards its revealed index
+        delta.append(KeychainDelta {
+            revealed: [(0, 7)].into(),
+            removed: [1].into(),
+        });
+        assert_eq!(delta.revealed, [(0, 7)].into());
+        assert_eq!(delta.removed, [1].into());
+
+        // a keychain added back after removal is revealed from scratch
+        delta.append(KeychainDelta {
+            revealed: [(0, 3), (1, 1)].into(),
+            removed: Default::default(),
+        });
+        assert_eq!(delta.revealed, [(0, 7), (1, 1)].into());
+        assert_eq!(delta.removed, [1].into());
+
+        assert!(KeychainDelta::<u8>::default().is_empty());
+        assert!(!delta.is_empty());
     }
 }
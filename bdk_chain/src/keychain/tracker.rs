@@ -80,7 +80,7 @@ where
         });
 
         Ok(KeychainChangeSet {
-            derivation_indices: DerivationAdditions(derivation_indices),
+            derivation_indices: DerivationAdditions(derivation_indices).into(),
             chain_graph: self.chain_graph.determine_changeset(&scan.update)?,
         })
     }
@@ -105,16 +105,11 @@ where
 
     /// Applies the changes in `changeset` to [`KeychainTracker`].
     ///
-    /// Internally, this calls [`KeychainTxOutIndex::apply_additions`] and
+    /// Internally, this calls [`KeychainTxOutIndex::apply_changeset`] and
     /// [`ChainGraph::apply_changeset`] in sequence.
     pub fn apply_changeset(&mut self, changeset: KeychainChangeSet<K, P, T>) {
-        let KeychainChangeSet {
-            derivation_indices,
-            chain_graph,
-        } = changeset;
-        self.txout_index.apply_additions(derivation_indices);
-        let _ = self.txout_index.scan(&chain_graph);
-        self.chain_graph.apply_changeset(chain_graph)
+        self.txout_index.apply_changeset(&changeset);
+        self.chain_graph.apply_changeset(changeset.chain_graph)
     }
 
     /// Iterates through [`FullTxOut`]s that are considered to exist in our representation of the
@@ -235,46 +230,61 @@ where
     ///
     /// When in doubt set `should_trust` to return false. This doesn't do anything other than change
     /// where the unconfirmed output's value is accounted for in `Balance`.
-    pub fn balance(&self, mut should_trust: impl FnMut(&K) -> bool) -> Balance {
-        let mut immature = 0;
-        let mut trusted_pending = 0;
-        let mut untrusted_pending = 0;
-        let mut confirmed = 0;
+    pub fn balance(&self, should_trust: impl FnMut(&K) -> bool) -> Balance {
         let last_sync_height = self.chain().latest_checkpoint().map(|latest| latest.height);
-        for ((keychain, _), utxo) in self.full_utxos() {
-            let chain_position = &utxo.chain_position;
-
-            match chain_position.height() {
-                TxHeight::Confirmed(_) => {
-                    if utxo.is_on_coinbase {
-                        if utxo.is_mature(
-                            last_sync_height
-                                .expect("since it's confirmed we must have a checkpoint"),
-                        ) {
-                            confirmed += utxo.txout.value;
-                        } else {
-                            immature += utxo.txout.value;
-                        }
-                    } else {
-                        confirmed += utxo.txout.value;
-                    }
-                }
-                TxHeight::Unconfirmed => {
-                    if should_trust(keychain) {
-                        trusted_pending += utxo.txout.value;
-                    } else {
-                        untrusted_pending += utxo.txout.value;
-                    }
-                }
-            }
-        }
+        balance_of_utxos(
+            self.full_utxos()
+                .map(|((keychain, _), utxo)| (keychain, utxo)),
+            last_sync_height,
+            0,
+            should_trust,
+        )
+    }
 
-        Balance {
-            immature,
-            trusted_pending,
-            untrusted_pending,
-            confirmed,
-        }
+    /// Returns the *balance* of the tracker like [`balance`], but confirmed outputs need at least
+    /// `min_confirmations` confirmations at `tip_height` to be counted as `confirmed`.
+    ///
+    /// Confirmed outputs with fewer confirmations are counted as pending (trusted or untrusted
+    /// depending on `should_trust`). Coinbase outputs are immature until they mature at
+    /// `tip_height`.
+    ///
+    /// [`balance`]: Self::balance
+    pub fn balance_with_min_confirmations(
+        &self,
+        tip_height: u32,
+        min_confirmations: u32,
+        should_trust: impl FnMut(&K) -> bool,
+    ) -> Balance {
+        balance_of_utxos(
+            self.full_utxos()
+                .map(|((keychain, _), utxo)| (keychain, utxo)),
+            Some(tip_height),
+            min_confirmations,
+            should_trust,
+        )
+    }
+
+    /// Returns the *balance* of a single `keychain` i.e. the value of unspent transaction outputs
+    /// with script pubkeys derived from `keychain`.
+    ///
+    /// Outputs are categorized the same way as in [`balance`], with `should_trust` deciding whether
+    /// unconfirmed outputs of `keychain` are trusted. Coinbase outputs are immature until they
+    /// mature at `tip_height`.
+    ///
+    /// [`balance`]: Self::balance
+    pub fn balance_of_keychain(
+        &self,
+        keychain: &K,
+        tip_height: u32,
+        should_trust: impl FnMut(&K) -> bool,
+    ) -> Balance {
+        let utxos = self
+            .txout_index
+            .txouts_of_keychain(keychain)
+            .filter_map(|(_, op)| self.chain_graph.full_txout(op))
+            .filter(|txout| txout.spent_by.is_none())
+            .map(|utxo| (keychain, utxo));
+        balance_of_utxos(utxos, Some(tip_height), 0, should_trust)
     }
 
     /// Returns the balance of all spendable confirmed unspent outputs of this tracker at a
@@ -287,6 +297,113 @@ where
     }
 }
 
+/// Returns the *balance* of the unspent outputs in `chain_graph` which are indexed by
+/// `txout_index`.
+///
+/// Coinbase outputs are `immature` until they mature at `tip_height`. Unconfirmed outputs of every
+/// keychain are `trusted_pending`, since all of them are owned by the wallet. Use
+/// [`balance_with_trust`] to decide which keychains are trusted, e.g. to only trust the internal
+/// (change) keychain since outputs on it can only be created by the wallet itself.
+///
+/// This is useful when the [`ChainGraph`] and [`KeychainTxOutIndex`] are not kept together in a
+/// [`KeychainTracker`] (see [`KeychainTracker::balance`]).
+pub fn balance<K, P, T>(
+    chain_graph: &ChainGraph<P, T>,
+    txout_index: &KeychainTxOutIndex<K>,
+    tip_height: u32,
+) -> Balance
+where
+    K: Ord + Clone + core::fmt::Debug,
+    P: sparse_chain::ChainPosition,
+    T: AsTransaction + Clone + Ord,
+{
+    balance_with_trust(chain_graph, txout_index, tip_height, |_| true)
+}
+
+/// Returns the *balance* like [`balance`], but unconfirmed outputs are `trusted_pending` if they
+/// belong to a keychain for which `is_trusted` returns true.
+///
+/// This lets applications decide which keychains count as trusted for pending funds, e.g. to
+/// implement conservative deposit-crediting policies. Trusting all keychains (`|_| true`) is the
+/// same as [`balance`].
+pub fn balance_with_trust<K, P, T>(
+    chain_graph: &ChainGraph<P, T>,
+    txout_index: &KeychainTxOutIndex<K>,
+    tip_height: u32,
+    is_trusted: impl Fn(&K) -> bool,
+) -> Balance
+where
+    K: Ord + Clone + core::fmt::Debug,
+    P: sparse_chain::ChainPosition,
+    T: AsTransaction + Clone + Ord,
+{
+    let utxos = txout_index
+        .txouts()
+        .filter_map(|((keychain, _), op, _)| Some((keychain, chain_graph.full_txout(op)?)))
+        .filter(|(_, utxo)| utxo.spent_by.is_none());
+    balance_of_utxos(utxos, Some(tip_height), 0, is_trusted)
+}
+
+fn balance_of_utxos<'a, K: 'a, P: sparse_chain::ChainPosition>(
+    utxos: impl Iterator<Item = (&'a K, FullTxOut<P>)>,
+    tip_height: Option<u32>,
+    min_confirmations: u32,
+    mut should_trust: impl FnMut(&K) -> bool,
+) -> Balance {
+    let mut immature = 0;
+    let mut trusted_pending = 0;
+    let mut untrusted_pending = 0;
+    let mut confirmed = 0;
+    for (keychain, utxo) in utxos {
+        let chain_position = &utxo.chain_position;
+
+        // confirmed outputs without enough confirmations are treated as unconfirmed
+        let height = match chain_position.height() {
+            TxHeight::Confirmed(height) => {
+                let tip_height =
+                    tip_height.expect("since it's confirmed we must have a checkpoint");
+                let confirmations = tip_height.checked_sub(height).map_or(0, |d| d + 1);
+                if utxo.is_on_coinbase || confirmations >= min_confirmations {
+                    TxHeight::Confirmed(height)
+                } else {
+                    TxHeight::Unconfirmed
+                }
+            }
+            TxHeight::Unconfirmed => TxHeight::Unconfirmed,
+        };
+
+        match height {
+            TxHeight::Confirmed(_) => {
+                if utxo.is_on_coinbase {
+                    if utxo.is_mature(
+                        tip_height.expect("since it's confirmed we must have a checkpoint"),
+                    ) {
+                        confirmed += utxo.txout.value;
+                    } else {
+                        immature += utxo.txout.value;
+                    }
+                } else {
+                    confirmed += utxo.txout.value;
+                }
+            }
+            TxHeight::Unconfirmed => {
+                if should_trust(keychain) {
+                    trusted_pending += utxo.txout.value;
+                } else {
+                    untrusted_pending += utxo.txout.value;
+                }
+            }
+        }
+    }
+
+    Balance {
+        immature,
+        trusted_pending,
+        untrusted_pending,
+        confirmed,
+    }
+}
+
 impl<K, P> Default for KeychainTracker<K, P> {
     fn default() -> Self {
         Self {
@@ -1,13 +1,21 @@
 use crate::{
     collections::*,
     miniscript::{Descriptor, DescriptorPublicKey},
-    ForEachTxOut, SpkTxOutIndex,
+    AsTransaction, ForEachTxOut, SpkTxOutIndex,
+};
+use alloc::{borrow::Cow, boxed::Box, string::String, sync::Arc, vec::Vec};
+use bitcoin::{
+    secp256k1::{Secp256k1, VerifyOnly},
+    util::address,
+    Address, Network, OutPoint, Script, TxOut,
+};
+use core::{
+    cell::OnceCell,
+    fmt::Debug,
+    ops::{Deref, Range},
 };
-use alloc::{borrow::Cow, vec::Vec};
-use bitcoin::{secp256k1::Secp256k1, OutPoint, Script, TxOut};
-use core::{fmt::Debug, ops::Deref};
 
-use super::DerivationAdditions;
+use super::{DerivationAdditions, KeychainChangeSet, KeychainDelta};
 
 /// Maximum [BIP32](https://bips.xyz/32) derivation index.
 pub const BIP32_MAX_INDEX: u32 = 1 << 31 - 1;
@@ -67,6 +75,13 @@ pub struct KeychainTxOutIndex<K> {
     last_revealed: BTreeMap<K, u32>,
     // lookahead settings for each keychain
     lookahead: BTreeMap<K, u32>,
+    // keychains restored lazily whose script pubkeys are not derived yet
+    deferred: BTreeSet<K>,
+    // `inner` with the deferred keychains derived, filled by the first read query and adopted as
+    // `inner` by the next mutation
+    derived: OnceCell<SpkTxOutIndex<(K, u32)>>,
+    // context shared by all derivations
+    secp: Arc<Secp256k1<VerifyOnly>>,
 }
 
 impl<K> Default for KeychainTxOutIndex<K> {
@@ -76,19 +91,180 @@ impl<K> Default for KeychainTxOutIndex<K> {
             keychains: BTreeMap::default(),
             last_revealed: BTreeMap::default(),
             lookahead: BTreeMap::default(),
+            deferred: BTreeSet::default(),
+            derived: OnceCell::new(),
+            secp: Arc::new(Secp256k1::verification_only()),
+        }
+    }
+}
+
+/// The persisted form of [`KeychainTxOutIndex`]. Script pubkeys are derived again on load.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(
+    crate = "serde_crate",
+    bound(
+        deserialize = "K: Ord + serde::Deserialize<'de>, D: serde::Deserialize<'de>",
+        serialize = "K: Ord + serde::Serialize, D: serde::Serialize"
+    )
+)]
+struct PersistedTxOutIndex<K, D> {
+    keychains: BTreeMap<K, D>,
+    last_revealed: BTreeMap<K, u32>,
+    lookahead: BTreeMap<K, u32>,
+}
+
+#[cfg(feature = "serde")]
+impl<K: Ord + serde::Serialize> serde::Serialize for KeychainTxOutIndex<K> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PersistedTxOutIndex {
+            keychains: self.keychains.iter().collect(),
+            last_revealed: self.last_revealed.iter().map(|(k, i)| (k, *i)).collect(),
+            lookahead: self.lookahead.iter().map(|(k, l)| (k, *l)).collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K> serde::Deserialize<'de> for KeychainTxOutIndex<K>
+where
+    K: Clone + Ord + Debug + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let persisted =
+            PersistedTxOutIndex::<K, Descriptor<DescriptorPublicKey>>::deserialize(deserializer)?;
+        if let Some(keychain) = persisted
+            .last_revealed
+            .keys()
+            .find(|keychain| !persisted.keychains.contains_key(keychain))
+        {
+            return Err(serde::de::Error::custom(format!(
+                "last revealed index of unknown keychain {:?}",
+                keychain
+            )));
         }
+        Ok(Self::from_persisted(
+            persisted.keychains,
+            persisted.last_revealed,
+            persisted.lookahead,
+        ))
     }
 }
 
-impl<K> Deref for KeychainTxOutIndex<K> {
+impl<K: Clone + Ord + Debug> Deref for KeychainTxOutIndex<K> {
     type Target = SpkTxOutIndex<(K, u32)>;
 
     fn deref(&self) -> &Self::Target {
-        &self.inner
+        self.inner()
     }
 }
 
 impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
+    /// Rebuild a [`KeychainTxOutIndex`] from persisted `keychains`, `last_revealed` indices and
+    /// `lookahead` settings.
+    ///
+    /// Script pubkeys are derived up to the last revealed index (plus the lookahead) of each
+    /// keychain. Txouts are not persisted so they must be scanned again (see [`scan`]).
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `last_revealed` contains a keychain that is not in `keychains`.
+    ///
+    /// [`scan`]: Self::scan
+    pub fn from_persisted(
+        keychains: BTreeMap<K, Descriptor<DescriptorPublicKey>>,
+        last_revealed: BTreeMap<K, u32>,
+        lookahead: BTreeMap<K, u32>,
+    ) -> Self {
+        let mut txout_index = Self {
+            keychains,
+            lookahead,
+            ..Default::default()
+        };
+        for keychain in &txout_index.keychains.keys().cloned().collect::<Vec<_>>() {
+            txout_index.replenish_lookahead(keychain);
+        }
+        let _ = txout_index.reveal_to_target_multi(&last_revealed);
+        txout_index
+    }
+
+    /// Like [`from_persisted`], but script pubkeys are not derived until they are needed.
+    ///
+    /// The last revealed indices and lookahead settings are restored as is, and the script pubkeys
+    /// of each keychain (up to the last revealed index plus the lookahead) are derived the first
+    /// time a mutating method touches the keychain (e.g. [`reveal_to_target`], [`set_lookahead`] or
+    /// [`next_unused_spk`]), or with [`derive_deferred_spks`]. [`scan`] derives every deferred
+    /// keychain, as any of them could match.
+    ///
+    /// Read-only methods that look at stored script pubkeys (e.g. [`spk_at_index`],
+    /// [`revealed_spks_of_keychain`], [`is_used`] and [`inner`]) derive every deferred keychain the
+    /// first time they are asked about a deferred keychain, as they can not store into `self`.
+    ///
+    /// This makes startup fast for wallets with many revealed script pubkeys, at the cost of a
+    /// slower first call per keychain (or first read-only query).
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `last_revealed` contains a keychain that is not in `keychains`.
+    ///
+    /// [`from_persisted`]: Self::from_persisted
+    /// [`reveal_to_target`]: Self::reveal_to_target
+    /// [`set_lookahead`]: Self::set_lookahead
+    /// [`next_unused_spk`]: Self::next_unused_spk
+    /// [`derive_deferred_spks`]: Self::derive_deferred_spks
+    /// [`scan`]: Self::scan
+    /// [`spk_at_index`]: Self::spk_at_index
+    /// [`revealed_spks_of_keychain`]: Self::revealed_spks_of_keychain
+    /// [`is_used`]: Self::is_used
+    /// [`inner`]: Self::inner
+    pub fn from_persisted_lazy(
+        keychains: BTreeMap<K, Descriptor<DescriptorPublicKey>>,
+        last_revealed: BTreeMap<K, u32>,
+        lookahead: BTreeMap<K, u32>,
+    ) -> Self {
+        assert!(
+            last_revealed.keys().all(|k| keychains.contains_key(k)),
+            "last revealed index of unknown keychain"
+        );
+        Self {
+            deferred: keychains.keys().cloned().collect(),
+            keychains,
+            last_revealed,
+            lookahead,
+            ..Default::default()
+        }
+    }
+
+    /// Returns whether the script pubkeys of `keychain` are not derived yet (see
+    /// [`from_persisted_lazy`]).
+    ///
+    /// [`from_persisted_lazy`]: Self::from_persisted_lazy
+    pub fn is_deferred(&self, keychain: &K) -> bool {
+        self.derived.get().is_none() && self.deferred.contains(keychain)
+    }
+
+    /// Derive the script pubkeys of `keychain` if they have been deferred by
+    /// [`from_persisted_lazy`]. Does nothing otherwise.
+    ///
+    /// [`from_persisted_lazy`]: Self::from_persisted_lazy
+    pub fn derive_deferred_spks(&mut self, keychain: &K) {
+        self.adopt_derived();
+        if self.deferred.contains(keychain) {
+            self.replenish_lookahead(keychain);
+        }
+    }
+
+    /// Convenience method to call [`derive_deferred_spks`] for all keychains.
+    ///
+    /// [`derive_deferred_spks`]: Self::derive_deferred_spks
+    pub fn derive_all_deferred_spks(&mut self) {
+        self.adopt_derived();
+        for keychain in &core::mem::take(&mut self.deferred) {
+            self.replenish_lookahead(keychain);
+        }
+    }
+
     /// Scans an object for relevant outpoints, which are stored and indexed internally.
     ///
     /// If the matched script pubkey is part of the lookahead, the last stored index is updated for
@@ -111,10 +287,32 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
         additions
     }
 
+    /// Like [`scan`], but also returns the outpoints that matched a script pubkey of the index,
+    /// alongside the keychain and derivation index of the script pubkey.
+    ///
+    /// Matches are returned in the order they were scanned.
+    ///
+    /// [`scan`]: Self::scan
+    pub fn scan_with_matches(
+        &mut self,
+        txouts: &impl ForEachTxOut,
+    ) -> (DerivationAdditions<K>, Vec<(OutPoint, K, u32)>) {
+        let mut additions = DerivationAdditions::<K>::default();
+        let mut matches = Vec::new();
+        txouts.for_each_txout(|(op, txout)| {
+            additions.append(self.scan_txout(op, txout));
+            if let Some((keychain, index)) = self.derivation_of_outpoint(&op) {
+                matches.push((op, keychain, index));
+            }
+        });
+        (additions, matches)
+    }
+
     /// Scan a single outpoint for a matching script pubkey.
     ///
     /// If it matches the index will store and index it.
     pub fn scan_txout(&mut self, op: OutPoint, txout: &TxOut) -> DerivationAdditions<K> {
+        self.derive_all_deferred_spks();
         match self.inner.scan_txout(op, txout).cloned() {
             Some((keychain, index)) => self.reveal_to_target(&keychain, index).1,
             None => DerivationAdditions::default(),
@@ -122,8 +320,50 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
     }
 
     /// Return a reference to the internal [`SpkTxOutIndex`].
+    ///
+    /// This derives the script pubkeys of every deferred keychain (see [`from_persisted_lazy`]).
+    ///
+    /// [`from_persisted_lazy`]: Self::from_persisted_lazy
     pub fn inner(&self) -> &SpkTxOutIndex<(K, u32)> {
-        &self.inner
+        if self.deferred.is_empty() {
+            return &self.inner;
+        }
+        self.derived.get_or_init(|| {
+            let mut inner = self.inner.clone();
+            for keychain in &self.deferred {
+                let descriptor = self.keychains.get(keychain).expect("keychain must exist");
+                let next_reveal_index = self.last_revealed.get(keychain).map_or(0, |v| *v + 1);
+                let lookahead = self.lookahead.get(keychain).map_or(0, |v| *v);
+                for (index, spk) in range_descriptor_spks(
+                    self.secp.clone(),
+                    Cow::Borrowed(descriptor),
+                    0..next_reveal_index.saturating_add(lookahead),
+                ) {
+                    let _inserted = inner.insert_spk((keychain.clone(), index), spk);
+                    debug_assert!(_inserted, "a deferred keychain has nothing stored");
+                }
+            }
+            inner
+        })
+    }
+
+    // Like `inner`, but only derives if `keychain` is deferred, as the stored script pubkeys of
+    // other keychains are complete.
+    fn inner_of(&self, keychain: &K) -> &SpkTxOutIndex<(K, u32)> {
+        if self.deferred.contains(keychain) {
+            self.inner()
+        } else {
+            &self.inner
+        }
+    }
+
+    // Mutations must be made on top of what a read query derived (see `inner`). Call this before
+    // changing `inner` or `deferred`.
+    fn adopt_derived(&mut self) {
+        if let Some(inner) = self.derived.take() {
+            self.inner = inner;
+            self.deferred.clear();
+        }
     }
 
     /// Return a reference to the internal map of keychain to descriptors.
@@ -131,6 +371,48 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
         &self.keychains
     }
 
+    /// Iterate over the keychains which have revealed at least one script pubkey.
+    ///
+    /// Unlike [`keychains`], this skips keychains which were added but never revealed from.
+    ///
+    /// [`keychains`]: Self::keychains
+    pub fn active_keychains(&self) -> impl Iterator<Item = &K> + '_ {
+        self.keychains
+            .keys()
+            .filter(move |keychain| self.last_revealed.contains_key(keychain))
+    }
+
+    /// Whether `keychain` has been added to the index.
+    pub fn has_keychain(&self, keychain: &K) -> bool {
+        self.keychains.contains_key(keychain)
+    }
+
+    /// Return the descriptor of `keychain`, if it exists.
+    pub fn descriptor_of(&self, keychain: &K) -> Option<&Descriptor<DescriptorPublicKey>> {
+        self.keychains.get(keychain)
+    }
+
+    /// Returns the script pubkey of `keychain` at derivation `index`, if it is stored in the index.
+    ///
+    /// Note that this also returns script pubkeys that are only stored as part of the lookahead.
+    pub fn spk_at_index(&self, keychain: &K, index: u32) -> Option<&Script> {
+        self.inner_of(keychain)
+            .spk_at_index(&(keychain.clone(), index))
+    }
+
+    /// Returns the keychain and derivation index of `spk`, if it is stored in the index.
+    ///
+    /// Note that this also finds script pubkeys that are only stored as part of the lookahead.
+    pub fn index_of_spk(&self, spk: &Script) -> Option<(K, u32)> {
+        self.inner().index_of_spk(spk).cloned()
+    }
+
+    /// Returns the keychain and derivation index of the txout at `op`, if the txout has been
+    /// scanned by the index.
+    pub fn derivation_of_outpoint(&self, op: &OutPoint) -> Option<(K, u32)> {
+        self.inner.txout(*op).map(|(index, _)| index.clone())
+    }
+
     /// Add a keychain to the tracker's `txout_index` with a descriptor to derive addresses for it.
     ///
     /// Adding a keychain means you will be able to derive new script pubkeys under that keychain
@@ -140,11 +422,148 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
     ///
     /// This will panic if a different `descriptor` is introduced to the same `keychain`.
     pub fn add_keychain(&mut self, keychain: K, descriptor: Descriptor<DescriptorPublicKey>) {
-        let old_descriptor = &*self.keychains.entry(keychain).or_insert(descriptor.clone());
-        assert_eq!(
-            &descriptor, old_descriptor,
-            "keychain already contains a different descriptor"
-        );
+        self.try_add_keychain(keychain, descriptor)
+            .expect("keychain already contains a different descriptor");
+    }
+
+    /// Like [`add_keychain`], but returns an error containing the existing descriptor instead of
+    /// panicking if a different `descriptor` is introduced to the same `keychain`.
+    ///
+    /// [`add_keychain`]: Self::add_keychain
+    #[allow(clippy::result_large_err)]
+    pub fn try_add_keychain(
+        &mut self,
+        keychain: K,
+        descriptor: Descriptor<DescriptorPublicKey>,
+    ) -> Result<(), KeychainConflict> {
+        self.check_conflict(&keychain, &descriptor)?;
+        self.keychains.entry(keychain).or_insert(descriptor);
+        Ok(())
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn check_conflict(
+        &self,
+        keychain: &K,
+        descriptor: &Descriptor<DescriptorPublicKey>,
+    ) -> Result<(), KeychainConflict> {
+        match self.keychains.get(keychain) {
+            Some(existing) if existing != descriptor => Err(KeychainConflict {
+                existing: existing.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Add two keychains from a single multipath descriptor string (i.e. one that contains
+    /// `<0;1>`).
+    ///
+    /// The first path of the multipath descriptor is added under `external` and the second path
+    /// under `internal` (see [`add_keychain`]). The descriptor may contain private keys, but only
+    /// the public descriptors are stored.
+    ///
+    /// Returns an error (without adding either keychain) if `external` and `internal` are the same
+    /// keychain, if the descriptor does not contain exactly two paths, has an invalid checksum, fails
+    /// to parse, or if either keychain already has a different descriptor (see
+    /// [`try_add_keychain`]).
+    ///
+    /// [`add_keychain`]: Self::add_keychain
+    /// [`try_add_keychain`]: Self::try_add_keychain
+    pub fn add_multipath_keychain(
+        &mut self,
+        external: K,
+        internal: K,
+        descriptor_str: &str,
+    ) -> Result<(), MultipathError> {
+        if external == internal {
+            return Err(MultipathError::SameKeychain);
+        }
+
+        let secp = Secp256k1::signing_only();
+        let [external_str, internal_str] = split_multipath(descriptor_str)?;
+        let (external_descriptor, _) =
+            Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, &external_str)
+                .map_err(MultipathError::Miniscript)?;
+        let (internal_descriptor, _) =
+            Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, &internal_str)
+                .map_err(MultipathError::Miniscript)?;
+
+        // check both keychains before adding either, so a conflict does not leave one of them added
+        self.check_conflict(&external, &external_descriptor)
+            .map_err(|err| MultipathError::Conflict(Box::new(err)))?;
+        self.check_conflict(&internal, &internal_descriptor)
+            .map_err(|err| MultipathError::Conflict(Box::new(err)))?;
+
+        self.add_keychain(external, external_descriptor);
+        self.add_keychain(internal, internal_descriptor);
+        Ok(())
+    }
+
+    /// Replace the descriptor of an existing `keychain` with `new_descriptor`.
+    ///
+    /// All script pubkeys previously derived for `keychain` (and the txouts indexed under them) are
+    /// removed. Script pubkeys of `new_descriptor` are then revealed up to the old last revealed
+    /// index (and the lookahead is replenished). The returned [`DerivationAdditions`] reports the
+    /// new last revealed index.
+    ///
+    /// **Warning:** txouts of the new descriptor are not known to the index until they are
+    /// scanned, so the caller must rescan the chain afterward.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `keychain` does not exist.
+    pub fn replace_descriptor(
+        &mut self,
+        keychain: K,
+        new_descriptor: Descriptor<DescriptorPublicKey>,
+    ) -> DerivationAdditions<K> {
+        let descriptor = self
+            .keychains
+            .get_mut(&keychain)
+            .expect("keychain must exist");
+        *descriptor = new_descriptor;
+
+        match self.reset_keychain(&keychain) {
+            Some(index) => self.reveal_to_target(&keychain, index).1,
+            None => DerivationAdditions::default(),
+        }
+    }
+
+    /// Remove everything derived and revealed for `keychain`, keeping its descriptor and lookahead
+    /// setting, and store the lookahead again. Returns the old last revealed index.
+    fn reset_keychain(&mut self, keychain: &K) -> Option<u32> {
+        self.adopt_derived();
+        self.deferred.remove(keychain);
+        self.inner
+            .remove_spks_in_range((keychain.clone(), u32::MIN)..=(keychain.clone(), u32::MAX));
+
+        // `reveal_to_target` expects the lookahead of the unrevealed range to already be stored
+        let last_revealed = self.last_revealed.remove(keychain);
+        self.replenish_lookahead(keychain);
+        last_revealed
+    }
+
+    /// Remove `keychain` and its descriptor from the index.
+    ///
+    /// All script pubkeys derived for `keychain` (and the txouts indexed under them), its last
+    /// revealed index and its lookahead setting are removed. The returned [`KeychainDelta`] records
+    /// the removal so that replaying persisted changes does not bring the keychain back. Nothing
+    /// happens (and the returned delta is empty) if `keychain` does not exist.
+    pub fn remove_keychain(&mut self, keychain: &K) -> KeychainDelta<K> {
+        if self.keychains.remove(keychain).is_none() {
+            return KeychainDelta::default();
+        }
+        self.last_revealed.remove(keychain);
+        self.lookahead.remove(keychain);
+        self.adopt_derived();
+        self.deferred.remove(keychain);
+        self.inner
+            .remove_spks_in_range((keychain.clone(), u32::MIN)..=(keychain.clone(), u32::MAX));
+
+        KeychainDelta {
+            revealed: Default::default(),
+            removed: [keychain.clone()].into(),
+        }
     }
 
     /// Return the lookahead setting for each keychain.
@@ -178,10 +597,48 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
     /// [`scan`]: Self::scan
     /// [`scan_txout`]: Self::scan_txout
     pub fn set_lookahead(&mut self, keychain: &K, lookahead: u32) {
+        self.derive_deferred_spks(keychain);
         self.lookahead.insert(keychain.clone(), lookahead);
         self.replenish_lookahead(keychain);
     }
 
+    /// Like [`set_lookahead`], but returns an error instead of panicking if `keychain` does not
+    /// exist.
+    ///
+    /// [`set_lookahead`]: Self::set_lookahead
+    pub fn try_set_lookahead(
+        &mut self,
+        keychain: &K,
+        lookahead: u32,
+    ) -> Result<(), NoSuchKeychain<K>> {
+        self.check_keychain(keychain)?;
+        self.set_lookahead(keychain, lookahead);
+        Ok(())
+    }
+
+    /// Set the lookahead count for `keychain` to `new_lookahead` and remove stored script pubkeys
+    /// beyond it to reclaim memory.
+    ///
+    /// Stored script pubkeys with a derivation index greater than the last revealed index plus
+    /// `new_lookahead` are removed, unless a txout has been found with it (or with a script pubkey
+    /// of a higher derivation index).
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `keychain` does not exist.
+    pub fn shrink_lookahead(&mut self, keychain: &K, new_lookahead: u32) {
+        self.set_lookahead(keychain, new_lookahead);
+
+        let next_reveal_index = self.last_revealed.get(keychain).map_or(0, |v| *v + 1);
+        let next_used_index = self.last_used_index(keychain).map_or(0, |v| v + 1);
+        let first_removed = next_reveal_index
+            .saturating_add(new_lookahead)
+            .max(next_used_index);
+        self.adopt_derived();
+        self.inner
+            .remove_spks_in_range((keychain.clone(), first_removed)..=(keychain.clone(), u32::MAX));
+    }
+
     /// Convenience method to call [`lookahead_to_target`] for multiple keychains.
     ///
     /// [`lookahead_to_target`]: Self::lookahead_to_target
@@ -195,6 +652,7 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
     ///
     /// This does not change the `lookahead` setting.
     pub fn lookahead_to_target(&mut self, keychain: &K, target_index: u32) {
+        self.derive_deferred_spks(keychain);
         let next_index = self.next_store_index(keychain);
         if let Some(temp_lookahead) = target_index.checked_sub(next_index).filter(|&v| v > 0) {
             let old_lookahead = self.lookahead.insert(keychain.clone(), temp_lookahead);
@@ -208,15 +666,23 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
         }
     }
 
+    /// Store script pubkeys of `keychain` up to the last revealed index plus the lookahead.
+    ///
+    /// Derivation starts from the first index which is not stored yet, so script pubkeys which are
+    /// already stored (e.g. when the lookahead is enlarged) are not derived again.
     fn replenish_lookahead(&mut self, keychain: &K) {
+        self.adopt_derived();
+        // a deferred keychain has nothing stored, so this derives everything up to the lookahead
+        self.deferred.remove(keychain);
         let descriptor = self.keychains.get(keychain).expect("keychain must exist");
         let next_store_index = self.next_store_index(keychain);
         let next_reveal_index = self.last_revealed.get(keychain).map_or(0, |v| *v + 1);
         let lookahead = self.lookahead.get(keychain).map_or(0, |v| *v);
 
         for (new_index, new_spk) in range_descriptor_spks(
+            self.secp.clone(),
             Cow::Borrowed(descriptor),
-            next_store_index..next_reveal_index + lookahead,
+            next_store_index..next_reveal_index.saturating_add(lookahead),
         ) {
             let _inserted = self
                 .inner
@@ -225,12 +691,102 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
         }
     }
 
+    /// Like [`set_lookahead`], but the newly stored script pubkeys are derived in parallel.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `keychain` does not exist.
+    ///
+    /// [`set_lookahead`]: Self::set_lookahead
+    #[cfg(feature = "rayon")]
+    pub fn set_lookahead_par(&mut self, keychain: &K, lookahead: u32) {
+        self.derive_deferred_spks(keychain);
+        self.lookahead.insert(keychain.clone(), lookahead);
+        self.replenish_lookahead_par(keychain);
+    }
+
+    /// Like [`reveal_to_target`], but the script pubkeys are derived in parallel. Only the
+    /// [`DerivationAdditions`] are returned, the newly revealed script pubkeys can be found with
+    /// [`revealed_spks_of_keychain`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keychain` does not exist.
+    ///
+    /// [`reveal_to_target`]: Self::reveal_to_target
+    /// [`revealed_spks_of_keychain`]: Self::revealed_spks_of_keychain
+    #[cfg(feature = "rayon")]
+    pub fn reveal_to_target_par(
+        &mut self,
+        keychain: &K,
+        target_index: u32,
+    ) -> DerivationAdditions<K> {
+        self.derive_deferred_spks(keychain);
+        let descriptor = self.keychains.get(keychain).expect("keychain must exist");
+        let target_index = if descriptor.has_wildcard() {
+            target_index
+        } else {
+            0
+        };
+        let next_reveal_index = self.last_revealed.get(keychain).map_or(0, |v| *v + 1);
+        let lookahead = self.lookahead.get(keychain).map_or(0, |v| *v);
+
+        // if target is already surpassed, we have nothing to reveal
+        if target_index < next_reveal_index {
+            return DerivationAdditions::default();
+        }
+
+        self.store_spks_par(
+            keychain,
+            target_index.saturating_add(lookahead).saturating_add(1),
+        );
+
+        // derivation may stop short of the target (see `range_descriptor_spks`)
+        match self.next_store_index(keychain).checked_sub(1) {
+            Some(last_stored) if last_stored >= next_reveal_index => {
+                let index = last_stored.min(target_index);
+                self.last_revealed.insert(keychain.clone(), index);
+                DerivationAdditions([(keychain.clone(), index)].into())
+            }
+            _ => DerivationAdditions::default(),
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    fn replenish_lookahead_par(&mut self, keychain: &K) {
+        let next_reveal_index = self.last_revealed.get(keychain).map_or(0, |v| *v + 1);
+        let lookahead = self.lookahead.get(keychain).map_or(0, |v| *v);
+        self.store_spks_par(keychain, next_reveal_index.saturating_add(lookahead));
+    }
+
+    /// Derive (in parallel) and store script pubkeys of `keychain` from the next store index up to
+    /// (but excluding) `end`.
+    #[cfg(feature = "rayon")]
+    fn store_spks_par(&mut self, keychain: &K, end: u32) {
+        self.adopt_derived();
+        let next_store_index = self.next_store_index(keychain);
+        let descriptor = self.keychains.get(keychain).expect("keychain must exist");
+
+        for (new_index, new_spk) in
+            par_range_descriptor_spks(&self.secp, descriptor, next_store_index..end)
+        {
+            let _inserted = self
+                .inner
+                .insert_spk((keychain.clone(), new_index), new_spk);
+            debug_assert!(_inserted, "must not have existing spk");
+        }
+    }
+
+    fn check_keychain(&self, keychain: &K) -> Result<(), NoSuchKeychain<K>> {
+        if self.keychains.contains_key(keychain) {
+            Ok(())
+        } else {
+            Err(NoSuchKeychain(keychain.clone()))
+        }
+    }
+
     fn next_store_index(&self, keychain: &K) -> u32 {
-        self.inner()
-            .all_spks()
-            .range((keychain.clone(), u32::MIN)..(keychain.clone(), u32::MAX))
-            .last()
-            .map_or(0, |((_, v), _)| *v + 1)
+        next_store_index(&self.inner, keychain)
     }
 
     /// Generates script pubkey iterators for every `keychain`. The iterators iterate over all
@@ -243,7 +799,7 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
             .map(|(keychain, descriptor)| {
                 (
                     keychain.clone(),
-                    range_descriptor_spks(Cow::Owned(descriptor.clone()), 0..),
+                    range_descriptor_spks(self.secp.clone(), Cow::Owned(descriptor.clone()), 0..),
                 )
             })
             .collect()
@@ -261,7 +817,19 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
             .get(keychain)
             .expect("keychain must exist")
             .clone();
-        range_descriptor_spks(Cow::Owned(descriptor), 0..)
+        range_descriptor_spks(self.secp.clone(), Cow::Owned(descriptor), 0..)
+    }
+
+    /// Like [`spks_of_keychain`], but returns an error instead of panicking if `keychain` does not
+    /// exist.
+    ///
+    /// [`spks_of_keychain`]: Self::spks_of_keychain
+    pub fn try_spks_of_keychain(
+        &self,
+        keychain: &K,
+    ) -> Result<impl Iterator<Item = (u32, Script)> + Clone, NoSuchKeychain<K>> {
+        self.check_keychain(keychain)?;
+        Ok(self.spks_of_keychain(keychain))
     }
 
     /// Convenience method to get [`revealed_spks_of_keychain`] of all keychains.
@@ -276,18 +844,50 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
             .collect()
     }
 
+    /// Iterates over the script pubkeys revealed by this index under all keychains.
+    ///
+    /// Unlike [`revealed_spks_of_all_keychains`], this is a single iterator ordered by keychain and
+    /// then by derivation index.
+    ///
+    /// [`revealed_spks_of_all_keychains`]: Self::revealed_spks_of_all_keychains
+    pub fn revealed_spks_flat(&self) -> impl DoubleEndedIterator<Item = (K, u32, &Script)> + '_ {
+        self.last_revealed.keys().flat_map(move |keychain| {
+            self.revealed_spks_of_keychain(keychain)
+                .map(move |(index, spk)| (keychain.clone(), index, spk))
+        })
+    }
+
     /// Iterates over the script pubkeys revealed by this index under `keychain`.
     pub fn revealed_spks_of_keychain(
         &self,
         keychain: &K,
     ) -> impl DoubleEndedIterator<Item = (u32, &Script)> + Clone {
         let next_index = self.last_revealed.get(keychain).map_or(0, |v| *v + 1);
-        self.inner
+        self.inner_of(keychain)
             .all_spks()
             .range((keychain.clone(), u32::MIN)..(keychain.clone(), next_index))
             .map(|((_, derivation_index), spk)| (*derivation_index, spk))
     }
 
+    /// Iterates over the script pubkeys of `keychain` that are stored in the index but not revealed
+    /// yet (i.e. the lookahead).
+    ///
+    /// This is the part of the scan window of [`scan`] and [`scan_txout`] that is past the last
+    /// revealed index. Usually this covers the `lookahead` indices after the last revealed index.
+    ///
+    /// [`scan`]: Self::scan
+    /// [`scan_txout`]: Self::scan_txout
+    pub fn lookahead_spks_of_keychain(
+        &self,
+        keychain: &K,
+    ) -> impl DoubleEndedIterator<Item = (u32, &Script)> + Clone {
+        let next_index = self.last_revealed.get(keychain).map_or(0, |v| *v + 1);
+        self.inner_of(keychain)
+            .all_spks()
+            .range((keychain.clone(), next_index)..=(keychain.clone(), u32::MAX))
+            .map(|((_, derivation_index), spk)| (*derivation_index, spk))
+    }
+
     /// Get the next derivation index for `keychain`. This is the index after the last revealed
     /// derivation index.
     ///
@@ -324,6 +924,40 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
         }
     }
 
+    /// Like [`next_index`], but returns an error instead of panicking if `keychain` does not exist.
+    ///
+    /// [`next_index`]: Self::next_index
+    pub fn try_next_index(&self, keychain: &K) -> Result<(u32, bool), NoSuchKeychain<K>> {
+        self.check_keychain(keychain)?;
+        Ok(self.next_index(keychain))
+    }
+
+    /// Derive up to `count` script pubkeys of `keychain` starting from the [`next_index`], without
+    /// revealing them.
+    ///
+    /// Fewer than `count` script pubkeys are returned if the keychain runs out of derivation
+    /// indices. A descriptor without a wildcard only has index 0, so nothing is returned if it has
+    /// already been revealed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `keychain` does not exist.
+    ///
+    /// [`next_index`]: Self::next_index
+    pub fn peek_spks(&self, keychain: &K, count: u32) -> Vec<(u32, Script)> {
+        let (next_index, is_new) = self.next_index(keychain);
+        if !is_new {
+            return Vec::new();
+        }
+        let descriptor = &self.keychains[keychain];
+        range_descriptor_spks(
+            self.secp.clone(),
+            Cow::Borrowed(descriptor),
+            next_index..next_index.saturating_add(count),
+        )
+        .collect()
+    }
+
     /// Get the last derivation index that is revealed for each keychain.
     ///
     /// Keychains with no revealed indices will not be included in the returned [`BTreeMap`].
@@ -336,6 +970,30 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
         self.last_revealed.get(keychain).cloned()
     }
 
+    /// Get the number of revealed script pubkeys of `keychain` (i.e. the last revealed index + 1).
+    pub fn revealed_count(&self, keychain: &K) -> u32 {
+        self.last_revealed.get(keychain).map_or(0, |v| *v + 1)
+    }
+
+    /// Get the number of revealed script pubkeys of each keychain.
+    ///
+    /// Unlike [`last_revealed_indices`], keychains with no revealed indices are included (with a
+    /// count of 0).
+    ///
+    /// [`last_revealed_indices`]: Self::last_revealed_indices
+    pub fn all_revealed_counts(&self) -> BTreeMap<K, u32> {
+        self.keychains
+            .keys()
+            .map(|keychain| (keychain.clone(), self.revealed_count(keychain)))
+            .collect()
+    }
+
+    /// Get the number of script pubkeys of `keychain` stored in the index. This includes both
+    /// revealed and lookahead script pubkeys.
+    pub fn stored_spk_count(&self, keychain: &K) -> u32 {
+        next_store_index(self.inner_of(keychain), keychain)
+    }
+
     /// Convenience method to call [`Self::reveal_to_target`] on multiple keychains.
     pub fn reveal_to_target_multi(
         &mut self,
@@ -377,6 +1035,7 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
         keychain: &K,
         target_index: u32,
     ) -> (impl Iterator<Item = (u32, Script)>, DerivationAdditions<K>) {
+        self.derive_deferred_spks(keychain);
         let descriptor = self.keychains.get(keychain).expect("keychain must exist");
         let has_wildcard = descriptor.has_wildcard();
 
@@ -391,22 +1050,22 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
         // if target is already surpassed, we have nothing to reveal
         if next_reveal_index <= target_index
             // if target is already stored (due to lookahead), this can be our new revealed index
-            && target_index < next_reveal_index + lookahead
+            && target_index < next_store_index
         {
             revealed_index = Some(target_index);
         }
 
-        // we range over indexes that are not stored
-        let range = next_reveal_index + lookahead..=target_index + lookahead;
+        // we only derive indexes that are not stored yet, so sequential reveals only derive the
+        // script pubkeys that are new to the lookahead
+        let range = next_store_index..=target_index.saturating_add(lookahead);
 
-        for (new_index, new_spk) in range_descriptor_spks(Cow::Borrowed(descriptor), range) {
-            // no need to store if already stored
-            if new_index >= next_store_index {
-                let _inserted = self
-                    .inner
-                    .insert_spk((keychain.clone(), new_index), new_spk);
-                debug_assert!(_inserted, "must not have existing spk",);
-            }
+        for (new_index, new_spk) in
+            range_descriptor_spks(self.secp.clone(), Cow::Borrowed(descriptor), range)
+        {
+            let _inserted = self
+                .inner
+                .insert_spk((keychain.clone(), new_index), new_spk);
+            debug_assert!(_inserted, "must not have existing spk",);
 
             // everything after `target_index` is stored for lookahead only
             if new_index <= target_index {
@@ -420,6 +1079,7 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
                 debug_assert!(_old_index < Some(index));
                 (
                     range_descriptor_spks(
+                        self.secp.clone(),
                         Cow::Owned(descriptor.clone()),
                         next_reveal_index..index + 1,
                     ),
@@ -428,6 +1088,7 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
             }
             None => (
                 range_descriptor_spks(
+                    self.secp.clone(),
                     Cow::Owned(descriptor.clone()),
                     next_reveal_index..next_reveal_index,
                 ),
@@ -436,6 +1097,75 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
         }
     }
 
+    /// Like [`reveal_to_target`], but the newly revealed script pubkeys are collected from the
+    /// index instead of being derived again by the returned iterator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keychain` does not exist.
+    ///
+    /// [`reveal_to_target`]: Self::reveal_to_target
+    pub fn reveal_to_target_with_scripts(
+        &mut self,
+        keychain: &K,
+        target_index: u32,
+    ) -> (Vec<(u32, Script)>, DerivationAdditions<K>) {
+        let next_reveal_index = self.last_revealed.get(keychain).map_or(0, |v| *v + 1);
+        let (_, additions) = self.reveal_to_target(keychain, target_index);
+
+        let spks = match additions.as_inner().get(keychain) {
+            Some(&last_revealed) => self
+                .inner
+                .all_spks()
+                .range((keychain.clone(), next_reveal_index)..=(keychain.clone(), last_revealed))
+                .map(|((_, index), spk)| (*index, spk.clone()))
+                .collect(),
+            None => Vec::new(),
+        };
+        (spks, additions)
+    }
+
+    /// Like [`reveal_to_target`], but returns an error instead of panicking if `keychain` does not
+    /// exist.
+    ///
+    /// [`reveal_to_target`]: Self::reveal_to_target
+    #[allow(clippy::type_complexity)]
+    pub fn try_reveal_to_target(
+        &mut self,
+        keychain: &K,
+        target_index: u32,
+    ) -> Result<(impl Iterator<Item = (u32, Script)>, DerivationAdditions<K>), NoSuchKeychain<K>>
+    {
+        self.check_keychain(keychain)?;
+        Ok(self.reveal_to_target(keychain, target_index))
+    }
+
+    /// Reveals script pubkeys of `keychain` up to `buffer` indices past the [`last_used_index`].
+    ///
+    /// If no script pubkey of `keychain` has been used, the first `buffer` script pubkeys are
+    /// revealed. This is useful to make sure enough script pubkeys are revealed after a sync.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keychain` does not exist.
+    ///
+    /// [`last_used_index`]: Self::last_used_index
+    pub fn reveal_to_last_used_plus(
+        &mut self,
+        keychain: &K,
+        buffer: u32,
+    ) -> DerivationAdditions<K> {
+        self.derive_deferred_spks(keychain);
+        let target_index = match self.last_used_index(keychain) {
+            Some(last_used) => last_used.saturating_add(buffer),
+            None => match buffer.checked_sub(1) {
+                Some(target_index) => target_index,
+                None => return DerivationAdditions::default(),
+            },
+        };
+        self.reveal_to_target(keychain, target_index).1
+    }
+
     /// Attempts to reveal the next script pubkey for `keychain`.
     ///
     /// Returns the derivation index of the revealed script pubkey, the revealed script pubkey and a
@@ -454,12 +1184,24 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
         let (next_index, _) = self.next_index(keychain);
         let additions = self.reveal_to_target(keychain, next_index).1;
         let script = self
-            .inner
-            .spk_at_index(&(keychain.clone(), next_index))
+            .spk_at_index(keychain, next_index)
             .expect("script must already be stored");
         ((next_index, script), additions)
     }
 
+    /// Like [`reveal_next_spk`], but returns an error instead of panicking if `keychain` does not
+    /// exist.
+    ///
+    /// [`reveal_next_spk`]: Self::reveal_next_spk
+    #[allow(clippy::type_complexity)]
+    pub fn try_reveal_next_spk(
+        &mut self,
+        keychain: &K,
+    ) -> Result<((u32, &Script), DerivationAdditions<K>), NoSuchKeychain<K>> {
+        self.check_keychain(keychain)?;
+        Ok(self.reveal_next_spk(keychain))
+    }
+
     /// Attempts to reveal the next script pubkey of the provided `keychain` and mark it as used.
     ///
     /// This is a convenience method that is equivalent to calling [`reveal_next_spk`] and
@@ -478,8 +1220,7 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
         let ((next_index, _), additions) = self.reveal_next_spk(keychain);
         self.mark_used(keychain, next_index);
         let script = self
-            .inner
-            .spk_at_index(&(keychain.clone(), next_index))
+            .spk_at_index(keychain, next_index)
             .expect("spk already returned above");
         ((next_index, script), additions)
     }
@@ -497,6 +1238,7 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
     ///
     /// Panics if `keychain` has never been added to the index
     pub fn next_unused_spk(&mut self, keychain: &K) -> ((u32, &Script), DerivationAdditions<K>) {
+        self.derive_deferred_spks(keychain);
         let need_new = self.unused_spks_of_keychain(keychain).next().is_none();
         // this rather strange branch is needed because of some lifetime issues
         if need_new {
@@ -511,6 +1253,77 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
         }
     }
 
+    /// Convenience method to call [`next_unused_spk`] on all keychains.
+    ///
+    /// The returned [`DerivationAdditions`] aggregates the changes of every keychain.
+    ///
+    /// [`next_unused_spk`]: Self::next_unused_spk
+    pub fn next_unused_spks_of_all_keychains(
+        &mut self,
+    ) -> (BTreeMap<K, (u32, Script)>, DerivationAdditions<K>) {
+        let mut additions = DerivationAdditions::default();
+        let mut spks = BTreeMap::new();
+
+        for keychain in &self.keychains.keys().cloned().collect::<Vec<_>>() {
+            let ((index, spk), new_additions) = self.next_unused_spk(keychain);
+            spks.insert(keychain.clone(), (index, spk.clone()));
+            additions.append(new_additions);
+        }
+
+        (spks, additions)
+    }
+
+    /// Like [`reveal_next_spk`], but returns the revealed script pubkey as an [`Address`] of
+    /// `network`.
+    ///
+    /// Returns an error (without revealing anything) if the `keychain`'s descriptor does not
+    /// produce addressable script pubkeys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `keychain` does not exist.
+    ///
+    /// [`reveal_next_spk`]: Self::reveal_next_spk
+    pub fn reveal_next_address(
+        &mut self,
+        keychain: &K,
+        network: Network,
+    ) -> Result<(u32, Address, DerivationAdditions<K>), address::Error> {
+        self.check_addressable(keychain, network)?;
+        let ((index, spk), additions) = self.reveal_next_spk(keychain);
+        let address = Address::from_script(spk, network)?;
+        Ok((index, address, additions))
+    }
+
+    /// Like [`next_unused_spk`], but returns the script pubkey as an [`Address`] of `network`.
+    ///
+    /// Returns an error (without revealing anything) if the `keychain`'s descriptor does not
+    /// produce addressable script pubkeys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `keychain` does not exist.
+    ///
+    /// [`next_unused_spk`]: Self::next_unused_spk
+    pub fn next_unused_address(
+        &mut self,
+        keychain: &K,
+        network: Network,
+    ) -> Result<(u32, Address, DerivationAdditions<K>), address::Error> {
+        self.check_addressable(keychain, network)?;
+        let ((index, spk), additions) = self.next_unused_spk(keychain);
+        let address = Address::from_script(spk, network)?;
+        Ok((index, address, additions))
+    }
+
+    // Whether the script pubkeys of a descriptor are addressable only depends on the type of the
+    // descriptor, so checking the first one is enough.
+    fn check_addressable(&self, keychain: &K, network: Network) -> Result<(), address::Error> {
+        let descriptor = self.keychains.get(keychain).expect("keychain must exist");
+        Address::from_script(&descriptor.at_derivation_index(0).script_pubkey(), network)?;
+        Ok(())
+    }
+
     /// Get the next unused script pubkey of the provided `keychain` and mark it as used.
     ///
     /// This is a convenience method that is equivalent to calling [`next_unused_spk`] and
@@ -529,12 +1342,24 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
         let ((index, _), additions) = self.next_unused_spk(keychain);
         self.mark_used(keychain, index);
         let script = self
-            .inner
-            .spk_at_index(&(keychain.clone(), index))
+            .spk_at_index(keychain, index)
             .expect("spk already returned above");
         ((index, script), additions)
     }
 
+    /// Returns whether the script pubkey at `index` of `keychain` has been used or not.
+    ///
+    /// Here "unused" means that after the script pubkey was stored in the index, the index has
+    /// never scanned a transaction output with it and it has not been marked used (see
+    /// [`mark_used`]). A script pubkey that is not stored in the index is never used.
+    ///
+    /// [`mark_used`]: Self::mark_used
+    pub fn is_used(&self, keychain: &K, index: u32) -> bool {
+        let inner = self.inner_of(keychain);
+        let index = (keychain.clone(), index);
+        inner.spk_at_index(&index).is_some() && inner.is_used(&index)
+    }
+
     /// Marks the script pubkey at `index` as used even though it hasn't seen an output with it.
     /// This only has an effect when the `index` had been added to `self` already and was unused.
     ///
@@ -546,6 +1371,7 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
     ///
     /// [`unmark_used`]: Self::unmark_used
     pub fn mark_used(&mut self, keychain: &K, index: u32) -> bool {
+        self.derive_deferred_spks(keychain);
         self.inner.mark_used(&(keychain.clone(), index))
     }
 
@@ -557,6 +1383,7 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
     ///
     /// [`mark_used`]: Self::mark_used
     pub fn unmark_used(&mut self, keychain: &K, index: u32) -> bool {
+        self.derive_deferred_spks(keychain);
         self.inner.unmark_used(&(keychain.clone(), index))
     }
 
@@ -567,22 +1394,54 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
     ) -> impl DoubleEndedIterator<Item = (u32, &Script)> {
         let next_index = self.last_revealed.get(keychain).map_or(0, |&v| v + 1);
         let range = (keychain.clone(), u32::MIN)..(keychain.clone(), next_index);
-        self.inner
+        self.inner_of(keychain)
             .unused_spks(range)
             .map(|((_, i), script)| (*i, script))
     }
 
+    /// Iterates over all revealed script pubkeys of `keychain` that are used.
+    ///
+    /// These are the script pubkeys revealed by [`revealed_spks_of_keychain`] but not in
+    /// [`unused_spks_of_keychain`] (i.e. a txout was found with it or it was marked used).
+    ///
+    /// [`revealed_spks_of_keychain`]: Self::revealed_spks_of_keychain
+    /// [`unused_spks_of_keychain`]: Self::unused_spks_of_keychain
+    pub fn used_spks_of_keychain(
+        &self,
+        keychain: &K,
+    ) -> impl DoubleEndedIterator<Item = (u32, &Script)> + '_ {
+        let keychain = keychain.clone();
+        self.revealed_spks_of_keychain(&keychain)
+            .filter(move |(index, _)| self.is_used(&keychain, *index))
+    }
+
     /// Iterates over all the [`OutPoint`] that have a `TxOut` with a script pubkey derived from
     /// `keychain`.
     pub fn txouts_of_keychain(
         &self,
         keychain: &K,
     ) -> impl DoubleEndedIterator<Item = (u32, OutPoint)> + '_ {
-        self.inner
+        self.inner_of(keychain)
             .outputs_in_range((keychain.clone(), u32::MIN)..(keychain.clone(), u32::MAX))
             .map(|((_, i), op)| (*i, op))
     }
 
+    /// Like [`txouts_of_keychain`], but only iterates over the [`OutPoint`]s of script pubkeys with
+    /// a derivation index within `range`.
+    ///
+    /// [`txouts_of_keychain`]: Self::txouts_of_keychain
+    pub fn txouts_of_keychain_in_range(
+        &self,
+        keychain: &K,
+        range: Range<u32>,
+    ) -> impl DoubleEndedIterator<Item = (u32, OutPoint)> + '_ {
+        // an inverted range is empty (instead of panicking in `BTreeMap::range`)
+        let end = range.end.max(range.start);
+        self.inner_of(keychain)
+            .outputs_in_range((keychain.clone(), range.start)..(keychain.clone(), end))
+            .map(|((_, i), op)| (*i, op))
+    }
+
     /// Returns the highest derivation index of the `keychain` where [`KeychainTxOutIndex`] has
     /// found a [`TxOut`] with it's script pubkey.
     pub fn last_used_index(&self, keychain: &K) -> Option<u32> {
@@ -601,21 +1460,284 @@ impl<K: Clone + Ord + Debug> KeychainTxOutIndex<K> {
             .collect()
     }
 
+    /// Returns the number of consecutive revealed script pubkeys of `keychain` that sit above the
+    /// [`last_used_index`] (i.e. have not been found in a [`TxOut`]).
+    ///
+    /// This can be compared against a gap limit to decide when to stop scanning during wallet
+    /// recovery.
+    ///
+    /// [`last_used_index`]: Self::last_used_index
+    pub fn trailing_unused_count(&self, keychain: &K) -> u32 {
+        let next_reveal_index = self.last_revealed.get(keychain).map_or(0, |v| *v + 1);
+        let next_used_index = self.last_used_index(keychain).map_or(0, |v| v + 1);
+        next_reveal_index.saturating_sub(next_used_index)
+    }
+
     /// Applies the derivation additions to the [`KeychainTxOutIndex`], extending the number of
     /// derived scripts per keychain, as specified in the `additions`.
-    pub fn apply_additions(&mut self, additions: DerivationAdditions<K>) {
-        let _ = self.reveal_to_target_multi(&additions.0);
+    ///
+    /// Returns the [`DerivationAdditions`] that actually changed the index. Entries that are not
+    /// higher than the already revealed index are ignored, so an empty return value for non-empty
+    /// `additions` means they were stale (e.g. changesets were replayed out of order).
+    pub fn apply_additions(&mut self, additions: DerivationAdditions<K>) -> DerivationAdditions<K> {
+        self.reveal_to_target_multi(&additions.0).1
     }
+
+    /// Applies a [`KeychainChangeSet`] to the [`KeychainTxOutIndex`].
+    ///
+    /// This removes the keychains in the changeset's `derivation_indices` (see
+    /// [`remove_keychain`]), applies its revealed indices (see [`apply_additions`]) and scans the
+    /// txouts of its `chain_graph` (see [`scan`]). Applying every persisted changeset in order
+    /// restores the index to the state it had when they were recorded.
+    ///
+    /// Changesets do not record descriptors, so a keychain that was added again after its removal
+    /// must be added again by the caller before applying. Such a keychain is both removed and
+    /// revealed in the (appended) changeset: it is then reset instead of removed, keeping the
+    /// descriptor the caller added, and revealed from scratch. Changesets recorded on either side of
+    /// the re-addition must be appended (see [`KeychainChangeSet::append`]) before applying them.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if a revealed keychain does not exist.
+    ///
+    /// [`remove_keychain`]: Self::remove_keychain
+    /// [`apply_additions`]: Self::apply_additions
+    /// [`scan`]: Self::scan
+    pub fn apply_changeset<P, T: AsTransaction>(&mut self, changeset: &KeychainChangeSet<K, P, T>) {
+        let delta = &changeset.derivation_indices;
+        for keychain in &delta.removed {
+            if delta.revealed.contains_key(keychain) && self.keychains.contains_key(keychain) {
+                let _ = self.reset_keychain(keychain);
+            } else {
+                let _ = self.remove_keychain(keychain);
+            }
+        }
+        let _ = self.reveal_to_target_multi(&delta.revealed);
+        let _ = self.scan(&changeset.chain_graph);
+    }
+}
+
+/// Represents a failure to add keychains with [`KeychainTxOutIndex::add_multipath_keychain`].
+#[derive(Debug)]
+pub enum MultipathError {
+    /// The `external` and `internal` keychains are the same, so both paths cannot be added.
+    SameKeychain,
+    /// The descriptor does not have exactly two derivation paths. Contains the number of paths
+    /// found.
+    InvalidPathCount(usize),
+    /// The checksum of the multipath descriptor does not match the descriptor.
+    InvalidChecksum,
+    /// A descriptor expanded from the multipath descriptor failed to parse.
+    Miniscript(crate::miniscript::Error),
+    /// One of the keychains already has a different descriptor.
+    Conflict(Box<KeychainConflict>),
+}
+
+impl core::fmt::Display for MultipathError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MultipathError::SameKeychain => {
+                write!(f, "external and internal keychains must be different")
+            }
+            MultipathError::InvalidPathCount(count) => write!(
+                f,
+                "multipath descriptor must have exactly 2 paths, found {}",
+                count
+            ),
+            MultipathError::InvalidChecksum => write!(f, "invalid multipath descriptor checksum"),
+            MultipathError::Miniscript(err) => write!(f, "invalid descriptor: {}", err),
+            MultipathError::Conflict(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MultipathError {}
+
+/// Error returned by the `try_*` methods of [`KeychainTxOutIndex`] when the keychain does not exist.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NoSuchKeychain<K>(pub K);
+
+impl<K: Debug> core::fmt::Display for NoSuchKeychain<K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "keychain {:?} does not exist", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Debug> std::error::Error for NoSuchKeychain<K> {}
+
+/// Error returned by [`KeychainTxOutIndex::try_add_keychain`] when the keychain already has a
+/// different descriptor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeychainConflict {
+    /// The descriptor that is already associated with the keychain.
+    pub existing: Descriptor<DescriptorPublicKey>,
+}
+
+impl core::fmt::Display for KeychainConflict {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "keychain already contains a different descriptor: {}",
+            self.existing
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for KeychainConflict {}
+
+/// Expands every `<a;b>` group of a multipath descriptor string into two descriptor strings, one
+/// with `a` and one with `b`. The checksum (if any) is verified and dropped as it is only valid for
+/// the multipath string.
+fn split_multipath(descriptor_str: &str) -> Result<[String; 2], MultipathError> {
+    let mut rest = match descriptor_str.split_once('#') {
+        Some((descriptor, checksum)) => {
+            if descriptor_checksum(descriptor).as_deref() != Some(checksum) {
+                return Err(MultipathError::InvalidChecksum);
+            }
+            descriptor
+        }
+        None => descriptor_str,
+    };
+    let mut paths = [String::new(), String::new()];
+    let mut has_multipath = false;
+
+    while let Some((before, after)) = rest.split_once('<') {
+        let (group, after) = match after.split_once('>') {
+            Some(split) => split,
+            // leave it to the descriptor parser to complain
+            None => break,
+        };
+        let parts = group.split(';').collect::<Vec<_>>();
+        if parts.len() != 2 {
+            return Err(MultipathError::InvalidPathCount(parts.len()));
+        }
+        for (path, part) in paths.iter_mut().zip(parts) {
+            path.push_str(before);
+            path.push_str(part);
+        }
+        rest = after;
+        has_multipath = true;
+    }
+
+    if !has_multipath {
+        return Err(MultipathError::InvalidPathCount(1));
+    }
+    for path in &mut paths {
+        path.push_str(rest);
+    }
+    Ok(paths)
+}
+
+/// Computes the checksum of a descriptor string (as in Bitcoin Core's `descriptor.cpp`). Returns
+/// `None` if the string contains a character that is not allowed in descriptors.
+///
+/// Miniscript only verifies checksums of descriptors it can parse, which excludes multipath
+/// descriptors.
+fn descriptor_checksum(descriptor_str: &str) -> Option<String> {
+    const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+    const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+    fn poly_mod(mut c: u64, val: u64) -> u64 {
+        let c0 = c >> 35;
+        c = ((c & 0x7ffffffff) << 5) ^ val;
+        for (bit, generator) in [
+            0xf5dee51989,
+            0xa9fdca3312,
+            0x1bab10e32d,
+            0x3706b1677a,
+            0x644d626ffd,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if c0 & (1 << bit) > 0 {
+                c ^= generator;
+            }
+        }
+        c
+    }
+
+    let mut c = 1;
+    let mut cls = 0;
+    let mut cls_count = 0;
+    for ch in descriptor_str.chars() {
+        let pos = INPUT_CHARSET.find(ch)? as u64;
+        c = poly_mod(c, pos & 31);
+        cls = cls * 3 + (pos >> 5);
+        cls_count += 1;
+        if cls_count == 3 {
+            c = poly_mod(c, cls);
+            cls = 0;
+            cls_count = 0;
+        }
+    }
+    if cls_count > 0 {
+        c = poly_mod(c, cls);
+    }
+    for _ in 0..8 {
+        c = poly_mod(c, 0);
+    }
+    c ^= 1;
+
+    Some(
+        (0..8)
+            .map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char)
+            .collect(),
+    )
+}
+
+/// Parallel version of [`range_descriptor_spks`].
+#[cfg(feature = "rayon")]
+fn par_range_descriptor_spks(
+    secp: &Secp256k1<VerifyOnly>,
+    descriptor: &Descriptor<DescriptorPublicKey>,
+    range: core::ops::Range<u32>,
+) -> Vec<(u32, Script)> {
+    use rayon::prelude::*;
+
+    // non-wildcard descriptors can only have one derivation index (0) and we can only derive
+    // non-hardened indices
+    let max_end = if descriptor.has_wildcard() {
+        BIP32_MAX_INDEX.saturating_add(1)
+    } else {
+        1
+    };
+    let range = range.start..range.end.min(max_end);
+
+    range
+        .into_par_iter()
+        .map(|index| {
+            descriptor
+                .derived_descriptor(secp, index)
+                .map(|desc| (index, desc.script_pubkey()))
+                .ok()
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        // take until failure
+        .map_while(|spk| spk)
+        .collect()
+}
+
+fn next_store_index<K: Clone + Ord>(inner: &SpkTxOutIndex<(K, u32)>, keychain: &K) -> u32 {
+    inner
+        .all_spks()
+        .range((keychain.clone(), u32::MIN)..(keychain.clone(), u32::MAX))
+        .last()
+        .map_or(0, |((_, v), _)| *v + 1)
 }
 
 fn range_descriptor_spks<'a, R>(
+    secp: Arc<Secp256k1<VerifyOnly>>,
     descriptor: Cow<'a, Descriptor<DescriptorPublicKey>>,
     range: R,
 ) -> impl Iterator<Item = (u32, Script)> + Clone + Send + 'a
 where
     R: Iterator<Item = u32> + Clone + Send + 'a,
 {
-    let secp = Secp256k1::verification_only();
     let has_wildcard = descriptor.has_wildcard();
     range
         .into_iter()
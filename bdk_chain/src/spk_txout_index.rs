@@ -4,6 +4,7 @@ use crate::{
     collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
     ForEachTxOut,
 };
+use alloc::vec::Vec;
 use bitcoin::{self, OutPoint, Script, Transaction, TxOut, Txid};
 
 /// An index storing [`TxOut`]s that have a script pubkey that matches those in a list.
@@ -184,6 +185,33 @@ impl<I: Clone + Ord> SpkTxOutIndex<I> {
         }
     }
 
+    /// Removes all script pubkeys in the index `range` along with any txouts indexed under them.
+    ///
+    /// This breaks the monotone nature of the index and is only meant for replacing the source of
+    /// the script pubkeys (i.e. a keychain's descriptor) or dropping speculatively stored ones.
+    pub(crate) fn remove_spks_in_range(&mut self, range: impl RangeBounds<I> + Clone) {
+        let removed = self
+            .spks
+            .range(range.clone())
+            .map(|(index, _)| index.clone())
+            .collect::<Vec<_>>();
+        for index in &removed {
+            if let Some(spk) = self.spks.remove(index) {
+                self.spk_indices.remove(&spk);
+            }
+            self.unused.remove(index);
+        }
+
+        let outpoints = self
+            .outputs_in_range(range)
+            .map(|(index, op)| (index.clone(), op))
+            .collect::<Vec<_>>();
+        for (index, op) in outpoints {
+            self.txouts.remove(&op);
+            self.spk_txouts.remove(&(index, op));
+        }
+    }
+
     /// Iterates over a unused script pubkeys in a index range.
     ///
     /// Here "unused" means that after the script pubkey was stored in the index, the index has
@@ -2,7 +2,7 @@
 #[macro_use]
 mod common;
 use bdk_chain::{
-    keychain::{Balance, KeychainTracker},
+    keychain::{balance, balance_with_trust, Balance, KeychainTracker, KeychainTxOutIndex},
     miniscript::{
         bitcoin::{secp256k1::Secp256k1, OutPoint, PackedLockTime, Transaction, TxOut},
         Descriptor,
@@ -172,6 +172,28 @@ fn test_balance() {
         }
     );
 
+    // per-keychain balances are categorized the same way and add up to the whole balance
+    assert_eq!(
+        tracker.balance_of_keychain(&Keychain::One, 5, should_trust),
+        Balance {
+            untrusted_pending: 13_000,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        tracker.balance_of_keychain(&Keychain::Two, 5, should_trust),
+        Balance {
+            trusted_pending: 7_000,
+            immature: 11_000,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        tracker.balance_of_keychain(&Keychain::One, 5, should_trust)
+            + tracker.balance_of_keychain(&Keychain::Two, 5, should_trust),
+        tracker.balance(should_trust)
+    );
+
     let _ = tracker
         .insert_tx(tx1.clone(), TxHeight::Confirmed(1))
         .unwrap();
@@ -234,6 +256,57 @@ fn test_balance() {
         }
     );
 
+    assert_eq!(
+        tracker.balance_of_keychain(&Keychain::Two, 99, should_trust),
+        Balance {
+            confirmed: 18_000,
+            ..Default::default()
+        }
+    );
+    // the coinbase output is still immature at height 98
+    assert_eq!(
+        tracker.balance_of_keychain(&Keychain::Two, 98, should_trust),
+        Balance {
+            immature: 11_000,
+            confirmed: 7_000,
+            ..Default::default()
+        }
+    );
+
+    // tx1 has 99 confirmations and tx2 has 98 confirmations at height 99
+    assert_eq!(
+        tracker.balance_with_min_confirmations(99, 99, should_trust),
+        Balance {
+            trusted_pending: 7_000,
+            untrusted_pending: 0,
+            immature: 0,
+            confirmed: 24_000,
+        }
+    );
+    assert_eq!(
+        tracker.balance_with_min_confirmations(99, 100, should_trust),
+        Balance {
+            trusted_pending: 7_000,
+            untrusted_pending: 13_000,
+            immature: 0,
+            confirmed: 11_000,
+        }
+    );
+    // the coinbase output is immature at an earlier tip
+    assert_eq!(
+        tracker.balance_with_min_confirmations(98, 1, should_trust),
+        Balance {
+            trusted_pending: 0,
+            untrusted_pending: 0,
+            immature: 11_000,
+            confirmed: 20_000,
+        }
+    );
+    assert_eq!(
+        tracker.balance_with_min_confirmations(99, 0, should_trust),
+        tracker.balance(should_trust)
+    );
+
     assert_eq!(tracker.balance_at(0), 0);
     assert_eq!(tracker.balance_at(1), 13_000);
     assert_eq!(tracker.balance_at(2), 20_000);
@@ -241,3 +314,173 @@ fn test_balance() {
     assert_eq!(tracker.balance_at(99), 31_000);
     assert_eq!(tracker.balance_at(100), 31_000);
 }
+
+#[test]
+fn test_balance_of_chain_graph() {
+    use core::str::FromStr;
+    #[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+    enum Keychain {
+        External,
+        Internal,
+    }
+    let mut tracker = KeychainTracker::<Keychain, TxHeight>::default();
+    let external = Descriptor::from_str("tr([73c5da0a/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/0/*)#rg247h69").unwrap();
+    let internal = Descriptor::from_str("tr([73c5da0a/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/1/*)#ju05rz2a").unwrap();
+    tracker.add_keychain(Keychain::External, external);
+    tracker.add_keychain(Keychain::Internal, internal);
+    let mut reveal = |keychain| tracker.txout_index.reveal_next_spk(&keychain).0 .1.clone();
+    let external_spks = [reveal(Keychain::External), reveal(Keychain::External)];
+    let internal_spk = reveal(Keychain::Internal);
+
+    let tx_receive = Transaction {
+        version: 0x01,
+        lock_time: PackedLockTime(0),
+        input: vec![],
+        output: vec![TxOut {
+            value: 13_000,
+            script_pubkey: external_spks[0].clone(),
+        }],
+    };
+    // pays change to our internal keychain
+    let tx_send = Transaction {
+        version: 0x01,
+        lock_time: PackedLockTime(0),
+        input: vec![TxIn {
+            previous_output: OutPoint::new(tx_receive.txid(), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: 5_000,
+            script_pubkey: internal_spk,
+        }],
+    };
+    // received on our external keychain, so only trusted if the external keychain is
+    let tx_receive_pending = Transaction {
+        version: 0x01,
+        lock_time: PackedLockTime(0),
+        input: vec![TxIn {
+            previous_output: OutPoint::new(h!("foreign"), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: 2_000,
+            script_pubkey: external_spks[1].clone(),
+        }],
+    };
+    let tx_coinbase = Transaction {
+        version: 0x01,
+        lock_time: PackedLockTime(0),
+        input: vec![TxIn::default()],
+        output: vec![TxOut {
+            value: 11_000,
+            script_pubkey: external_spks[1].clone(),
+        }],
+    };
+
+    let _ = tracker
+        .insert_checkpoint(BlockId {
+            height: 5,
+            hash: h!("5"),
+        })
+        .unwrap();
+    let _ = tracker
+        .insert_tx(tx_coinbase, TxHeight::Confirmed(0))
+        .unwrap();
+    let _ = tracker
+        .insert_tx(tx_receive, TxHeight::Confirmed(1))
+        .unwrap();
+    assert_eq!(
+        balance(tracker.chain_graph(), &tracker.txout_index, 5),
+        Balance {
+            immature: 11_000,
+            confirmed: 13_000,
+            ..Default::default()
+        }
+    );
+
+    let _ = tracker.insert_tx(tx_send, TxHeight::Unconfirmed).unwrap();
+    let _ = tracker
+        .insert_tx(tx_receive_pending, TxHeight::Unconfirmed)
+        .unwrap();
+    // all owned keychains are trusted by default
+    assert_eq!(
+        balance(tracker.chain_graph(), &tracker.txout_index, 5),
+        Balance {
+            immature: 11_000,
+            trusted_pending: 7_000,
+            untrusted_pending: 0,
+            confirmed: 0,
+        }
+    );
+    assert_eq!(
+        balance_with_trust(tracker.chain_graph(), &tracker.txout_index, 5, |_| true),
+        balance(tracker.chain_graph(), &tracker.txout_index, 5)
+    );
+
+    // only trusting the internal keychain leaves the pending receive untrusted
+    let only_internal = |keychain: &Keychain| keychain == &Keychain::Internal;
+    assert_eq!(
+        balance_with_trust(
+            tracker.chain_graph(),
+            &tracker.txout_index,
+            5,
+            only_internal
+        ),
+        Balance {
+            immature: 11_000,
+            trusted_pending: 5_000,
+            untrusted_pending: 2_000,
+            confirmed: 0,
+        }
+    );
+
+    // the coinbase output matures at height 99
+    assert_eq!(
+        balance_with_trust(
+            tracker.chain_graph(),
+            &tracker.txout_index,
+            99,
+            only_internal
+        ),
+        Balance {
+            trusted_pending: 5_000,
+            untrusted_pending: 2_000,
+            confirmed: 11_000,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn test_txout_index_apply_changeset() {
+    let mut tracker = KeychainTracker::<(), ConfirmationTime>::default();
+    let secp = Secp256k1::new();
+    let (descriptor, _) = Descriptor::parse_descriptor(&secp, "tr([73c5da0a/86'/0'/0']xprv9xgqHN7yz9MwCkxsBPN5qetuNdQSUttZNKw1dcYTV4mkaAFiBVGQziHs3NRSWMkCzvgjEe3n9xV8oYywvM8at9yRqyaZVz6TYYhX98VjsUk/0/*)").unwrap();
+    tracker.add_keychain((), descriptor.clone());
+
+    let tx = Transaction {
+        version: 0x01,
+        lock_time: PackedLockTime(0),
+        input: vec![],
+        output: vec![TxOut {
+            value: 100_000,
+            script_pubkey: descriptor.at_derivation_index(3).script_pubkey(),
+        }],
+    };
+
+    let (_, additions) = tracker.txout_index.reveal_to_target(&(), 7);
+    let mut changeset = tracker
+        .insert_tx_preview(tx.clone(), ConfirmationTime::Unconfirmed)
+        .unwrap();
+    changeset.derivation_indices = additions.into();
+
+    // a fresh index recovers both the revealed indices and the txouts from the changeset
+    let mut txout_index = KeychainTxOutIndex::default();
+    txout_index.add_keychain((), descriptor);
+    txout_index.apply_changeset(&changeset);
+    assert_eq!(txout_index.last_revealed_index(&()), Some(7));
+    assert_eq!(
+        txout_index.txouts_of_keychain(&()).collect::<Vec<_>>(),
+        vec![(3, OutPoint::new(tx.txid(), 0))]
+    );
+}
@@ -4,10 +4,14 @@
 mod common;
 use bdk_chain::{
     collections::BTreeMap,
-    keychain::{DerivationAdditions, KeychainTxOutIndex},
+    keychain::{
+        DerivationAdditions, KeychainChangeSet, KeychainConflict, KeychainTxOutIndex,
+        MultipathError, NoSuchKeychain,
+    },
+    TxHeight,
 };
 
-use bitcoin::{secp256k1::Secp256k1, Script, Transaction, TxOut};
+use bitcoin::{secp256k1::Secp256k1, Address, Network, OutPoint, Script, Transaction, TxOut};
 use miniscript::{Descriptor, DescriptorPublicKey};
 
 #[derive(Clone, Debug, PartialEq, Eq, Ord, PartialOrd)]
@@ -219,6 +223,49 @@ fn test_lookahead() {
     // TODO!
 }
 
+#[test]
+fn test_scan_with_matches() {
+    let (mut txout_index, external_desc, internal_desc) = init_txout_index();
+    txout_index.set_lookahead_for_all(5);
+
+    let tx = Transaction {
+        output: vec![
+            TxOut {
+                script_pubkey: spk_at_index(&internal_desc, 2),
+                value: 10_000,
+            },
+            TxOut {
+                script_pubkey: Script::default(),
+                value: 10_000,
+            },
+            TxOut {
+                script_pubkey: spk_at_index(&external_desc, 4),
+                value: 10_000,
+            },
+        ],
+        ..common::new_tx(0)
+    };
+    let txid = tx.txid();
+
+    let (additions, matches) = txout_index.scan_with_matches(&tx);
+    assert_eq!(
+        additions.as_inner(),
+        &[(TestKeychain::External, 4), (TestKeychain::Internal, 2)].into()
+    );
+    assert_eq!(
+        matches,
+        vec![
+            (OutPoint::new(txid, 0), TestKeychain::Internal, 2),
+            (OutPoint::new(txid, 2), TestKeychain::External, 4),
+        ]
+    );
+
+    // scanning again matches the same outpoints without revealing anything new
+    let (additions, matches) = txout_index.scan_with_matches(&tx);
+    assert!(additions.is_empty());
+    assert_eq!(matches.len(), 2);
+}
+
 #[test]
 fn test_wildcard_derivations() {
     let (mut txout_index, external_desc, _) = init_txout_index();
@@ -504,3 +551,1164 @@ fn test_non_wildcard_reserve_spk_functions() {
     assert_eq!(revealed_spks.count(), 0);
     assert!(revealed_additions.is_empty());
 }
+
+#[test]
+fn test_replace_descriptor() {
+    let (mut txout_index, external_desc, internal_desc) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::External, 5);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 9);
+
+    // scan a txout of the old descriptor
+    let tx = Transaction {
+        output: vec![TxOut {
+            script_pubkey: spk_at_index(&external_desc, 3),
+            value: 10_000,
+        }],
+        ..common::new_tx(0)
+    };
+    let _ = txout_index.scan(&tx);
+    assert_eq!(
+        txout_index.last_used_index(&TestKeychain::External),
+        Some(3)
+    );
+
+    // given:
+    // - external keychain revealed up to 9 with a lookahead of 5
+    // when:
+    // - the external descriptor is replaced with the internal descriptor's
+    // expect:
+    // - all spks of the old descriptor (and txouts indexed under them) are gone
+    // - spks of the new descriptor are revealed up to 9 (plus the lookahead)
+    let additions = txout_index.replace_descriptor(TestKeychain::External, internal_desc.clone());
+    assert_eq!(additions.as_inner(), &[(TestKeychain::External, 9)].into());
+    assert_eq!(
+        txout_index.keychains().get(&TestKeychain::External),
+        Some(&internal_desc)
+    );
+    assert_eq!(
+        txout_index.last_revealed_index(&TestKeychain::External),
+        Some(9)
+    );
+    assert_eq!(txout_index.last_used_index(&TestKeychain::External), None);
+    assert!(txout_index
+        .index_of_spk(&spk_at_index(&external_desc, 3))
+        .is_none());
+    assert_eq!(
+        txout_index
+            .revealed_spks_of_keychain(&TestKeychain::External)
+            .map(|(i, spk)| (i, spk.clone()))
+            .collect::<Vec<_>>(),
+        (0..=9)
+            .map(|i| (i, spk_at_index(&internal_desc, i)))
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(txout_index.inner().all_spks().len(), 15);
+
+    // keychains with nothing revealed only get the lookahead replenished
+    let (mut txout_index, external_desc, _) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::Internal, 3);
+    let additions = txout_index.replace_descriptor(TestKeychain::Internal, external_desc.clone());
+    assert!(additions.is_empty());
+    assert_eq!(
+        txout_index
+            .inner()
+            .spk_at_index(&(TestKeychain::Internal, 2)),
+        Some(&spk_at_index(&external_desc, 2))
+    );
+}
+
+#[test]
+fn test_add_multipath_keychain() {
+    let (_, external_desc, internal_desc) = init_txout_index();
+    let mut txout_index = KeychainTxOutIndex::<TestKeychain>::default();
+
+    let multipath = "tr([73c5da0a/86'/0'/0']xprv9xgqHN7yz9MwCkxsBPN5qetuNdQSUttZNKw1dcYTV4mkaAFiBVGQziHs3NRSWMkCzvgjEe3n9xV8oYywvM8at9yRqyaZVz6TYYhX98VjsUk/<0;1>/*)";
+    txout_index
+        .add_multipath_keychain(TestKeychain::External, TestKeychain::Internal, multipath)
+        .expect("must add multipath keychain");
+    assert_eq!(
+        txout_index.keychains(),
+        &[
+            (TestKeychain::External, external_desc),
+            (TestKeychain::Internal, internal_desc)
+        ]
+        .into()
+    );
+
+    // descriptors without exactly two paths are rejected
+    let mut txout_index = KeychainTxOutIndex::<TestKeychain>::default();
+    for (descriptor, path_count) in [
+        (multipath.replace("<0;1>", "0"), 1),
+        (multipath.replace("<0;1>", "<0;1;2>"), 3),
+    ] {
+        assert!(matches!(
+            txout_index.add_multipath_keychain(
+                TestKeychain::External,
+                TestKeychain::Internal,
+                &descriptor
+            ),
+            Err(MultipathError::InvalidPathCount(count)) if count == path_count
+        ));
+    }
+    assert!(matches!(
+        txout_index.add_multipath_keychain(
+            TestKeychain::External,
+            TestKeychain::Internal,
+            "tr(not_a_key/<0;1>/*)"
+        ),
+        Err(MultipathError::Miniscript(_))
+    ));
+    assert!(txout_index.keychains().is_empty());
+
+    // the checksum is verified against the multipath descriptor
+    txout_index
+        .add_multipath_keychain(
+            TestKeychain::External,
+            TestKeychain::Internal,
+            &format!("{}#h6a9ljl6", multipath),
+        )
+        .expect("checksum must be valid");
+    let mut txout_index = KeychainTxOutIndex::<TestKeychain>::default();
+    assert!(matches!(
+        txout_index.add_multipath_keychain(
+            TestKeychain::External,
+            TestKeychain::Internal,
+            &format!("{}#rg247h69", multipath),
+        ),
+        Err(MultipathError::InvalidChecksum)
+    ));
+    assert!(txout_index.keychains().is_empty());
+}
+
+#[test]
+fn test_add_multipath_keychain_conflict() {
+    let (_, external_desc, _) = init_txout_index();
+    let multipath = "tr([73c5da0a/86'/0'/0']xprv9xgqHN7yz9MwCkxsBPN5qetuNdQSUttZNKw1dcYTV4mkaAFiBVGQziHs3NRSWMkCzvgjEe3n9xV8oYywvM8at9yRqyaZVz6TYYhX98VjsUk/<0;1>/*)";
+
+    // the internal keychain conflicts, so the external keychain must not be added either
+    let mut txout_index = KeychainTxOutIndex::<TestKeychain>::default();
+    txout_index.add_keychain(TestKeychain::Internal, external_desc.clone());
+    assert!(matches!(
+        txout_index.add_multipath_keychain(TestKeychain::External, TestKeychain::Internal, multipath),
+        Err(MultipathError::Conflict(conflict)) if conflict.existing == external_desc
+    ));
+    assert_eq!(
+        txout_index.keychains(),
+        &[(TestKeychain::Internal, external_desc.clone())].into()
+    );
+
+    // both paths can not share a keychain
+    let mut txout_index = KeychainTxOutIndex::<TestKeychain>::default();
+    assert!(matches!(
+        txout_index.add_multipath_keychain(
+            TestKeychain::External,
+            TestKeychain::External,
+            multipath
+        ),
+        Err(MultipathError::SameKeychain)
+    ));
+    assert!(txout_index.keychains().is_empty());
+
+    // even if the keychain already has a different descriptor
+    let mut txout_index = KeychainTxOutIndex::<TestKeychain>::default();
+    txout_index.add_keychain(TestKeychain::Internal, external_desc.clone());
+    assert!(matches!(
+        txout_index.add_multipath_keychain(
+            TestKeychain::Internal,
+            TestKeychain::Internal,
+            multipath
+        ),
+        Err(MultipathError::SameKeychain)
+    ));
+    assert_eq!(
+        txout_index.keychains(),
+        &[(TestKeychain::Internal, external_desc)].into()
+    );
+}
+
+#[test]
+fn test_from_persisted() {
+    let (mut txout_index, _, _) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::External, 10);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 14);
+    let _ = txout_index.reveal_to_target(&TestKeychain::Internal, 3);
+
+    let restored = KeychainTxOutIndex::from_persisted(
+        txout_index.keychains().clone(),
+        txout_index.last_revealed_indices().clone(),
+        txout_index.lookaheads().clone(),
+    );
+    assert_eq!(restored.keychains(), txout_index.keychains());
+    assert_eq!(
+        restored.last_revealed_indices(),
+        txout_index.last_revealed_indices()
+    );
+    assert_eq!(restored.lookaheads(), txout_index.lookaheads());
+    assert_eq!(restored.inner().all_spks(), txout_index.inner().all_spks());
+}
+
+#[test]
+fn test_from_persisted_lazy() {
+    let (mut txout_index, external_desc, _) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::External, 10);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 14);
+    let _ = txout_index.reveal_to_target(&TestKeychain::Internal, 3);
+
+    let mut restored = KeychainTxOutIndex::from_persisted_lazy(
+        txout_index.keychains().clone(),
+        txout_index.last_revealed_indices().clone(),
+        txout_index.lookaheads().clone(),
+    );
+    assert_eq!(
+        restored.last_revealed_indices(),
+        txout_index.last_revealed_indices()
+    );
+    // nothing is derived on load
+    assert!(restored.is_deferred(&TestKeychain::External));
+    assert!(restored.is_deferred(&TestKeychain::Internal));
+
+    // the first mutating call on a keychain derives it
+    let ((index, _), additions) = restored.next_unused_spk(&TestKeychain::Internal);
+    assert_eq!(index, 0);
+    assert!(additions.is_empty());
+    assert!(!restored.is_deferred(&TestKeychain::Internal));
+    assert!(restored.is_deferred(&TestKeychain::External));
+    assert_eq!(restored.stored_spk_count(&TestKeychain::Internal), 4);
+
+    // scanning derives every keychain, so txouts in the lookahead are found
+    let tx = Transaction {
+        output: vec![TxOut {
+            value: 10_000,
+            script_pubkey: spk_at_index(&external_desc, 20),
+        }],
+        ..common::new_tx(0)
+    };
+    assert_eq!(
+        restored.scan(&tx).as_inner(),
+        &[(TestKeychain::External, 20)].into()
+    );
+    assert!(!restored.is_deferred(&TestKeychain::External));
+
+    let _ = txout_index.scan(&tx);
+    assert_eq!(restored.inner().all_spks(), txout_index.inner().all_spks());
+}
+
+#[test]
+fn test_derive_deferred_spks() {
+    let (mut txout_index, _, _) = init_txout_index();
+    txout_index.set_lookahead_for_all(5);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 9);
+
+    let mut restored = KeychainTxOutIndex::from_persisted_lazy(
+        txout_index.keychains().clone(),
+        txout_index.last_revealed_indices().clone(),
+        txout_index.lookaheads().clone(),
+    );
+    restored.derive_deferred_spks(&TestKeychain::External);
+    assert!(!restored.is_deferred(&TestKeychain::External));
+    assert!(restored.is_deferred(&TestKeychain::Internal));
+    assert_eq!(restored.stored_spk_count(&TestKeychain::External), 15);
+
+    restored.derive_all_deferred_spks();
+    assert_eq!(restored.inner().all_spks(), txout_index.inner().all_spks());
+}
+
+#[test]
+fn test_mark_used_derives_deferred_keychain() {
+    let (mut txout_index, _, _) = init_txout_index();
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 4);
+
+    let mut restored = KeychainTxOutIndex::from_persisted_lazy(
+        txout_index.keychains().clone(),
+        txout_index.last_revealed_indices().clone(),
+        txout_index.lookaheads().clone(),
+    );
+    assert!(restored.mark_used(&TestKeychain::External, 2));
+    assert!(!restored.is_deferred(&TestKeychain::External));
+    assert!(restored.is_used(&TestKeychain::External, 2));
+    assert!(!restored.is_used(&TestKeychain::External, 3));
+
+    assert!(!restored.unmark_used(&TestKeychain::Internal, 0));
+    assert!(!restored.is_deferred(&TestKeychain::Internal));
+}
+
+#[test]
+fn test_read_derives_deferred_keychains() {
+    let (mut txout_index, _, _) = init_txout_index();
+    txout_index.set_lookahead_for_all(3);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 4);
+
+    let mut restored = KeychainTxOutIndex::from_persisted_lazy(
+        txout_index.keychains().clone(),
+        txout_index.last_revealed_indices().clone(),
+        txout_index.lookaheads().clone(),
+    );
+    // a read query derives every deferred keychain, including through `Deref`
+    assert_eq!(
+        restored.spk_at_index(&TestKeychain::External, 0),
+        txout_index.spk_at_index(&TestKeychain::External, 0)
+    );
+    assert!(!restored.is_deferred(&TestKeychain::External));
+    assert!(!restored.is_deferred(&TestKeychain::Internal));
+    assert_eq!(restored.all_spks(), txout_index.all_spks());
+
+    // mutations apply on top of what the read derived
+    let _ = txout_index.reveal_next_spk(&TestKeychain::Internal);
+    let _ = restored.reveal_next_spk(&TestKeychain::Internal);
+    assert!(txout_index.mark_used(&TestKeychain::External, 2));
+    assert!(restored.mark_used(&TestKeychain::External, 2));
+    assert_eq!(restored.all_spks(), txout_index.all_spks());
+    assert!(restored.is_used(&TestKeychain::External, 2));
+}
+
+#[test]
+fn test_lookahead_spks_of_keychain() {
+    let (mut txout_index, external_desc, _) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::External, 3);
+    assert_eq!(
+        txout_index
+            .lookahead_spks_of_keychain(&TestKeychain::External)
+            .map(|(index, spk)| (index, spk.clone()))
+            .collect::<Vec<_>>(),
+        (0..3)
+            .map(|index| (index, spk_at_index(&external_desc, index)))
+            .collect::<Vec<_>>()
+    );
+
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 4);
+    assert_eq!(
+        txout_index
+            .lookahead_spks_of_keychain(&TestKeychain::External)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>(),
+        vec![5, 6, 7]
+    );
+    assert_eq!(
+        txout_index
+            .lookahead_spks_of_keychain(&TestKeychain::Internal)
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn test_index_of_spk() {
+    let (mut txout_index, external_desc, internal_desc) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::Internal, 5);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 2);
+
+    assert_eq!(
+        txout_index.index_of_spk(&spk_at_index(&external_desc, 2)),
+        Some((TestKeychain::External, 2))
+    );
+    // lookahead spks are found too
+    assert_eq!(
+        txout_index.index_of_spk(&spk_at_index(&internal_desc, 4)),
+        Some((TestKeychain::Internal, 4))
+    );
+    assert_eq!(
+        txout_index.index_of_spk(&spk_at_index(&external_desc, 3)),
+        None
+    );
+}
+
+#[test]
+fn test_derivation_of_outpoint() {
+    let (mut txout_index, external_desc, internal_desc) = init_txout_index();
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 1);
+    let _ = txout_index.reveal_to_target(&TestKeychain::Internal, 0);
+
+    let tx = Transaction {
+        output: vec![
+            TxOut {
+                script_pubkey: spk_at_index(&internal_desc, 0),
+                value: 10_000,
+            },
+            TxOut {
+                script_pubkey: Script::new(),
+                value: 10_000,
+            },
+            TxOut {
+                script_pubkey: spk_at_index(&external_desc, 1),
+                value: 10_000,
+            },
+        ],
+        ..common::new_tx(0)
+    };
+    let txid = tx.txid();
+    let _ = txout_index.scan(&tx);
+
+    assert_eq!(
+        txout_index.derivation_of_outpoint(&OutPoint::new(txid, 0)),
+        Some((TestKeychain::Internal, 0))
+    );
+    assert_eq!(
+        txout_index.derivation_of_outpoint(&OutPoint::new(txid, 1)),
+        None
+    );
+    assert_eq!(
+        txout_index.derivation_of_outpoint(&OutPoint::new(txid, 2)),
+        Some((TestKeychain::External, 1))
+    );
+}
+
+#[test]
+fn test_trailing_unused_count() {
+    let (mut txout_index, external_desc, _) = init_txout_index();
+    assert_eq!(
+        txout_index.trailing_unused_count(&TestKeychain::External),
+        0
+    );
+
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 9);
+    assert_eq!(
+        txout_index.trailing_unused_count(&TestKeychain::External),
+        10
+    );
+
+    let tx = Transaction {
+        output: vec![TxOut {
+            script_pubkey: spk_at_index(&external_desc, 6),
+            value: 10_000,
+        }],
+        ..common::new_tx(0)
+    };
+    let _ = txout_index.scan(&tx);
+    assert_eq!(
+        txout_index.trailing_unused_count(&TestKeychain::External),
+        3
+    );
+
+    // reserving a script pubkey does not count as used
+    txout_index.mark_used(&TestKeychain::External, 9);
+    assert_eq!(
+        txout_index.trailing_unused_count(&TestKeychain::External),
+        3
+    );
+    assert_eq!(
+        txout_index.trailing_unused_count(&TestKeychain::Internal),
+        0
+    );
+}
+
+#[test]
+fn test_try_methods_on_missing_keychain() {
+    let mut txout_index = KeychainTxOutIndex::<TestKeychain>::default();
+    let (_, external_desc, _) = init_txout_index();
+    txout_index.add_keychain(TestKeychain::External, external_desc);
+    let missing = NoSuchKeychain(TestKeychain::Internal);
+
+    assert_eq!(
+        txout_index.try_next_index(&TestKeychain::Internal),
+        Err(missing.clone())
+    );
+    assert_eq!(
+        txout_index.try_set_lookahead(&TestKeychain::Internal, 10),
+        Err(missing.clone())
+    );
+    assert!(txout_index.lookaheads().is_empty());
+    assert_eq!(
+        txout_index
+            .try_spks_of_keychain(&TestKeychain::Internal)
+            .err(),
+        Some(missing.clone())
+    );
+    assert_eq!(
+        txout_index
+            .try_reveal_to_target(&TestKeychain::Internal, 5)
+            .err()
+            .map(|e| e.0),
+        Some(TestKeychain::Internal)
+    );
+    assert_eq!(
+        txout_index
+            .try_reveal_next_spk(&TestKeychain::Internal)
+            .err(),
+        Some(missing)
+    );
+
+    // existing keychains behave like the panicking methods
+    assert_eq!(
+        txout_index.try_next_index(&TestKeychain::External),
+        Ok((0, true))
+    );
+    assert!(txout_index
+        .try_set_lookahead(&TestKeychain::External, 10)
+        .is_ok());
+    let (_, additions) = txout_index
+        .try_reveal_to_target(&TestKeychain::External, 5)
+        .expect("keychain exists");
+    assert_eq!(additions.as_inner(), &[(TestKeychain::External, 5)].into());
+    let ((index, _), _) = txout_index
+        .try_reveal_next_spk(&TestKeychain::External)
+        .expect("keychain exists");
+    assert_eq!(index, 6);
+}
+
+#[test]
+fn test_try_add_keychain() {
+    let (mut txout_index, external_desc, internal_desc) = init_txout_index();
+
+    // re-adding the same descriptor is fine
+    assert_eq!(
+        txout_index.try_add_keychain(TestKeychain::External, external_desc.clone()),
+        Ok(())
+    );
+    assert_eq!(
+        txout_index.try_add_keychain(TestKeychain::External, internal_desc),
+        Err(KeychainConflict {
+            existing: external_desc.clone()
+        })
+    );
+    assert_eq!(
+        txout_index.keychains().get(&TestKeychain::External),
+        Some(&external_desc)
+    );
+}
+
+#[test]
+fn test_reveal_to_target_with_scripts() {
+    let (mut txout_index, external_desc, _) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::External, 3);
+
+    let (spks, additions) = txout_index.reveal_to_target_with_scripts(&TestKeychain::External, 4);
+    assert_eq!(
+        spks,
+        (0..=4)
+            .map(|i| (i, spk_at_index(&external_desc, i)))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(additions.as_inner(), &[(TestKeychain::External, 4)].into());
+
+    // only the newly revealed spks are returned
+    let (spks, additions) = txout_index.reveal_to_target_with_scripts(&TestKeychain::External, 6);
+    assert_eq!(
+        spks,
+        (5..=6)
+            .map(|i| (i, spk_at_index(&external_desc, i)))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(additions.as_inner(), &[(TestKeychain::External, 6)].into());
+
+    let (spks, additions) = txout_index.reveal_to_target_with_scripts(&TestKeychain::External, 2);
+    assert!(spks.is_empty());
+    assert!(additions.is_empty());
+}
+
+#[test]
+fn test_addresses() {
+    let (mut txout_index, external_desc, _) = init_txout_index();
+    let address_at = |index| {
+        Address::from_script(&spk_at_index(&external_desc, index), Network::Testnet).unwrap()
+    };
+
+    let (index, address, additions) = txout_index
+        .reveal_next_address(&TestKeychain::External, Network::Testnet)
+        .expect("taproot is addressable");
+    assert_eq!((index, address), (0, address_at(0)));
+    assert_eq!(additions.as_inner(), &[(TestKeychain::External, 0)].into());
+
+    let (index, address, additions) = txout_index
+        .next_unused_address(&TestKeychain::External, Network::Testnet)
+        .expect("taproot is addressable");
+    assert_eq!((index, address), (0, address_at(0)));
+    assert!(additions.is_empty());
+
+    txout_index.mark_used(&TestKeychain::External, 0);
+    let (index, address, additions) = txout_index
+        .next_unused_address(&TestKeychain::External, Network::Testnet)
+        .expect("taproot is addressable");
+    assert_eq!((index, address), (1, address_at(1)));
+    assert_eq!(additions.as_inner(), &[(TestKeychain::External, 1)].into());
+
+    // bare descriptors are not addressable
+    let secp = bitcoin::secp256k1::Secp256k1::signing_only();
+    let (bare_descriptor, _) = Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, "pk([73c5da0a/86'/0'/0']xprv9xgqHN7yz9MwCkxsBPN5qetuNdQSUttZNKw1dcYTV4mkaAFiBVGQziHs3NRSWMkCzvgjEe3n9xV8oYywvM8at9yRqyaZVz6TYYhX98VjsUk/2/*)").unwrap();
+    let mut txout_index = KeychainTxOutIndex::<TestKeychain>::default();
+    txout_index.add_keychain(TestKeychain::External, bare_descriptor);
+    assert!(txout_index
+        .reveal_next_address(&TestKeychain::External, Network::Testnet)
+        .is_err());
+    assert_eq!(
+        txout_index.last_revealed_index(&TestKeychain::External),
+        None
+    );
+}
+
+#[test]
+fn test_used_spks_of_keychain() {
+    let (mut txout_index, external_desc, _) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::External, 5);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 4);
+    assert_eq!(
+        txout_index
+            .used_spks_of_keychain(&TestKeychain::External)
+            .count(),
+        0
+    );
+
+    let tx = Transaction {
+        output: vec![TxOut {
+            script_pubkey: spk_at_index(&external_desc, 3),
+            value: 10_000,
+        }],
+        ..common::new_tx(0)
+    };
+    let _ = txout_index.scan(&tx);
+    txout_index.mark_used(&TestKeychain::External, 1);
+    // lookahead spks are not revealed so are never reported
+    txout_index.mark_used(&TestKeychain::External, 7);
+
+    assert_eq!(
+        txout_index
+            .used_spks_of_keychain(&TestKeychain::External)
+            .map(|(i, spk)| (i, spk.clone()))
+            .collect::<Vec<_>>(),
+        [1, 3]
+            .into_iter()
+            .map(|i| (i, spk_at_index(&external_desc, i)))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_is_used() {
+    let (mut txout_index, external_desc, _) = init_txout_index();
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 2);
+    assert!(!txout_index.is_used(&TestKeychain::External, 1));
+    // script pubkeys which are not stored are not used
+    assert!(!txout_index.is_used(&TestKeychain::External, 3));
+    assert!(!txout_index.is_used(&TestKeychain::Internal, 0));
+
+    txout_index.mark_used(&TestKeychain::External, 1);
+    assert!(txout_index.is_used(&TestKeychain::External, 1));
+    txout_index.unmark_used(&TestKeychain::External, 1);
+    assert!(!txout_index.is_used(&TestKeychain::External, 1));
+
+    let tx = Transaction {
+        output: vec![TxOut {
+            script_pubkey: spk_at_index(&external_desc, 2),
+            value: 10_000,
+        }],
+        ..common::new_tx(0)
+    };
+    let _ = txout_index.scan(&tx);
+    assert!(txout_index.is_used(&TestKeychain::External, 2));
+    // scanned spks cannot be unmarked
+    txout_index.unmark_used(&TestKeychain::External, 2);
+    assert!(txout_index.is_used(&TestKeychain::External, 2));
+}
+
+#[test]
+fn test_shrink_lookahead() {
+    let (mut txout_index, _, internal_desc) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::External, 20);
+    txout_index.set_lookahead(&TestKeychain::Internal, 20);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 4);
+    assert_eq!(txout_index.inner().all_spks().len(), 25 + 20);
+
+    // given:
+    // - external keychain revealed up to 4 with a lookahead of 20
+    // when:
+    // - lookahead is shrunk to 3
+    // expect:
+    // - only spks up to 4 + 3 are kept, other keychains are untouched
+    txout_index.shrink_lookahead(&TestKeychain::External, 3);
+    assert_eq!(
+        txout_index.lookaheads().get(&TestKeychain::External),
+        Some(&3)
+    );
+    assert_eq!(txout_index.inner().all_spks().len(), 8 + 20);
+    assert!(txout_index
+        .inner()
+        .spk_at_index(&(TestKeychain::External, 8))
+        .is_none());
+
+    // revealing past the shrunk lookahead still works
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 6);
+    assert_eq!(txout_index.inner().all_spks().len(), 10 + 20);
+
+    // keychains with nothing revealed
+    txout_index.shrink_lookahead(&TestKeychain::Internal, 0);
+    assert_eq!(txout_index.inner().all_spks().len(), 10);
+    let ((index, spk), _) = txout_index.reveal_next_spk(&TestKeychain::Internal);
+    assert_eq!((index, spk), (0, &spk_at_index(&internal_desc, 0)));
+
+    // growing the lookahead again works as usual
+    txout_index.set_lookahead(&TestKeychain::External, 20);
+    assert_eq!(txout_index.inner().all_spks().len(), 27 + 1);
+}
+
+#[test]
+fn test_shrink_then_grow_lookahead() {
+    let (mut txout_index, external_desc, _) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::External, 5);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 2);
+    assert_eq!(txout_index.stored_spk_count(&TestKeychain::External), 8);
+
+    // enlarging the lookahead only stores the missing spks
+    txout_index.set_lookahead(&TestKeychain::External, 10);
+    assert_eq!(txout_index.stored_spk_count(&TestKeychain::External), 13);
+
+    txout_index.shrink_lookahead(&TestKeychain::External, 1);
+    assert_eq!(txout_index.stored_spk_count(&TestKeychain::External), 4);
+
+    txout_index.set_lookahead(&TestKeychain::External, 7);
+    assert_eq!(txout_index.stored_spk_count(&TestKeychain::External), 10);
+
+    // stored spks are contiguous and match the descriptor
+    assert_eq!(
+        txout_index
+            .inner()
+            .all_spks()
+            .iter()
+            .map(|((_, i), spk)| (*i, spk.clone()))
+            .collect::<Vec<_>>(),
+        (0..10)
+            .map(|i| (i, spk_at_index(&external_desc, i)))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_revealed_and_stored_counts() {
+    let (mut txout_index, _, _) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::Internal, 10);
+    assert_eq!(txout_index.revealed_count(&TestKeychain::External), 0);
+    assert_eq!(txout_index.stored_spk_count(&TestKeychain::Internal), 10);
+
+    let _ = txout_index.reveal_next_spk(&TestKeychain::External);
+    assert_eq!(txout_index.revealed_count(&TestKeychain::External), 1);
+    let _ = txout_index.reveal_to_target(&TestKeychain::Internal, 36);
+    assert_eq!(txout_index.revealed_count(&TestKeychain::Internal), 37);
+    assert_eq!(txout_index.stored_spk_count(&TestKeychain::Internal), 47);
+    assert_eq!(txout_index.stored_spk_count(&TestKeychain::External), 1);
+
+    assert_eq!(
+        txout_index.all_revealed_counts(),
+        [(TestKeychain::External, 1), (TestKeychain::Internal, 37)].into()
+    );
+    let (txout_index, _, _) = init_txout_index();
+    assert_eq!(
+        txout_index.all_revealed_counts(),
+        [(TestKeychain::External, 0), (TestKeychain::Internal, 0)].into()
+    );
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_par_derivation_matches_serial() {
+    let (mut serial, _, _) = init_txout_index();
+    let (mut par, _, _) = init_txout_index();
+
+    serial.set_lookahead(&TestKeychain::External, 50);
+    par.set_lookahead_par(&TestKeychain::External, 50);
+    assert_eq!(serial.inner().all_spks(), par.inner().all_spks());
+
+    for target in [0, 10, 5, 300] {
+        for keychain in [TestKeychain::External, TestKeychain::Internal] {
+            let (_, serial_additions) = serial.reveal_to_target(&keychain, target);
+            let par_additions = par.reveal_to_target_par(&keychain, target);
+            assert_eq!(serial_additions, par_additions);
+        }
+        assert_eq!(serial.last_revealed_indices(), par.last_revealed_indices());
+        assert_eq!(serial.inner().all_spks(), par.inner().all_spks());
+    }
+
+    // non-wildcard descriptors only have index 0
+    let secp = bitcoin::secp256k1::Secp256k1::signing_only();
+    let (no_wildcard_descriptor, _) = Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, "wpkh([73c5da0a/86'/0'/0']xprv9xgqHN7yz9MwCkxsBPN5qetuNdQSUttZNKw1dcYTV4mkaAFiBVGQziHs3NRSWMkCzvgjEe3n9xV8oYywvM8at9yRqyaZVz6TYYhX98VjsUk/1/0)").unwrap();
+    let mut txout_index = KeychainTxOutIndex::<TestKeychain>::default();
+    txout_index.add_keychain(TestKeychain::External, no_wildcard_descriptor);
+    txout_index.set_lookahead_par(&TestKeychain::External, 10);
+    let additions = txout_index.reveal_to_target_par(&TestKeychain::External, 200);
+    assert_eq!(additions.as_inner(), &[(TestKeychain::External, 0)].into());
+    assert_eq!(txout_index.inner().all_spks().len(), 1);
+    assert!(txout_index
+        .reveal_to_target_par(&TestKeychain::External, 200)
+        .is_empty());
+}
+
+#[test]
+fn test_spk_at_index() {
+    let (mut txout_index, external_desc, internal_desc) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::Internal, 2);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 3);
+
+    assert_eq!(
+        txout_index.spk_at_index(&TestKeychain::External, 3),
+        Some(&spk_at_index(&external_desc, 3))
+    );
+    assert_eq!(txout_index.spk_at_index(&TestKeychain::External, 4), None);
+    // lookahead spks are stored too
+    assert_eq!(
+        txout_index.spk_at_index(&TestKeychain::Internal, 1),
+        Some(&spk_at_index(&internal_desc, 1))
+    );
+}
+
+#[test]
+fn test_reveal_within_stored_range() {
+    let (mut txout_index, external_desc, _) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::External, 2);
+    // store more than the lookahead requires
+    txout_index.lookahead_to_target(&TestKeychain::External, 10);
+    let stored_count = txout_index.stored_spk_count(&TestKeychain::External);
+    assert!(stored_count > 2);
+
+    // given:
+    // - external keychain has more spks stored than the lookahead of 2
+    // when:
+    // - revealing spks one at a time (within and past the stored range)
+    // expect:
+    // - already stored spks are revealed and only the missing lookahead is stored
+    for index in 0..15 {
+        let ((revealed_index, spk), additions) =
+            txout_index.reveal_next_spk(&TestKeychain::External);
+        assert_eq!(revealed_index, index);
+        assert_eq!(spk, &spk_at_index(&external_desc, index));
+        assert_eq!(
+            additions.as_inner(),
+            &[(TestKeychain::External, index)].into()
+        );
+        assert_eq!(
+            txout_index.stored_spk_count(&TestKeychain::External),
+            stored_count.max(index + 3)
+        );
+    }
+}
+
+#[test]
+fn test_reveal_to_last_used_plus() {
+    let (mut txout_index, external_desc, _) = init_txout_index();
+    txout_index.set_lookahead(&TestKeychain::External, 10);
+
+    // nothing is used, so we reveal `buffer` spks from 0
+    assert!(txout_index
+        .reveal_to_last_used_plus(&TestKeychain::Internal, 0)
+        .is_empty());
+    assert_eq!(
+        txout_index
+            .reveal_to_last_used_plus(&TestKeychain::Internal, 5)
+            .as_inner(),
+        &[(TestKeychain::Internal, 4)].into()
+    );
+
+    let tx = Transaction {
+        output: vec![TxOut {
+            script_pubkey: spk_at_index(&external_desc, 6),
+            value: 10_000,
+        }],
+        ..common::new_tx(0)
+    };
+    let _ = txout_index.scan(&tx);
+    assert_eq!(
+        txout_index
+            .reveal_to_last_used_plus(&TestKeychain::External, 10)
+            .as_inner(),
+        &[(TestKeychain::External, 16)].into()
+    );
+    assert_eq!(
+        txout_index.trailing_unused_count(&TestKeychain::External),
+        10
+    );
+
+    // nothing changes if the buffer is already revealed
+    assert!(txout_index
+        .reveal_to_last_used_plus(&TestKeychain::External, 3)
+        .is_empty());
+}
+
+#[test]
+fn test_next_unused_spks_of_all_keychains() {
+    let (mut txout_index, external_desc, internal_desc) = init_txout_index();
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 1);
+    txout_index.mark_used(&TestKeychain::External, 0);
+
+    // given:
+    // - external keychain has index 1 revealed and unused
+    // - internal keychain has nothing revealed
+    // expect:
+    // - external index 1 is returned without revealing
+    // - internal index 0 is revealed
+    let (spks, additions) = txout_index.next_unused_spks_of_all_keychains();
+    assert_eq!(
+        spks,
+        [
+            (TestKeychain::External, (1, spk_at_index(&external_desc, 1))),
+            (TestKeychain::Internal, (0, spk_at_index(&internal_desc, 0))),
+        ]
+        .into()
+    );
+    assert_eq!(additions.as_inner(), &[(TestKeychain::Internal, 0)].into());
+
+    // nothing is revealed when called again
+    let (spks_again, additions) = txout_index.next_unused_spks_of_all_keychains();
+    assert_eq!(spks_again, spks);
+    assert!(additions.is_empty());
+}
+
+#[test]
+fn test_apply_additions_reports_changes() {
+    let (mut source, _, _) = init_txout_index();
+    let (_, older) = source.reveal_to_target(&TestKeychain::External, 4);
+    let (_, newer) = source
+        .reveal_to_target_multi(&[(TestKeychain::External, 9), (TestKeychain::Internal, 2)].into());
+
+    let (mut txout_index, _, _) = init_txout_index();
+    assert_eq!(txout_index.apply_additions(newer.clone()), newer);
+
+    // given:
+    // - additions were applied out of order
+    // expect:
+    // - stale additions change nothing and report nothing
+    assert!(txout_index.apply_additions(older).is_empty());
+    assert!(txout_index.apply_additions(newer).is_empty());
+    assert_eq!(
+        txout_index.last_revealed_indices(),
+        &[(TestKeychain::External, 9), (TestKeychain::Internal, 2)].into()
+    );
+}
+
+#[test]
+fn test_txouts_of_keychain_in_range() {
+    let (mut txout_index, external_desc, internal_desc) = init_txout_index();
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 9);
+    let _ = txout_index.reveal_to_target(&TestKeychain::Internal, 9);
+
+    let tx = Transaction {
+        output: [1, 4, 5, 8]
+            .into_iter()
+            .map(|index| TxOut {
+                script_pubkey: spk_at_index(&external_desc, index),
+                value: 10_000,
+            })
+            .chain([TxOut {
+                script_pubkey: spk_at_index(&internal_desc, 4),
+                value: 10_000,
+            }])
+            .collect(),
+        ..common::new_tx(0)
+    };
+    let txid = tx.txid();
+    let _ = txout_index.scan(&tx);
+
+    assert_eq!(
+        txout_index
+            .txouts_of_keychain_in_range(&TestKeychain::External, 4..8)
+            .collect::<Vec<_>>(),
+        vec![(4, OutPoint::new(txid, 1)), (5, OutPoint::new(txid, 2))]
+    );
+    assert_eq!(
+        txout_index
+            .txouts_of_keychain_in_range(&TestKeychain::External, 0..u32::MAX)
+            .count(),
+        4
+    );
+    assert_eq!(
+        txout_index
+            .txouts_of_keychain_in_range(&TestKeychain::External, 6..2)
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn test_descriptor_of() {
+    let mut txout_index = KeychainTxOutIndex::<TestKeychain>::default();
+    let (_, external_desc, _) = init_txout_index();
+    txout_index.add_keychain(TestKeychain::External, external_desc.clone());
+
+    assert_eq!(
+        txout_index.descriptor_of(&TestKeychain::External),
+        Some(&external_desc)
+    );
+    assert_eq!(txout_index.descriptor_of(&TestKeychain::Internal), None);
+}
+
+#[test]
+fn test_revealed_spks_flat() {
+    let (mut txout_index, external_desc, internal_desc) = init_txout_index();
+    txout_index.set_lookahead_for_all(5);
+    let _ = txout_index.reveal_to_target(&TestKeychain::Internal, 1);
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 2);
+
+    // lookahead spks are not included
+    assert_eq!(
+        txout_index.revealed_spks_flat().collect::<Vec<_>>(),
+        vec![
+            (TestKeychain::External, 0, &spk_at_index(&external_desc, 0)),
+            (TestKeychain::External, 1, &spk_at_index(&external_desc, 1)),
+            (TestKeychain::External, 2, &spk_at_index(&external_desc, 2)),
+            (TestKeychain::Internal, 0, &spk_at_index(&internal_desc, 0)),
+            (TestKeychain::Internal, 1, &spk_at_index(&internal_desc, 1)),
+        ]
+    );
+}
+
+#[test]
+fn test_active_keychains() {
+    let (mut txout_index, _, _) = init_txout_index();
+    assert!(txout_index.has_keychain(&TestKeychain::External));
+    assert!(txout_index.has_keychain(&TestKeychain::Internal));
+    assert_eq!(txout_index.active_keychains().count(), 0);
+
+    let _ = txout_index.reveal_next_spk(&TestKeychain::Internal);
+    assert_eq!(
+        txout_index.active_keychains().collect::<Vec<_>>(),
+        vec![&TestKeychain::Internal]
+    );
+
+    let txout_index = KeychainTxOutIndex::<TestKeychain>::default();
+    assert!(!txout_index.has_keychain(&TestKeychain::External));
+}
+
+#[test]
+fn test_peek_spks() {
+    let (mut txout_index, external_desc, _) = init_txout_index();
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 1);
+
+    let peeked = txout_index.peek_spks(&TestKeychain::External, 3);
+    assert_eq!(
+        peeked,
+        (2..5)
+            .map(|i| (i, spk_at_index(&external_desc, i)))
+            .collect::<Vec<_>>()
+    );
+    // peeking does not reveal
+    assert_eq!(
+        txout_index.last_revealed_index(&TestKeychain::External),
+        Some(1)
+    );
+    assert_eq!(txout_index.next_index(&TestKeychain::External), (2, true));
+    assert!(txout_index.peek_spks(&TestKeychain::Internal, 0).is_empty());
+
+    // descriptors without wildcards only have index 0
+    let mut txout_index = KeychainTxOutIndex::<TestKeychain>::default();
+    let secp = bitcoin::secp256k1::Secp256k1::signing_only();
+    let (no_wildcard_descriptor, _) = Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, "wpkh([73c5da0a/86'/0'/0']xprv9xgqHN7yz9MwCkxsBPN5qetuNdQSUttZNKw1dcYTV4mkaAFiBVGQziHs3NRSWMkCzvgjEe3n9xV8oYywvM8at9yRqyaZVz6TYYhX98VjsUk/1/0)").unwrap();
+    let external_spk = no_wildcard_descriptor
+        .at_derivation_index(0)
+        .script_pubkey();
+    txout_index.add_keychain(TestKeychain::External, no_wildcard_descriptor);
+
+    assert_eq!(
+        txout_index.peek_spks(&TestKeychain::External, 5),
+        vec![(0, external_spk)]
+    );
+    let _ = txout_index.reveal_next_spk(&TestKeychain::External);
+    assert!(txout_index.peek_spks(&TestKeychain::External, 5).is_empty());
+}
+
+#[test]
+fn test_remove_keychain() {
+    let (mut txout_index, external_desc, _) = init_txout_index();
+    let _ = txout_index.reveal_to_target(&TestKeychain::External, 5);
+    let _ = txout_index.reveal_to_target(&TestKeychain::Internal, 2);
+    let tx = Transaction {
+        output: vec![TxOut {
+            value: 10_000,
+            script_pubkey: spk_at_index(&external_desc, 1),
+        }],
+        ..common::new_tx(0)
+    };
+    let _ = txout_index.scan(&tx);
+
+    let delta = txout_index.remove_keychain(&TestKeychain::External);
+    assert!(delta.revealed.is_empty());
+    assert_eq!(delta.removed, [TestKeychain::External].into());
+    assert!(!txout_index.has_keychain(&TestKeychain::External));
+    assert_eq!(
+        txout_index.last_revealed_index(&TestKeychain::External),
+        None
+    );
+    assert_eq!(
+        txout_index.index_of_spk(&spk_at_index(&external_desc, 1)),
+        None
+    );
+    assert_eq!(
+        txout_index.derivation_of_outpoint(&OutPoint::new(tx.txid(), 0)),
+        None
+    );
+    // other keychains are untouched
+    assert_eq!(
+        txout_index.last_revealed_index(&TestKeychain::Internal),
+        Some(2)
+    );
+
+    // removing it again has no effect
+    assert!(txout_index
+        .remove_keychain(&TestKeychain::External)
+        .is_empty());
+}
+
+#[test]
+fn test_apply_changeset_removes_keychain() {
+    let (mut txout_index, _, _) = init_txout_index();
+    let mut changeset = KeychainChangeSet::<TestKeychain, TxHeight>::from(
+        txout_index.reveal_to_target(&TestKeychain::External, 5).1,
+    );
+    changeset.append(
+        txout_index
+            .reveal_to_target(&TestKeychain::Internal, 2)
+            .1
+            .into(),
+    );
+    changeset.append(txout_index.remove_keychain(&TestKeychain::External).into());
+
+    // replaying the changes on a fresh index does not bring the removed keychain back
+    let (mut restored, _, _) = init_txout_index();
+    restored.apply_changeset(&changeset);
+    assert!(!restored.has_keychain(&TestKeychain::External));
+    assert_eq!(
+        restored.last_revealed_indices(),
+        txout_index.last_revealed_indices()
+    );
+}
+
+#[test]
+fn test_apply_changeset_readds_removed_keychain() {
+    let (mut txout_index, _, internal_descriptor) = init_txout_index();
+    let mut changeset = KeychainChangeSet::<TestKeychain, TxHeight>::from(
+        txout_index.reveal_to_target(&TestKeychain::External, 5).1,
+    );
+    changeset.append(txout_index.remove_keychain(&TestKeychain::External).into());
+    txout_index.add_keychain(TestKeychain::External, internal_descriptor.clone());
+    changeset.append(
+        txout_index
+            .reveal_to_target(&TestKeychain::External, 2)
+            .1
+            .into(),
+    );
+    assert!(changeset
+        .derivation_indices
+        .removed
+        .contains(&TestKeychain::External));
+    assert_eq!(
+        changeset
+            .derivation_indices
+            .revealed
+            .get(&TestKeychain::External),
+        Some(&2)
+    );
+
+    // the caller adds the keychain back with its new descriptor before replaying
+    let mut restored = KeychainTxOutIndex::<TestKeychain>::default();
+    restored.add_keychain(TestKeychain::External, internal_descriptor.clone());
+    restored.apply_changeset(&changeset);
+    assert_eq!(
+        restored.last_revealed_indices(),
+        txout_index.last_revealed_indices()
+    );
+    assert_eq!(
+        restored.spk_at_index(&TestKeychain::External, 2),
+        Some(&spk_at_index(&internal_descriptor, 2))
+    );
+
+    // applying the merged changeset to an index that already revealed more resets the keychain
+    let (mut stale, _, _) = init_txout_index();
+    let _ = stale.reveal_to_target(&TestKeychain::External, 5);
+    let _ = stale.replace_descriptor(TestKeychain::External, internal_descriptor);
+    stale.apply_changeset(&changeset);
+    assert_eq!(stale.last_revealed_index(&TestKeychain::External), Some(2));
+}
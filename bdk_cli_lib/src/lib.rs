@@ -13,9 +13,11 @@ use bdk_chain::{
         Descriptor, DescriptorPublicKey,
     },
     sparse_chain::{self, ChainPosition},
-    DescriptorExt, FullTxOut,
+    FullTxOut,
+};
+use bdk_coin_select::{
+    coin_select_bnb, CoinSelector, CoinSelectorOptBuilder, FeeRate, WeightedValue,
 };
-use bdk_coin_select::{coin_select_bnb, CoinSelector, CoinSelectorOpt, WeightedValue};
 use bdk_file_store::KeychainStore;
 pub use clap;
 use clap::{Parser, Subcommand};
@@ -231,7 +233,7 @@ where
                     "{:?} {} used:{}",
                     index,
                     address,
-                    txout_index.is_used(&(target_keychain, index))
+                    txout_index.is_used(&target_keychain, index)
                 );
             }
             Ok(())
@@ -346,13 +348,7 @@ pub fn create_tx<P: ChainPosition>(
     // turn the txos we chose into a weight and value
     let wv_candidates = candidates
         .iter()
-        .map(|(plan, utxo)| {
-            WeightedValue::new(
-                utxo.txout.value,
-                plan.expected_weight() as _,
-                plan.witness_version().is_some(),
-            )
-        })
+        .map(|(plan, utxo)| WeightedValue::from_plan(&utxo.txout, plan))
         .collect();
 
     let mut outputs = vec![TxOut {
@@ -395,20 +391,14 @@ pub fn create_tx<P: ChainPosition>(
         script_pubkey: change_script,
     };
 
-    let cs_opts = CoinSelectorOpt {
-        target_feerate: 0.5,
-        min_drain_value: keychain_tracker
-            .txout_index
-            .keychains()
-            .get(&internal_keychain)
-            .expect("must exist")
-            .dust_value(),
-        ..CoinSelectorOpt::fund_outputs(
-            &outputs,
-            &change_output,
-            change_plan.expected_weight() as u32,
-        )
-    };
+    let cs_opts = CoinSelectorOptBuilder::fund_outputs(
+        &outputs,
+        &change_output,
+        change_plan.expected_weight() as u32,
+    )
+    .target_feerate(FeeRate::from_sat_per_vb(2.0))
+    .drain_dust_limit(&change_output.script_pubkey)
+    .build()?;
 
     // TODO: How can we make it easy to shuffle in order of inputs and outputs here?
     // apply coin selection by saying we need to fund these outputs
@@ -0,0 +1,198 @@
+//! [`Arbitrary`] implementations for fuzzing coin selection with [`proptest`].
+//!
+//! Generated values are bounded so that selections are realistic (values up to 1 BTC per
+//! candidate, feerates up to 250 sats/vb) and arithmetic does not overflow.
+use super::*;
+use proptest::{
+    arbitrary::{any, Arbitrary},
+    option,
+    strategy::{BoxedStrategy, Strategy},
+};
+
+impl Arbitrary for WeightedValue {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            0_u64..=100_000_000,
+            0_u32..=1_000,
+            any::<bool>(),
+            option::of(0_u32..=1_000),
+        )
+            .prop_map(
+                |(value, satisfaction_weight, is_segwit, confirmations)| WeightedValue {
+                    confirmations,
+                    ..WeightedValue::new(value, satisfaction_weight, is_segwit)
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for CoinSelectorOpt {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            (
+                option::of(0_u64..=200_000_000),
+                0_u64..=10_000,
+                0.25_f32..=250.0,
+                option::of(0.0_f32..=250.0),
+                0_u64..=100_000,
+            ),
+            (
+                40_u32..=4_000,
+                32_u32..=400,
+                100_u32..=1_000,
+                0_u64..=10_000,
+                option::of(2_u32..=4),
+            ),
+        )
+            .prop_map(
+                |(
+                    (target_value, max_extra_target, target_feerate, long_term_feerate, min_fee),
+                    (base_weight, drain_weight, spend_drain_weight, min_drain_value, multi_drain),
+                )| {
+                    let mut builder = CoinSelectorOptBuilder::new(base_weight)
+                        .max_extra_target(max_extra_target)
+                        .target_feerate(FeeRate::from_sat_per_vb(target_feerate))
+                        .min_absolute_fee(min_fee)
+                        .drain(drain_weight, spend_drain_weight)
+                        .min_drain_value(min_drain_value);
+                    if let Some(target_value) = target_value {
+                        builder = builder.target_value(target_value);
+                    }
+                    if let Some(long_term_feerate) = long_term_feerate {
+                        builder =
+                            builder.long_term_feerate(FeeRate::from_sat_per_vb(long_term_feerate));
+                    }
+                    if let Some(count) = multi_drain {
+                        builder = builder.multi_drain(count);
+                    }
+                    builder.build().expect("generated feerates are valid")
+                },
+            )
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        coin_select_bnb, coin_select_largest_first, CoinSelectError, CoinSelector, CoinSelectorOpt,
+        FeeRate, Selection, Vec, WeightedValue,
+    };
+    use proptest::{collection, prelude::*, sample::Index};
+
+    fn check_invariants(
+        selector: &CoinSelector,
+        selection: &Selection,
+        preselected: &[usize],
+    ) -> Result<(), TestCaseError> {
+        let opts = selector.opts();
+        for index in preselected {
+            prop_assert!(
+                selection.selected.contains(index),
+                "preselected {} was deselected",
+                index
+            );
+        }
+        for (kind, strategy) in &selection.excess_strategies {
+            prop_assert!(
+                strategy.fee as f64 >= opts.target_feerate.fee_of_weight(strategy.weight),
+                "{} pays a lower feerate than the target",
+                kind
+            );
+            prop_assert!(
+                strategy.fee >= opts.min_absolute_fee,
+                "{} pays less than the min absolute fee",
+                kind
+            );
+            prop_assert_eq!(
+                selector.selected_absolute_value(),
+                strategy.recipient_value.unwrap_or(0)
+                    + strategy.drain_value.unwrap_or(0)
+                    + strategy.fee,
+                "{} inputs must equal outputs plus fee",
+                kind
+            );
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn selection_invariants(
+            candidates in collection::vec(any::<WeightedValue>(), 1..20),
+            opts in any::<CoinSelectorOpt>(),
+            preselected in collection::vec(any::<Index>(), 0..3),
+        ) {
+            let mut selector = CoinSelector::new(&candidates, &opts);
+            for index in &preselected {
+                selector.select(index.index(candidates.len()));
+            }
+            let preselected = selector.selected_indexes().collect::<Vec<_>>();
+
+            if let Some(bnb_selector) = coin_select_bnb(1_000, selector.clone()) {
+                if let Ok(selection) = bnb_selector.finish() {
+                    check_invariants(&bnb_selector, &selection, &preselected)?;
+                }
+            }
+
+            let mut largest_first_selector = selector.clone();
+            if let Ok(selection) = coin_select_largest_first(&mut largest_first_selector) {
+                check_invariants(&largest_first_selector, &selection, &preselected)?;
+            }
+
+            if let Ok(selection) = selector.select_until_finished() {
+                check_invariants(&selector, &selection, &preselected)?;
+            }
+        }
+
+        #[test]
+        fn lower_feerate_selects_fewer_or_equal(
+            candidates in collection::vec(any::<WeightedValue>(), 1..20),
+            opts in any::<CoinSelectorOpt>(),
+            lower_feerate_ratio in 0.0_f32..1.0,
+        ) {
+            let lower_opts = CoinSelectorOpt {
+                target_feerate: FeeRate::from_sat_per_wu(
+                    (opts.target_feerate.as_sat_per_wu() * lower_feerate_ratio).max(0.0625),
+                ),
+                ..opts
+            };
+
+            // `coin_select_bnb` minimizes waste, so it can select more when the target feerate
+            // drops below the long term feerate. What holds is that its selection stays valid at
+            // the lower feerate.
+            if let Some(higher_selector) =
+                coin_select_bnb(1_000, CoinSelector::new(&candidates, &opts))
+            {
+                if higher_selector.finish().is_ok() {
+                    let mut lower_selector = CoinSelector::new(&candidates, &lower_opts);
+                    for index in higher_selector.selected_indexes() {
+                        lower_selector.select(index);
+                    }
+                    prop_assert!(lower_selector.finish().is_ok());
+                }
+            }
+
+            let fallbacks: [fn(&mut CoinSelector) -> Result<Selection, CoinSelectError>; 2] = [
+                coin_select_largest_first,
+                |selector| selector.select_until_finished(),
+            ];
+            for select in fallbacks {
+                let mut higher_selector = CoinSelector::new(&candidates, &opts);
+                let mut lower_selector = CoinSelector::new(&candidates, &lower_opts);
+                if let Ok(higher) = select(&mut higher_selector) {
+                    let lower = select(&mut lower_selector);
+                    prop_assert!(lower.is_ok());
+                    prop_assert!(lower.unwrap().len() <= higher.len());
+                }
+            }
+        }
+    }
+}
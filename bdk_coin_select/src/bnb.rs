@@ -20,8 +20,8 @@ impl BranchStrategy {
 }
 
 /// Closure to decide the branching strategy, alongside a score (if the current selection is a
-/// candidate solution).
-pub type DecideStrategy<'c, S> = dyn Fn(&Bnb<'c, S>) -> (BranchStrategy, Option<S>);
+/// candidate solution). The closure may borrow state that lives for `'f`.
+pub type DecideStrategy<'c, 'f, S> = dyn Fn(&Bnb<'c, S>) -> (BranchStrategy, Option<S>) + 'f;
 
 /// [`Bnb`] represents the current state of the BnB algorithm.
 pub struct Bnb<'c, S> {
@@ -37,10 +37,10 @@ pub struct Bnb<'c, S> {
 impl<'c, S: Ord> Bnb<'c, S> {
     /// Creates a new [`Bnb`].
     pub fn new(selector: CoinSelector<'c>, pool: Vec<(usize, &'c WeightedValue)>, max: S) -> Self {
-        let (rem_abs, rem_eff) = pool.iter().fold((0, 0), |(abs, eff), (_, c)| {
+        let (rem_abs, rem_eff) = pool.iter().fold((0_u64, 0_i64), |(abs, eff), &(index, c)| {
             (
-                abs + c.value,
-                eff + c.effective_value(selector.opts.target_feerate),
+                abs.saturating_add(c.value),
+                eff.saturating_add(selector.effective_value(index)),
             )
         });
 
@@ -58,7 +58,7 @@ impl<'c, S: Ord> Bnb<'c, S> {
     ///
     /// `strategy` should assess our current selection/node and determine the branching strategy and
     /// whether this selection is a candidate solution (if so, return the score of the selection).
-    pub fn into_iter<'f>(self, strategy: &'f DecideStrategy<'c, S>) -> BnbIter<'c, 'f, S> {
+    pub fn into_iter<'f>(self, strategy: &'f DecideStrategy<'c, 'f, S>) -> BnbIter<'c, 'f, S> {
         BnbIter {
             state: self,
             done: false,
@@ -80,8 +80,10 @@ impl<'c, S: Ord> Bnb<'c, S> {
                     self.selection.deselect(index);
                     return true;
                 } else {
-                    self.rem_abs += candidate.value;
-                    self.rem_eff += candidate.effective_value(self.selection.opts.target_feerate);
+                    self.rem_abs = self.rem_abs.saturating_add(candidate.value);
+                    self.rem_eff = self
+                        .rem_eff
+                        .saturating_add(self.selection.effective_value(index));
                     return false;
                 }
             })
@@ -91,8 +93,10 @@ impl<'c, S: Ord> Bnb<'c, S> {
     /// Continue down this branch, skip inclusion branch if specified.
     pub fn forward(&mut self, skip: bool) {
         let (index, candidate) = self.pool[self.pool_pos];
-        self.rem_abs -= candidate.value;
-        self.rem_eff -= candidate.effective_value(self.selection.opts.target_feerate);
+        self.rem_abs = self.rem_abs.saturating_sub(candidate.value);
+        self.rem_eff = self
+            .rem_eff
+            .saturating_sub(self.selection.effective_value(index));
 
         if !skip {
             self.selection.select(index);
@@ -116,7 +120,7 @@ pub struct BnbIter<'c, 'f, S> {
 
     /// Check our current selection (node), and returns the branching strategy, alongside a score
     /// (if the current selection is a candidate solution).
-    strategy: &'f DecideStrategy<'c, S>,
+    strategy: &'f DecideStrategy<'c, 'f, S>,
 }
 
 impl<'c, 'f, S: Ord + Copy + Display> Iterator for BnbIter<'c, 'f, S> {
@@ -174,6 +178,31 @@ pub enum BnbLimit {
     Rounds(usize),
     #[cfg(feature = "std")]
     Duration(core::time::Duration),
+    /// Stop at whichever limit is reached first.
+    #[cfg(feature = "std")]
+    DurationOrRounds(core::time::Duration, usize),
+}
+
+/// Number of BnB rounds between checks of the clock when limiting by duration.
+#[cfg(feature = "std")]
+const BNB_CLOCK_CHECK_INTERVAL: usize = 1_000;
+
+/// Take items from `iter` until `timeout` elapses or `rounds` items are taken.
+///
+/// The clock is only checked every [`BNB_CLOCK_CHECK_INTERVAL`] rounds.
+#[cfg(feature = "std")]
+fn take_until_timeout<I: Iterator>(
+    iter: I,
+    timeout: core::time::Duration,
+    rounds: usize,
+) -> impl Iterator<Item = I::Item> {
+    let start = std::time::Instant::now();
+    iter.take(rounds)
+        .enumerate()
+        .take_while(move |(round, _)| {
+            round % BNB_CLOCK_CHECK_INTERVAL != 0 || start.elapsed() <= timeout
+        })
+        .map(|(_, item)| item)
 }
 
 impl From<usize> for BnbLimit {
@@ -198,52 +227,192 @@ impl From<core::time::Duration> for BnbLimit {
 ///   RBF), and `max_extra_target` (which can be used to increase the possible solution set, given
 ///   that the sender is okay with sending extra to the receiver).
 ///
+/// Candidates that are already selected (including [mandatory] candidates) are kept and are not part
+/// of the search.
+///
 /// Murch's Master Thesis: <https://murch.one/wp-content/uploads/2016/11/erhardt2016coinselection.pdf>
 /// Bitcoin Core Implementation: <https://github.com/bitcoin/bitcoin/blob/23.x/src/wallet/coinselection.cpp#L65>
 ///
+/// As the search minimizes waste, lowering the target feerate can result in more candidates being
+/// selected (i.e. consolidation once the target feerate drops below the long term feerate), though
+/// a selection found at a higher feerate stays valid (i.e. [`CoinSelector::finish`] succeeds) at a
+/// lower one. The deterministic fallbacks ([`coin_select_largest_first`] and
+/// [`CoinSelector::select_until_finished`]) never select more candidates at a lower feerate.
+///
 /// TODO: Another optimization we could do is figure out candidate with smallest waste, and
 /// if we find a result with waste equal to this, we can just break.
+///
+/// [mandatory]: CoinSelector::select_mandatory
 pub fn coin_select_bnb<L>(limit: L, selector: CoinSelector) -> Option<CoinSelector>
 where
     L: Into<BnbLimit>,
 {
-    let opts = selector.opts;
-
-    // prepare pool of candidates to select from:
-    // * filter out candidates with negative/zero effective values
-    // * sort candidates by descending effective value
-    let pool = {
-        let mut pool = selector
-            .unselected()
-            .filter(|(_, c)| c.effective_value(opts.target_feerate) > 0)
-            .collect::<Vec<_>>();
-        pool.sort_unstable_by(|(_, a), (_, b)| {
-            let a = a.effective_value(opts.target_feerate);
-            let b = b.effective_value(opts.target_feerate);
-            b.cmp(&a)
-        });
-        pool
-    };
+    search_bnb(limit, selector, None)
+}
+
+/// Run [`coin_select_bnb`] until `timeout` elapses, returning the best selection found so far.
+///
+/// `max_tries` is kept as a secondary cap on the number of rounds.
+#[cfg(feature = "std")]
+pub fn coin_select_bnb_timed(
+    timeout: core::time::Duration,
+    max_tries: usize,
+    selector: CoinSelector,
+) -> Option<CoinSelector> {
+    coin_select_bnb(BnbLimit::DurationOrRounds(timeout, max_tries), selector)
+}
+
+/// Run branch and bound over the unselected candidates of `selector` with a custom `strategy`.
+///
+/// [`coin_select_bnb`] is this with a strategy that scores selections by waste. `run_bnb` lets the
+/// caller supply their own score, e.g. to minimize the input count or to maximize privacy.
+///
+/// The pool only contains candidates with a positive effective value, sorted by descending
+/// effective value. `worst_score` is the initial [`Bnb::best_score`]; a selection is only reported
+/// if its score is lower than or equal to the best score so far.
+///
+/// `strategy` is called on every node and decides which branches to explore, alongside the score
+/// of the current selection (if it is a solution). It must not return
+/// [`BranchStrategy::Continue`] or [`BranchStrategy::SkipInclusion`] once the end of the pool is
+/// reached. If the score can only increase as more candidates are selected (e.g. waste when the
+/// target feerate is higher than the long term feerate), the strategy should return
+/// [`BranchStrategy::SkipBoth`] as soon as the current selection scores worse than
+/// [`Bnb::best_score`], as there is no better solution down this branch.
+///
+/// Returns the selection with the lowest score found before `limit` is reached.
+pub fn run_bnb<'a, 'f, S, L>(
+    strategy: &'f DecideStrategy<'a, 'f, S>,
+    selector: CoinSelector<'a>,
+    worst_score: S,
+    limit: L,
+) -> Option<CoinSelector<'a>>
+where
+    S: Ord + Copy + Display,
+    L: Into<BnbLimit>,
+{
+    let pool = bnb_pool(&selector);
+    let bnb = Bnb::new(selector, pool, worst_score);
+    best_within_limit(bnb.into_iter(strategy), limit.into())
+}
+
+/// Prepare the pool of candidates to select from:
+/// * filter out candidates with negative/zero effective values
+/// * sort candidates by descending effective value
+fn bnb_pool<'a>(selector: &CoinSelector<'a>) -> Vec<(usize, &'a WeightedValue)> {
+    let mut pool = selector
+        .unselected()
+        .filter(|&(index, _)| selector.effective_value(index) > 0)
+        .collect::<Vec<_>>();
+    pool.sort_unstable_by(|&(a, _), &(b, _)| {
+        selector
+            .effective_value(b)
+            .cmp(&selector.effective_value(a))
+    });
+    pool
+}
+
+/// Iterate `bnb` until `limit` is reached, returning the last (best) selection found.
+fn best_within_limit<'c, 'f, S: Ord + Copy + Display>(
+    bnb: BnbIter<'c, 'f, S>,
+    limit: BnbLimit,
+) -> Option<CoinSelector<'c>> {
+    // each round yields `Some` only if a new best selection is found
+    let last_found =
+        |best: Option<CoinSelector<'c>>, found: Option<CoinSelector<'c>>| found.or(best);
+    match limit {
+        BnbLimit::Rounds(rounds) => bnb.take(rounds).fold(None, last_found),
+        #[cfg(feature = "std")]
+        BnbLimit::Duration(duration) => {
+            take_until_timeout(bnb, duration, usize::MAX).fold(None, last_found)
+        }
+        #[cfg(feature = "std")]
+        BnbLimit::DurationOrRounds(duration, rounds) => {
+            take_until_timeout(bnb, duration, rounds).fold(None, last_found)
+        }
+    }
+}
+
+/// Like [`coin_select_bnb`], but does not give up on selections that overshoot the upper bound.
+///
+/// Returns the best selection found alongside a flag indicating whether it is an exact
+/// (changeless) match. If no exact match is found before the limit is reached (or the search is
+/// exhausted), the selection that meets the target with the least waste (assuming a drain output
+/// is added) is returned instead, with the flag set to `false`.
+///
+/// Returns `None` only if no selection that meets the target was visited.
+pub fn coin_select_bnb_best_effort<'a, L>(
+    limit: L,
+    selector: CoinSelector<'a>,
+) -> Option<(CoinSelector<'a>, bool)>
+where
+    L: Into<BnbLimit>,
+{
+    let best_inexact = core::cell::RefCell::new(None);
+    match search_bnb(limit, selector, Some(&best_inexact)) {
+        Some(selector) => Some((selector, true)),
+        None => best_inexact
+            .into_inner()
+            .map(|(_, selector)| (selector, false)),
+    }
+}
+
+/// The lowest waste selection (alongside its waste) which overshoots the upper bound of
+/// [`coin_select_bnb`] but can pay for a drain output.
+type BestInexact<'a> = core::cell::RefCell<Option<(i64, CoinSelector<'a>)>>;
+
+/// The search of [`coin_select_bnb`]. Selections which overshoot the upper bound are only
+/// considered if `best_inexact` is provided, so the plain search does not pay for it.
+fn search_bnb<'a, L>(
+    limit: L,
+    selector: CoinSelector<'a>,
+    best_inexact: Option<&BestInexact<'a>>,
+) -> Option<CoinSelector<'a>>
+where
+    L: Into<BnbLimit>,
+{
+    let opts = selector.opts();
+    let pool = bnb_pool(&selector);
 
     let feerate_decreases = opts.target_feerate > opts.long_term_feerate();
 
-    let target_abs = opts.target_value.unwrap_or(0) + opts.min_absolute_fee;
     let target_eff = selector.effective_target();
+    let cost_of_change = match opts.cost_of_change {
+        Some(cost_of_change) => saturating_i64(cost_of_change),
+        None => opts.drain_waste(),
+    };
+    let upper_bound_eff = target_eff.saturating_add(cost_of_change);
 
-    let upper_bound_abs = target_abs + (opts.drain_weight as f32 * opts.target_feerate) as u64;
-    let upper_bound_eff = target_eff + opts.drain_waste();
-
-    let strategy = move |bnb: &Bnb<i64>| -> (BranchStrategy, Option<i64>) {
+    let strategy = |bnb: &Bnb<'a, i64>| -> (BranchStrategy, Option<i64>) {
         let selected_abs = bnb.selection.selected_absolute_value();
         let selected_eff = bnb.selection.selected_effective_value();
+        let target_abs = bnb.selection.absolute_target();
+        let upper_bound_abs = target_abs.saturating_add(cost_of_change.max(0) as u64);
 
         // backtrack if remaining value is not enough to reach target
-        if selected_abs + bnb.rem_abs < target_abs || selected_eff + bnb.rem_eff < target_eff {
+        if selected_abs.saturating_add(bnb.rem_abs) < target_abs
+            || selected_eff.saturating_add(bnb.rem_eff) < target_eff
+        {
             return (BranchStrategy::SkipBoth, None);
         }
 
         // backtrack if selected value already surpassed upper bounds
         if selected_abs > upper_bound_abs && selected_eff > upper_bound_eff {
+            // the selection is not changeless, but it is a fallback for the best effort search if
+            // the excess is enough for a drain output
+            if let Some(best_inexact) = best_inexact.filter(|_| can_drain(&bnb.selection)) {
+                let waste = bnb
+                    .selection
+                    .selected_waste()
+                    .saturating_add(opts.drain_waste());
+                let mut best_inexact = best_inexact.borrow_mut();
+                let is_better = match &*best_inexact {
+                    Some((best_waste, _)) => waste < *best_waste,
+                    None => true,
+                };
+                if is_better {
+                    *best_inexact = Some((waste, bnb.selection.clone()));
+                }
+            }
             return (BranchStrategy::SkipBoth, None);
         }
 
@@ -281,47 +450,54 @@ where
     };
 
     // determine sum of absolute and effective values for current selection
-    let (selected_abs, selected_eff) = selector.selected().fold((0, 0), |(abs, eff), (_, c)| {
-        (
-            abs + c.value,
-            eff + c.effective_value(selector.opts.target_feerate),
-        )
-    });
+    let (selected_abs, selected_eff) = (
+        selector.selected_absolute_value(),
+        selector.selected_effective_value(),
+    );
 
+    let target_abs = selector.absolute_target();
     let bnb = Bnb::new(selector, pool, i64::MAX);
 
     // not enough to select anyway
-    if selected_abs + bnb.rem_abs < target_abs || selected_eff + bnb.rem_eff < target_eff {
+    if selected_abs.saturating_add(bnb.rem_abs) < target_abs
+        || selected_eff.saturating_add(bnb.rem_eff) < target_eff
+    {
         return None;
     }
 
-    match limit.into() {
-        BnbLimit::Rounds(rounds) => {
-            bnb.into_iter(&strategy)
-                .take(rounds)
-                .reduce(|b, c| if c.is_some() { c } else { b })
-        }
-        #[cfg(feature = "std")]
-        BnbLimit::Duration(duration) => {
-            let start = std::time::SystemTime::now();
-            bnb.into_iter(&strategy)
-                .take_while(|_| start.elapsed().expect("failed to get system time") <= duration)
-                .reduce(|b, c| if c.is_some() { c } else { b })
-        }
-    }?
+    best_within_limit(bnb.into_iter(&strategy), limit.into())
+}
+
+/// Whether `selection` can pay for a drain output of at least `min_drain_value` (with the same
+/// rules as [`CoinSelector::finish`]).
+fn can_drain(selection: &CoinSelector) -> bool {
+    let opts = selection.opts();
+    let weight_with_drain = selection.current_weight().saturating_add(opts.drain_weight);
+    let fee_with_drain = opts
+        .tx_fee(weight_with_drain)
+        .max(opts.min_fee(weight_with_drain));
+    let required = opts
+        .target_value
+        .unwrap_or(0)
+        .saturating_add(fee_with_drain)
+        .saturating_add(opts.min_drain_value);
+    weight_with_drain <= opts.max_weight.unwrap_or(u32::MAX)
+        && selection.selected_absolute_value() >= required
 }
 
 #[cfg(all(test, feature = "miniscript"))]
 mod test {
     use bitcoin::secp256k1::Secp256k1;
 
-    use crate::coin_select::{evaluate_cs::evaluate, ExcessStrategyKind};
+    use crate::{evaluate_cs::evaluate, ExcessStrategyKind};
 
     use super::{
-        coin_select_bnb,
+        coin_select_bnb, coin_select_bnb_best_effort, coin_select_bnb_timed,
         evaluate_cs::{Evaluation, EvaluationError},
+        run_bnb,
         tester::Tester,
-        CoinSelector, CoinSelectorOpt, Vec, WeightedValue,
+        Bnb, BranchStrategy, CoinSelector, CoinSelectorOpt, CoinSelectorOptBuilder, FeeRate, Vec,
+        WeightedValue,
     };
 
     fn tester() -> Tester {
@@ -341,6 +517,25 @@ mod test {
         })
     }
 
+    /// A candidate without weight, so spending it costs no fee.
+    fn weightless(value: u64) -> WeightedValue {
+        WeightedValue {
+            weight: 0,
+            ..WeightedValue::new(value, 0, false)
+        }
+    }
+
+    /// The fee `opts` require for spending the candidates at `indexes`, as seen by BnB (which
+    /// works with effective values and the effective target).
+    fn fee_of(candidates: &Vec<WeightedValue>, opts: &CoinSelectorOpt, indexes: &[usize]) -> u64 {
+        let selector = CoinSelector::new(candidates, opts);
+        let input_fees = indexes
+            .iter()
+            .map(|&index| candidates[index].value - selector.effective_value(index) as u64)
+            .sum::<u64>();
+        input_fees + selector.effective_target_breakdown().fee
+    }
+
     #[test]
     fn not_enough_coins() {
         let t = tester();
@@ -362,9 +557,14 @@ mod test {
             t.gen_candidate(2, 100_000).into(),
         ];
         let opts = CoinSelectorOpt {
-            target_feerate: 0.0,
+            target_feerate: FeeRate::from_sat_per_wu(1.0),
             ..t.gen_opts(200_000)
         };
+        // the preselected candidates pay for exactly the target and their fee
+        let opts = CoinSelectorOpt {
+            target_value: Some(200_000 - fee_of(&candidates, &opts, &[0, 1])),
+            ..opts
+        };
         let selector = {
             let mut selector = CoinSelector::new(&candidates, &opts);
             selector.select(0); // preselect
@@ -382,6 +582,147 @@ mod test {
         );
     }
 
+    #[test]
+    fn mandatory_candidates_are_kept() {
+        let t = tester();
+        let candidates: Vec<WeightedValue> = vec![
+            t.gen_candidate(0, 100_000).into(),
+            t.gen_candidate(1, 50_000).into(), // mandatory
+            t.gen_candidate(2, 50_000).into(),
+        ];
+        let opts = CoinSelectorOpt {
+            target_feerate: FeeRate::from_sat_per_wu(1.0),
+            ..t.gen_opts(100_000)
+        };
+        let opts = CoinSelectorOpt {
+            target_value: Some(100_000 - fee_of(&candidates, &opts, &[1, 2])),
+            ..opts
+        };
+        let selector = {
+            let mut selector = CoinSelector::new(&candidates, &opts);
+            selector.select_mandatory(1);
+            selector
+        };
+
+        let evaluation = evaluate_bnb(selector, 10_000).expect("eval failed");
+        assert_eq!(
+            evaluation.solution.selected,
+            [1, 2].iter().cloned().collect()
+        );
+    }
+
+    #[test]
+    fn best_effort_falls_back_to_selection_with_drain() {
+        let candidates = vec![weightless(6_000), weightless(6_000)];
+        // nothing has weight, so there are no fees
+        let opts = CoinSelectorOpt {
+            target_value: Some(10_000),
+            min_drain_value: 2_000,
+            ..CoinSelectorOptBuilder::new(0).build().unwrap()
+        };
+
+        // there is no changeless solution
+        assert!(coin_select_bnb(10_000, CoinSelector::new(&candidates, &opts)).is_none());
+
+        let (selector, exact) =
+            coin_select_bnb_best_effort(10_000, CoinSelector::new(&candidates, &opts))
+                .expect("should fall back to a selection with drain");
+        assert!(!exact);
+        assert_eq!(selector.selected_count(), 2);
+        let selection = selector.finish().expect("selection must be valid");
+        assert_eq!(
+            selection.excess_strategies[&ExcessStrategyKind::ToDrain].drain_value,
+            Some(2_000)
+        );
+
+        // the excess can not pay for a drain output of `min_drain_value`
+        let opts = CoinSelectorOpt {
+            min_drain_value: 2_001,
+            ..opts
+        };
+        assert!(
+            coin_select_bnb_best_effort(10_000, CoinSelector::new(&candidates, &opts)).is_none()
+        );
+    }
+
+    /// A custom strategy that minimizes the number of inputs
+    #[test]
+    fn run_bnb_with_custom_score() {
+        let candidates = vec![
+            weightless(5_000),
+            weightless(5_000),
+            weightless(5_000),
+            weightless(12_000),
+            weightless(4_000),
+        ];
+        let opts = CoinSelectorOptBuilder::new(0).build().unwrap();
+        let target = 14_000;
+
+        let strategy = |bnb: &Bnb<usize>| {
+            let count = bnb.selection.selected_count();
+            if bnb.selection.selected_absolute_value() >= target {
+                return (BranchStrategy::SkipBoth, Some(count));
+            }
+            if count >= bnb.best_score || bnb.pool_pos == bnb.pool.len() {
+                return (BranchStrategy::SkipBoth, None);
+            }
+            (BranchStrategy::Continue, None)
+        };
+
+        let selector = run_bnb(
+            &strategy,
+            CoinSelector::new(&candidates, &opts),
+            usize::MAX,
+            10_000,
+        )
+        .expect("should find solution");
+        assert_eq!(selector.selected_count(), 2);
+        assert!(selector.is_selected(3));
+    }
+
+    /// An explicit `cost_of_change` replaces `drain_waste` as the upper-bound
+    #[test]
+    fn explicit_cost_of_change() {
+        let t = tester();
+        let candidates: Vec<WeightedValue> = vec![
+            t.gen_candidate(0, 200_000).into(),
+            t.gen_candidate(1, 200_000).into(),
+        ];
+        let opts = t.gen_opts(0);
+        let fee_from_inputs = opts
+            .target_feerate
+            .fee_of_weight(candidates[0].weight)
+            .ceil() as u64
+            * 2;
+        let fee_from_template = opts
+            .target_feerate
+            .fee_of_weight(opts.base_weight + 2)
+            .ceil() as u64;
+        // overshoots by more than `drain_waste`
+        let opts = CoinSelectorOpt {
+            target_value: Some(
+                400_000 - fee_from_inputs - fee_from_template - opts.drain_waste() as u64 - 1_000,
+            ),
+            ..opts
+        };
+        assert!(coin_select_bnb(10_000, CoinSelector::new(&candidates, &opts)).is_none());
+
+        let opts = CoinSelectorOpt {
+            cost_of_change: Some(opts.drain_waste() as u64 + 1_000),
+            ..opts
+        };
+        let selector = coin_select_bnb(10_000, CoinSelector::new(&candidates, &opts))
+            .expect("should find changeless solution");
+        assert_eq!(selector.selected_count(), 2);
+
+        let opts = CoinSelectorOpt {
+            cost_of_change: Some(0),
+            target_value: Some(400_000 - fee_from_inputs - fee_from_template - 1),
+            ..opts
+        };
+        assert!(coin_select_bnb(10_000, CoinSelector::new(&candidates, &opts)).is_none());
+    }
+
     /// `cost_of_change` acts as the upper-bound in Bnb, we check whether these boundaries are
     /// enforced in code
     #[test]
@@ -398,10 +739,15 @@ mod test {
         let (lowest_opts, highest_opts) = {
             let opts = t.gen_opts(0);
 
-            let fee_from_inputs =
-                (candidates[0].weight as f32 * opts.target_feerate).ceil() as u64 * 2;
-            let fee_from_template =
-                ((opts.base_weight + 2) as f32 * opts.target_feerate).ceil() as u64;
+            let fee_from_inputs = opts
+                .target_feerate
+                .fee_of_weight(candidates[0].weight)
+                .ceil() as u64
+                * 2;
+            let fee_from_template = opts
+                .target_feerate
+                .fee_of_weight(opts.base_weight + 2)
+                .ceil() as u64;
 
             let lowest_opts = CoinSelectorOpt {
                 target_value: Some(
@@ -475,22 +821,29 @@ mod test {
             t.gen_candidate(3, 200_000).into(),
             t.gen_candidate(4, 200_000).into(),
         ];
-        let make_opts = |v: u64| -> CoinSelectorOpt {
-            CoinSelectorOpt {
-                target_feerate: 0.0,
+        // `v` minus the fee of spending `n` candidates (which all have the same weight), so `n`
+        // candidates adding up to `v` are an exact match
+        let make_opts = |v: u64, n: usize| -> CoinSelectorOpt {
+            let opts = CoinSelectorOpt {
+                target_feerate: FeeRate::from_sat_per_wu(1.0),
                 ..t.gen_opts(v)
+            };
+            let indexes = (0..n).collect::<Vec<_>>();
+            CoinSelectorOpt {
+                target_value: Some(v - fee_of(&candidates, &opts, &indexes)),
+                ..opts
             }
         };
 
         let test_cases = vec![
-            (make_opts(100_000), false, 0),
-            (make_opts(200_000), true, 1),
-            (make_opts(300_000), true, 1),
-            (make_opts(500_000), true, 2),
-            (make_opts(1_000_000), true, 4),
-            (make_opts(1_200_000), false, 0),
-            (make_opts(1_300_000), true, 5),
-            (make_opts(1_400_000), false, 0),
+            (make_opts(100_000, 0), false, 0),
+            (make_opts(200_000, 1), true, 1),
+            (make_opts(300_000, 1), true, 1),
+            (make_opts(500_000, 2), true, 2),
+            (make_opts(1_000_000, 4), true, 4),
+            (make_opts(1_200_000, 0), false, 0),
+            (make_opts(1_300_000, 5), true, 5),
+            (make_opts(1_400_000, 0), false, 0),
         ];
 
         for (opts, expect_solution, expect_selected) in test_cases {
@@ -527,9 +880,13 @@ mod test {
             candidates
         };
         let opts = CoinSelectorOpt {
-            target_feerate: 0.0,
+            target_feerate: FeeRate::from_sat_per_wu(1.0),
             ..t.gen_opts(300_000)
         };
+        let opts = CoinSelectorOpt {
+            target_value: Some(300_000 - fee_of(&candidates, &opts, &[0, 1, 2])),
+            ..opts
+        };
 
         let result = evaluate_bnb(CoinSelector::new(&candidates, &opts), 1100);
         assert!(result.is_ok());
@@ -552,6 +909,32 @@ mod test {
         println!("error as expected: {}", result.unwrap_err());
     }
 
+    #[test]
+    fn timed_bnb_stops_at_either_limit() {
+        let t = tester();
+        let candidates = (0..1000_u32)
+            .map(|index| t.gen_candidate(index, 10_000).into())
+            .collect::<Vec<WeightedValue>>();
+        // no exact match exists, so the search would otherwise take forever
+        let opts = t.gen_opts(10_001 * 500);
+
+        let timeout = std::time::Duration::from_millis(50);
+        let start = std::time::Instant::now();
+        let result =
+            coin_select_bnb_timed(timeout, usize::MAX, CoinSelector::new(&candidates, &opts));
+        assert!(result.is_none());
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+
+        let start = std::time::Instant::now();
+        let result = coin_select_bnb_timed(
+            std::time::Duration::from_secs(3600),
+            1_000,
+            CoinSelector::new(&candidates, &opts),
+        );
+        assert!(result.is_none());
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
     /// Solution should have fee >= min_absolute_fee (or no solution at all)
     #[test]
     fn min_absolute_fee() {
@@ -593,6 +976,40 @@ mod test {
         });
     }
 
+    /// Solutions should pay for the transaction they replace, and the `base_fee`
+    #[test]
+    fn replacement_and_base_fee() {
+        let t = tester();
+        let candidates = {
+            let mut candidates = Vec::new();
+            t.gen_weighted_values(&mut candidates, 10, 10_300);
+            t.gen_weighted_values(&mut candidates, 10, 10_700);
+            t.gen_weighted_values(&mut candidates, 10, 11_000);
+            t.gen_weighted_values(&mut candidates, 10, 13_000);
+            candidates
+        };
+        let mut solutions = 0;
+        for fee in (1..=40_u64).map(|fee_factor| fee_factor * 97) {
+            let rbf_opts = t
+                .gen_opts(50_000)
+                .for_rbf(fee, 1_000, FeeRate::from_sat_per_vb(1.0))
+                .unwrap();
+            let base_fee_opts = CoinSelectorOpt {
+                base_fee: fee,
+                ..t.gen_opts(50_000)
+            };
+            for opts in [rbf_opts, base_fee_opts] {
+                if let Some(selector) =
+                    coin_select_bnb(21_000, CoinSelector::new(&candidates, &opts))
+                {
+                    assert!(selector.finish().is_ok());
+                    solutions += 1;
+                }
+            }
+        }
+        assert!(solutions > 0);
+    }
+
     /// For a decreasing feerate (longterm feerate is lower than effective feerate), we should
     /// select less. For increasing feerate (longterm feerate is higher than effective feerate), we
     /// should select more.
@@ -608,14 +1025,14 @@ mod test {
         };
 
         let decreasing_feerate_opts = CoinSelectorOpt {
-            target_feerate: 1.25,
-            long_term_feerate: Some(0.25),
+            target_feerate: FeeRate::from_sat_per_wu(1.25),
+            long_term_feerate: Some(FeeRate::from_sat_per_wu(0.25)),
             ..t.gen_opts(100_000)
         };
 
         let increasing_feerate_opts = CoinSelectorOpt {
-            target_feerate: 0.25,
-            long_term_feerate: Some(1.25),
+            target_feerate: FeeRate::from_sat_per_wu(0.25),
+            long_term_feerate: Some(FeeRate::from_sat_per_wu(1.25)),
             ..t.gen_opts(100_000)
         };
 
@@ -643,9 +1060,5 @@ mod test {
     ///     * We should always have `ExcessStrategy::ToFee`.
     ///     * We should only have `ExcessStrategy::ToRecipient` when `max_extra_target > 0`.
     ///     * We should only have `ExcessStrategy::ToDrain` when `drain_value >= min_drain_value`.
-    /// * Fuzz
-    ///     * Solution feerate should never be lower than target feerate
-    ///     * Solution fee should never be lower than `min_absolute_fee`
-    ///     * Preselected should always remain selected
     fn _todo() {}
 }
@@ -0,0 +1,211 @@
+use super::*;
+
+/// The algorithm which produced the [`Selection`] of [`coin_select`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoinSelectAlgorithm {
+    /// A changeless solution was found with [`coin_select_bnb`].
+    Bnb,
+    /// [`coin_select_bnb`] failed, so [`coin_select_largest_first`] was used.
+    LargestFirst,
+}
+
+impl core::fmt::Display for CoinSelectAlgorithm {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoinSelectAlgorithm::Bnb => core::write!(f, "bnb"),
+            CoinSelectAlgorithm::LargestFirst => core::write!(f, "largest_first"),
+        }
+    }
+}
+
+/// Try to find a changeless solution with [`coin_select_bnb`] (bounded by `max_tries`), otherwise
+/// fall back to [`coin_select_largest_first`].
+///
+/// The returned [`CoinSelectAlgorithm`] tells which algorithm produced the [`Selection`]. BnB is
+/// skipped if the target can not be met (see [`CoinSelector::can_meet_target`]).
+pub fn coin_select(
+    selector: CoinSelector,
+    max_tries: usize,
+) -> Result<(CoinSelectAlgorithm, Selection), CoinSelectError> {
+    if selector.can_meet_target() {
+        if let Some(bnb_selector) = coin_select_bnb(max_tries, selector.clone()) {
+            if let Ok(selection) = bnb_selector.finish() {
+                return Ok((CoinSelectAlgorithm::Bnb, selection));
+            }
+        }
+    }
+
+    let mut selector = selector;
+    coin_select_largest_first(&mut selector)
+        .map(|selection| (CoinSelectAlgorithm::LargestFirst, selection))
+}
+
+/// Select candidates in order of descending effective value until the selection is finished.
+///
+/// This is a deterministic fallback for when [`coin_select_bnb`] cannot find a changeless
+/// solution. Selecting the largest candidates first tends to keep the input count low. Selection
+/// stops as soon as [`CoinSelector::finish`] succeeds, leaving the remaining candidates unselected.
+///
+/// Candidates that are already selected stay selected. If [`CoinSelector::finish`] never succeeds,
+/// the selection is restored to what it was before the call.
+pub fn coin_select_largest_first(
+    selector: &mut CoinSelector,
+) -> Result<Selection, CoinSelectError> {
+    // a stable sort keeps candidates of equal effective value in index order
+    let mut pool = selector.unselected().collect::<Vec<_>>();
+    pool.sort_by_key(|&(index, _)| core::cmp::Reverse(selector.effective_value(index)));
+
+    selector.select_in_order_until_finished(pool.into_iter().map(|(index, _)| index))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        coin_select, coin_select_bnb, coin_select_largest_first, CoinSelectAlgorithm,
+        CoinSelectError, CoinSelector, CoinSelectorOpt, CoinSelectorOptBuilder, ExcessStrategyKind,
+        SelectionConstraint, Vec, WeightedValue,
+    };
+
+    // the template has no weight, so selections of `weightless` candidates pay no fee
+    fn opts(target_value: u64) -> CoinSelectorOpt {
+        CoinSelectorOptBuilder::new(0)
+            .target_value(target_value)
+            .drain(100, 66)
+            .min_drain_value(1000)
+            .build()
+            .unwrap()
+    }
+
+    /// A candidate without weight, so spending it costs no fee.
+    fn weightless(value: u64) -> WeightedValue {
+        WeightedValue {
+            weight: 0,
+            ..WeightedValue::new(value, 0, false)
+        }
+    }
+
+    #[test]
+    fn tries_bnb_then_falls_back() {
+        let candidates = vec![weightless(30_000), weightless(20_000), weightless(45_000)];
+
+        // 30_000 + 20_000 is an exact match
+        let opts = opts(50_000);
+        let (algorithm, selection) =
+            coin_select(CoinSelector::new(&candidates, &opts), 10_000).unwrap();
+        assert_eq!(algorithm, CoinSelectAlgorithm::Bnb);
+        assert_eq!(selection.selected, [0, 1].iter().cloned().collect());
+
+        // no exact match exists
+        let opts = CoinSelectorOpt {
+            target_value: Some(50_500),
+            ..opts
+        };
+        let (algorithm, selection) =
+            coin_select(CoinSelector::new(&candidates, &opts), 10_000).unwrap();
+        assert_eq!(algorithm, CoinSelectAlgorithm::LargestFirst);
+        assert_eq!(selection.selected, [0, 2].iter().cloned().collect());
+
+        let opts = CoinSelectorOpt {
+            target_value: Some(1_000_000),
+            ..opts
+        };
+        assert!(matches!(
+            coin_select(CoinSelector::new(&candidates, &opts), 10_000),
+            Err(CoinSelectError::InsufficientFunds { .. })
+        ));
+    }
+
+    #[test]
+    fn empty_candidates() {
+        let candidates = Vec::new();
+
+        // the template transaction still has to pay fees
+        let opts = CoinSelectorOpt {
+            base_weight: 10,
+            ..opts(0)
+        };
+        let fee = opts.fee_for_weight(opts.base_weight);
+        assert!(fee > 0);
+        let selector = CoinSelector::new(&candidates, &opts);
+        assert_eq!(
+            selector.finish().unwrap_err(),
+            CoinSelectError::InsufficientFunds {
+                selected: 0,
+                missing: fee,
+                constraint: SelectionConstraint::TargetFee,
+            }
+        );
+        assert!(coin_select_bnb(10_000, selector.clone()).is_none());
+        assert_eq!(
+            coin_select(selector, 10_000).unwrap_err(),
+            CoinSelectError::InsufficientFunds {
+                selected: 0,
+                missing: fee,
+                constraint: SelectionConstraint::TargetFee,
+            }
+        );
+
+        // without fees, a zero target is met by selecting nothing
+        let opts = CoinSelectorOpt {
+            base_weight: 0,
+            ..opts
+        };
+        let selector = CoinSelector::new(&candidates, &opts);
+        let selection = selector.finish().unwrap();
+        assert!(selection.is_empty());
+        assert_eq!(selection.excess, 0);
+        assert_eq!(
+            selection.excess_strategies.keys().collect::<Vec<_>>(),
+            vec![&ExcessStrategyKind::ToFee]
+        );
+        assert_eq!(selection.best_strategy().1.fee, 0);
+        let (algorithm, selection) = coin_select(selector, 10_000).unwrap();
+        assert_eq!(algorithm, CoinSelectAlgorithm::Bnb);
+        assert!(selection.is_empty());
+    }
+
+    #[test]
+    fn selects_largest_candidates_first() {
+        let candidates = [1_000_u64, 50_000, 5_000, 20_000, 30_000]
+            .iter()
+            .map(|&value| WeightedValue::new(value, 100, false))
+            .collect::<Vec<_>>();
+        let opts = opts(45_000);
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        let selection = coin_select_largest_first(&mut selector).expect("should succeed");
+
+        // 50_000 alone covers the target and fee, nothing else should be selected
+        assert_eq!(selection.selected, [1].iter().cloned().collect());
+        assert_eq!(selector.selected_count(), 1);
+    }
+
+    #[test]
+    fn largest_first_keeps_preselected() {
+        let candidates = [1_000_u64, 50_000, 5_000]
+            .iter()
+            .map(|&value| WeightedValue::new(value, 100, false))
+            .collect::<Vec<_>>();
+        let opts = opts(50_000);
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select(0);
+        let selection = coin_select_largest_first(&mut selector).expect("should succeed");
+
+        assert_eq!(selection.selected, [0, 1].iter().cloned().collect());
+    }
+
+    #[test]
+    fn largest_first_restores_selection_on_failure() {
+        let candidates = [1_000_u64, 2_000, 3_000]
+            .iter()
+            .map(|&value| WeightedValue::new(value, 100, false))
+            .collect::<Vec<_>>();
+        let opts = opts(10_000);
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select(1);
+        assert!(coin_select_largest_first(&mut selector).is_err());
+        assert_eq!(selector.selected_indexes().collect::<Vec<_>>(), vec![1]);
+    }
+}
@@ -3,6 +3,11 @@ use super::*;
 /// A [`WeightedValue`] represents an input candidate for [`CoinSelector`]. This can either be a
 /// single UTXO, or a group of UTXOs that should be spent together.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(crate = "serde")
+)]
 pub struct WeightedValue {
     /// Total value of the UTXO(s) that this [`WeightedValue`] represents.
     pub value: u64,
@@ -14,6 +19,11 @@ pub struct WeightedValue {
     pub input_count: usize,
     /// Whether this [`WeightedValue`] contains at least one segwit spend.
     pub is_segwit: bool,
+    /// Number of confirmations of the UTXO(s) (the least confirmed one if this represents a group).
+    /// `None` if this is unknown.
+    pub confirmations: Option<u32>,
+    /// Whether this [`WeightedValue`] spends foreign UTXO(s) which we are not expected to sign for.
+    pub is_foreign: bool,
 }
 
 impl WeightedValue {
@@ -28,19 +38,171 @@ impl WeightedValue {
             weight,
             input_count: 1,
             is_segwit,
+            confirmations: None,
+            is_foreign: false,
         }
     }
 
-    /// Effective value of this input candidate: `actual_value - input_weight * feerate (sats/wu)`.
-    pub fn effective_value(&self, effective_feerate: f32) -> i64 {
+    /// Create a new [`WeightedValue`] that represents a single foreign input (i.e. a UTXO of a
+    /// descriptor that we do not own, such as one contributed by a co-signer).
+    ///
+    /// Foreign inputs are treated the same as owned inputs in selection; the value and weight of
+    /// the input must be provided explicitly since we cannot derive them. `satisfaction_weight` is
+    /// the weight of `scriptSigLen + scriptSig + scriptWitnessLen + scriptWitness`.
+    pub fn foreign(value: u64, satisfaction_weight: u32, is_segwit: bool) -> WeightedValue {
+        WeightedValue {
+            is_foreign: true,
+            ..Self::new(value, satisfaction_weight, is_segwit)
+        }
+    }
+
+    /// Create a new [`WeightedValue`] that represents spending `txout`.
+    ///
+    /// `satisfaction_weight` is the weight of `scriptSigLen + scriptSig + scriptWitnessLen +
+    /// scriptWitness`.
+    pub fn from_txout(txout: &TxOut, satisfaction_weight: u32, is_segwit: bool) -> WeightedValue {
+        Self::new(txout.value, satisfaction_weight, is_segwit)
+    }
+
+    /// Create a new [`WeightedValue`] that represents spending `txout` with the given `plan`.
+    #[cfg(feature = "miniscript")]
+    pub fn from_plan<Ak: Clone>(txout: &TxOut, plan: &bdk_tmp_plan::Plan<Ak>) -> WeightedValue {
+        Self::from_txout(
+            txout,
+            plan.expected_weight() as u32,
+            plan.witness_version().is_some(),
+        )
+    }
+
+    /// Create a new [`WeightedValue`] that represents spending an output of `value` sent to the
+    /// script pubkey at `derivation_index` of `keychain` in `txout_index`.
+    ///
+    /// Unlike [`from_plan`], no signing assets are needed: the satisfaction weight is the maximum
+    /// satisfaction weight of the keychain's descriptor.
+    ///
+    /// Errors if `keychain` does not exist in `txout_index` or its descriptor cannot be satisfied.
+    ///
+    /// [`from_plan`]: Self::from_plan
+    #[cfg(feature = "miniscript")]
+    pub fn from_keychain<K: Clone + Ord + Debug>(
+        txout_index: &bdk_chain::keychain::KeychainTxOutIndex<K>,
+        keychain: &K,
+        derivation_index: u32,
+        value: u64,
+    ) -> Result<WeightedValue, FromKeychainError<K>> {
+        let descriptor = txout_index
+            .descriptor_of(keychain)
+            .ok_or_else(|| FromKeychainError::NoSuchKeychain(keychain.clone()))?
+            .at_derivation_index(derivation_index);
+        let satisfaction_weight =
+            descriptor
+                .max_satisfaction_weight()
+                .map_err(|_| FromKeychainError::Unsatisfiable {
+                    keychain: keychain.clone(),
+                    derivation_index,
+                })?;
+        Ok(Self::new(
+            value,
+            satisfaction_weight as u32,
+            descriptor.desc_type().segwit_version().is_some(),
+        ))
+    }
+
+    /// Create a new [`WeightedValue`] that represents spending all `members` together (e.g. UTXOs
+    /// that share an address, as spending only some of them would leak common ownership anyway).
+    ///
+    /// Values, weights and input counts are summed. The group is segwit (or foreign) if any member
+    /// is, and has the confirmations of its least-confirmed member.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `members` is empty.
+    pub fn group(members: &[WeightedValue]) -> WeightedValue {
+        assert!(!members.is_empty(), "group must have at least one member");
+        members
+            .iter()
+            .skip(1)
+            .fold(members[0], |group, member| WeightedValue {
+                value: group.value.saturating_add(member.value),
+                weight: group.weight.saturating_add(member.weight),
+                input_count: group.input_count.saturating_add(member.input_count),
+                is_segwit: group.is_segwit || member.is_segwit,
+                confirmations: match (group.confirmations, member.confirmations) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    _ => None,
+                },
+                is_foreign: group.is_foreign || member.is_foreign,
+            })
+    }
+
+    /// Set the number of confirmations of this [`WeightedValue`].
+    pub fn with_confirmations(self, confirmations: u32) -> WeightedValue {
+        WeightedValue {
+            confirmations: Some(confirmations),
+            ..self
+        }
+    }
+
+    /// Effective value of this input candidate at the target feerate.
+    ///
+    /// This is the same as [`effective_value_at`], where `effective_feerate` is expected to be
+    /// [`CoinSelectorOpt::target_feerate`].
+    ///
+    /// [`effective_value_at`]: Self::effective_value_at
+    pub fn effective_value(&self, effective_feerate: FeeRate) -> i64 {
+        self.effective_value_at(effective_feerate)
+    }
+
+    /// Effective value of this input candidate at `feerate`: `actual_value - input_weight *
+    /// feerate`.
+    ///
+    /// Evaluating this at both [`CoinSelectorOpt::target_feerate`] and
+    /// [`CoinSelectorOpt::long_term_feerate`] shows whether spending the candidate now is cheaper
+    /// than spending it in the future.
+    pub fn effective_value_at(&self, feerate: FeeRate) -> i64 {
         // We prefer undershooting the candidate's effective value (so we over estimate the fee of a
         // candidate). If we overshoot the candidate's effective value, it may be possible to find a
         // solution which does not meet the target feerate.
-        self.value as i64 - (self.weight as f32 * effective_feerate).ceil() as i64
+        saturating_i64(self.value).saturating_sub(feerate.fee_of_weight(self.weight).ceil() as i64)
     }
 }
 
+/// Create input candidates for the unspent outputs in `utxos` (outpoint, value and the height of the
+/// transaction), planning the satisfaction weight of each with [`WeightedValue::from_keychain`].
+///
+/// The confirmations of each candidate are counted from the chain `tip` (unconfirmed outputs have
+/// zero confirmations). Outputs which have not been scanned by `txout_index` are skipped, so the
+/// result keeps the outpoint of every candidate.
+///
+/// Errors if the descriptor of an output's keychain cannot be satisfied.
+#[cfg(feature = "miniscript")]
+pub fn candidates_from_txout_index<K: Clone + Ord + Debug>(
+    txout_index: &bdk_chain::keychain::KeychainTxOutIndex<K>,
+    utxos: impl IntoIterator<Item = (OutPoint, u64, TxHeight)>,
+    tip: BlockId,
+) -> Result<Vec<(OutPoint, WeightedValue)>, FromKeychainError<K>> {
+    utxos
+        .into_iter()
+        .filter_map(|(outpoint, value, height)| {
+            let (keychain, derivation_index) = txout_index.derivation_of_outpoint(&outpoint)?;
+            let confirmations = match height {
+                TxHeight::Confirmed(height) if height <= tip.height => tip.height - height + 1,
+                _ => 0,
+            };
+            let candidate =
+                WeightedValue::from_keychain(txout_index, &keychain, derivation_index, value)
+                    .map(|candidate| candidate.with_confirmations(confirmations));
+            Some(candidate.map(|candidate| (outpoint, candidate)))
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(crate = "serde")
+)]
 pub struct CoinSelectorOpt {
     /// The value we need to select.
     /// If the value is `None` then the selection will be complete if it can pay for the drain
@@ -49,12 +211,15 @@ pub struct CoinSelectorOpt {
     /// Additional leeway for the target value.
     pub max_extra_target: u64, // TODO: Maybe out of scope here?
 
-    /// The feerate we should try and achieve in sats per weight unit.
-    pub target_feerate: f32,
+    /// The feerate we should try and achieve.
+    pub target_feerate: FeeRate,
     /// The feerate
-    pub long_term_feerate: Option<f32>, // TODO: Maybe out of scope? (waste)
+    pub long_term_feerate: Option<FeeRate>, // TODO: Maybe out of scope? (waste)
     /// The minimum absolute fee. I.e. needed for RBF.
     pub min_absolute_fee: u64,
+    /// Fee (in sats) that must be paid on top of the fee for the transaction's own weight, e.g. to
+    /// bump an unconfirmed parent. See [`CoinSelectorOpt::with_cpfp`].
+    pub base_fee: u64,
 
     /// The weight of the template transaction including fixed fields and outputs.
     pub base_weight: u32,
@@ -63,18 +228,56 @@ pub struct CoinSelectorOpt {
     /// Weight of spending the drain (change) output in the future.
     pub spend_drain_weight: u32, // TODO: Maybe out of scope? (waste)
 
-    /// Minimum value allowed for a drain (change) output.
+    /// Minimum value allowed for a drain (change) output. This should be at least the
+    /// [`dust_limit`](Self::dust_limit) of the drain output's script.
     pub min_drain_value: u64,
+    /// If set, also consider splitting the excess across this many drain (change) outputs (each
+    /// with the same template as the single drain output). See [`ExcessStrategyKind::ToMultiDrain`].
+    pub multi_drain: Option<u32>,
+    /// The maximum weight of the transaction (e.g. for standardness).
+    pub max_weight: Option<u32>,
+    /// If set, the transaction replaces a previous one and must pay for it. See
+    /// [`CoinSelectorOpt::for_rbf`].
+    pub replaces: Option<Replacement>,
+    /// The maximum excess (in effective value) that [`coin_select_bnb`] accepts as a changeless
+    /// solution. Defaults to [`drain_waste`] if unset.
+    ///
+    /// [`drain_waste`]: Self::drain_waste
+    pub cost_of_change: Option<u64>,
+}
+
+/// The fee paid by a transaction being replaced (BIP-125).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(crate = "serde")
+)]
+pub struct Replacement {
+    /// Absolute fee paid by the replaced transaction.
+    pub previous_fee: u64,
+    /// The node's incremental relay feerate.
+    pub incremental_relay_feerate: FeeRate,
+}
+
+impl Replacement {
+    /// The minimum absolute fee a replacement transaction of `weight` must pay.
+    ///
+    /// The replacement must pay at least the fee of the replaced transaction (rule 3), plus its own
+    /// bandwidth at the incremental relay feerate (rule 4).
+    pub fn min_fee(&self, weight: u32) -> u64 {
+        self.previous_fee
+            .saturating_add(self.incremental_relay_feerate.fee_of_weight(weight).ceil() as u64)
+    }
 }
 
 impl CoinSelectorOpt {
     fn from_weights(base_weight: u32, drain_weight: u32, spend_drain_weight: u32) -> Self {
-        // 0.25 sats/wu == 1 sat/vb
-        let target_feerate = 0.25_f32;
+        let target_feerate = FeeRate::from_sat_per_vb(1.0);
 
         // set `min_drain_value` to dust limit
         let min_drain_value =
-            3 * ((drain_weight + spend_drain_weight) as f32 * target_feerate) as u64;
+            3 * target_feerate.fee_of_weight(drain_weight + spend_drain_weight) as u64;
 
         Self {
             target_value: None,
@@ -82,13 +285,251 @@ impl CoinSelectorOpt {
             target_feerate,
             long_term_feerate: None,
             min_absolute_fee: 0,
+            base_fee: 0,
             base_weight,
             drain_weight,
             spend_drain_weight,
             min_drain_value,
+            multi_drain: None,
+            max_weight: None,
+            replaces: None,
+            cost_of_change: None,
+        }
+    }
+
+    pub fn fund_outputs(
+        txouts: &[TxOut],
+        drain_output: &TxOut,
+        drain_satisfaction_weight: u32,
+    ) -> Self {
+        CoinSelectorOptBuilder::fund_outputs(txouts, drain_output, drain_satisfaction_weight)
+            .build()
+            .expect("default feerates are valid")
+    }
+
+    /// Like [`fund_outputs`], but errors with [`CoinSelectorOptError::DustOutput`] if any of
+    /// `txouts` is below the [`dust_limit`] of its script.
+    ///
+    /// [`fund_outputs`]: Self::fund_outputs
+    /// [`dust_limit`]: Self::dust_limit
+    pub fn try_fund_outputs(
+        txouts: &[TxOut],
+        drain_output: &TxOut,
+        drain_satisfaction_weight: u32,
+    ) -> Result<Self, CoinSelectorOptError> {
+        CoinSelectorOptBuilder::try_fund_outputs(txouts, drain_output, drain_satisfaction_weight)?
+            .build()
+    }
+
+    /// Set the feerates from raw `f32` values in sats per weight unit.
+    #[deprecated(note = "set `target_feerate` and `long_term_feerate` with `FeeRate` instead")]
+    pub fn with_feerates(self, target_feerate: f32, long_term_feerate: Option<f32>) -> Self {
+        Self {
+            target_feerate: FeeRate::from_sat_per_wu(target_feerate),
+            long_term_feerate: long_term_feerate.map(FeeRate::from_sat_per_wu),
+            ..self
+        }
+    }
+
+    /// Set the `target_value` in place.
+    pub fn set_target_value(&mut self, target_value: Option<u64>) {
+        self.target_value = target_value;
+    }
+
+    /// Set the `target_feerate` in place.
+    ///
+    /// A [`CoinSelector`] caches effective values at the target feerate, so it must be rebound
+    /// with [`CoinSelector::with_opts`] to pick up the change.
+    pub fn set_target_feerate(&mut self, target_feerate: FeeRate) {
+        self.target_feerate = target_feerate;
+    }
+
+    /// Increase [`base_fee`] so that the selection also pays for an unconfirmed parent
+    /// transaction's fee deficit (child-pays-for-parent).
+    ///
+    /// The deficit is the fee the parent (of `parent_weight`) would need at `target_feerate`,
+    /// minus the `parent_fee` it already pays. Nothing is added if the parent already pays enough.
+    /// The `target_value` is left untouched, so the recipient and drain outputs are unaffected.
+    ///
+    /// [`base_fee`]: Self::base_fee
+    pub fn with_cpfp(self, parent_weight: u32, parent_fee: u64) -> Self {
+        let parent_needs = self.fee_for_weight(parent_weight);
+        let deficit = parent_needs.saturating_sub(parent_fee);
+        Self {
+            base_fee: self.base_fee.saturating_add(deficit),
+            ..self
+        }
+    }
+
+    /// Make the selection a BIP-125 replacement of a previous transaction which paid
+    /// `previous_fee` and had `previous_weight`.
+    ///
+    /// The selection will pay at least `previous_fee` plus the weight of the new transaction at the
+    /// `incremental_relay_feerate`. Since the weight depends on the selection, this is enforced in
+    /// [`CoinSelector::finish`]. The `target_feerate` is also raised to at least the feerate of the
+    /// previous transaction.
+    ///
+    /// Errors with [`CoinSelectorOptError::ZeroPreviousWeight`] if `previous_weight` is zero, or
+    /// with the error of [`validate`] if the resulting feerates are invalid.
+    ///
+    /// [`validate`]: Self::validate
+    pub fn for_rbf(
+        self,
+        previous_fee: u64,
+        previous_weight: u32,
+        incremental_relay_feerate: FeeRate,
+    ) -> Result<Self, CoinSelectorOptError> {
+        if previous_weight == 0 {
+            return Err(CoinSelectorOptError::ZeroPreviousWeight);
+        }
+        let previous_feerate =
+            FeeRate::from_sat_per_wu(previous_fee as f32 / previous_weight as f32);
+        let target_feerate = if previous_feerate > self.target_feerate {
+            previous_feerate
+        } else {
+            self.target_feerate
+        };
+        let opts = Self {
+            target_feerate,
+            replaces: Some(Replacement {
+                previous_fee,
+                incremental_relay_feerate,
+            }),
+            ..self
+        };
+        opts.validate()?;
+        Ok(opts)
+    }
+
+    /// The minimum absolute fee a transaction of `weight` must pay.
+    ///
+    /// This is `min_absolute_fee`, or the [`Replacement::min_fee`] if it is larger.
+    pub fn min_fee(&self, weight: u32) -> u64 {
+        match self.replaces {
+            Some(replacement) => self.min_absolute_fee.max(replacement.min_fee(weight)),
+            None => self.min_absolute_fee,
+        }
+    }
+
+    /// The dust limit of an output with `script_pubkey`, as defined by Bitcoin Core's
+    /// `GetDustThreshold` (e.g. 294 sats for P2WPKH, 330 for P2TR and 546 for P2PKH).
+    ///
+    /// Outputs below this value will not be relayed, so this is a better `min_drain_value` than
+    /// the feerate-derived default.
+    pub fn dust_limit(script_pubkey: &Script) -> u64 {
+        script_pubkey.dust_value().to_sat()
+    }
+
+    /// Check that the feerates are sane.
+    ///
+    /// `target_feerate` must be positive and finite, and `long_term_feerate` (if set) must be
+    /// non-negative and finite.
+    pub fn validate(&self) -> Result<(), CoinSelectorOptError> {
+        let target_feerate = self.target_feerate.as_sat_per_wu();
+        if !target_feerate.is_finite() || target_feerate <= 0.0 {
+            return Err(CoinSelectorOptError::InvalidTargetFeerate(
+                self.target_feerate,
+            ));
+        }
+        if let Some(long_term_feerate) = self.long_term_feerate {
+            let rate = long_term_feerate.as_sat_per_wu();
+            if !rate.is_finite() || rate < 0.0 {
+                return Err(CoinSelectorOptError::InvalidLongTermFeerate(
+                    long_term_feerate,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn long_term_feerate(&self) -> FeeRate {
+        self.long_term_feerate.unwrap_or(self.target_feerate)
+    }
+
+    /// The fee (in sats) of `weight` weight units at the `target_feerate`, rounded up.
+    ///
+    /// Rounding up ensures the resulting feerate is never lower than the `target_feerate`.
+    pub fn fee_for_weight(&self, weight: u32) -> u64 {
+        self.target_feerate.fee_of_weight(weight).ceil() as u64
+    }
+
+    /// The fee (in sats) that a transaction of `weight` weight units must pay: the
+    /// [`fee_for_weight`] plus the [`base_fee`].
+    ///
+    /// [`fee_for_weight`]: Self::fee_for_weight
+    /// [`base_fee`]: Self::base_fee
+    pub fn tx_fee(&self, weight: u32) -> u64 {
+        self.fee_for_weight(weight).saturating_add(self.base_fee)
+    }
+
+    /// The waste of adding a drain (change) output: the fee of creating it now (rounded up, as
+    /// charged by [`fee_for_weight`]) plus the fee of spending it in the future at the
+    /// [`long_term_feerate`].
+    ///
+    /// [`fee_for_weight`]: Self::fee_for_weight
+    /// [`long_term_feerate`]: Self::long_term_feerate
+    pub fn drain_waste(&self) -> i64 {
+        let drain_fee = self.fee_for_weight(self.drain_weight) as f64;
+        let spend_drain_fee = self
+            .long_term_feerate()
+            .fee_of_weight(self.spend_drain_weight);
+        (drain_fee + spend_drain_fee) as i64
+    }
+}
+
+/// Builder for [`CoinSelectorOpt`].
+///
+/// Unless overridden with [`min_drain_value`], the built [`CoinSelectorOpt::min_drain_value`] is
+/// derived from the dust rule using the drain weights set with [`drain`].
+///
+/// [`min_drain_value`]: Self::min_drain_value
+/// [`drain`]: Self::drain
+#[derive(Debug, Clone, Copy)]
+pub struct CoinSelectorOptBuilder {
+    base_weight: u32,
+    target_value: Option<u64>,
+    max_extra_target: u64,
+    target_feerate: FeeRate,
+    long_term_feerate: Option<FeeRate>,
+    min_absolute_fee: u64,
+    base_fee: u64,
+    drain_weight: u32,
+    spend_drain_weight: u32,
+    min_drain_value: Option<u64>,
+    multi_drain: Option<u32>,
+    max_weight: Option<u32>,
+    replaces: Option<Replacement>,
+    cost_of_change: Option<u64>,
+}
+
+impl CoinSelectorOptBuilder {
+    /// Create a new builder from the weight of the template transaction (including fixed fields
+    /// and outputs).
+    pub fn new(base_weight: u32) -> Self {
+        let defaults = CoinSelectorOpt::from_weights(base_weight, 0, 0);
+        Self {
+            base_weight,
+            target_value: defaults.target_value,
+            max_extra_target: defaults.max_extra_target,
+            target_feerate: defaults.target_feerate,
+            long_term_feerate: defaults.long_term_feerate,
+            min_absolute_fee: defaults.min_absolute_fee,
+            base_fee: defaults.base_fee,
+            drain_weight: 0,
+            spend_drain_weight: 0,
+            min_drain_value: None,
+            multi_drain: None,
+            max_weight: None,
+            replaces: None,
+            cost_of_change: None,
         }
     }
 
+    /// Create a builder for a transaction which funds `txouts`, with `drain_output` as the drain
+    /// (change) output.
+    ///
+    /// `drain_satisfaction_weight` is the weight of satisfying the `drain_output` when spending it
+    /// in the future.
     pub fn fund_outputs(
         txouts: &[TxOut],
         drain_output: &TxOut,
@@ -106,60 +547,415 @@ impl CoinSelectorOpt {
             tx.output.push(drain_output.clone());
             tx.weight() - base_weight
         };
-        Self {
-            target_value: if txouts.is_empty() {
-                None
-            } else {
-                Some(txouts.iter().map(|txout| txout.value).sum())
-            },
-            ..Self::from_weights(
-                base_weight as u32,
-                drain_weight as u32,
-                TXIN_BASE_WEIGHT + drain_satisfaction_weight,
-            )
+        let builder = Self::new(base_weight as u32).drain(
+            drain_weight as u32,
+            TXIN_BASE_WEIGHT + drain_satisfaction_weight,
+        );
+        if txouts.is_empty() {
+            builder
+        } else {
+            builder.target_value(txouts.iter().map(|txout| txout.value).sum())
         }
     }
 
-    pub fn long_term_feerate(&self) -> f32 {
-        self.long_term_feerate.unwrap_or(self.target_feerate)
+    /// Like [`fund_outputs`], but errors with [`CoinSelectorOptError::DustOutput`] if any of
+    /// `txouts` is below the [dust limit] of its script.
+    ///
+    /// [`fund_outputs`]: Self::fund_outputs
+    /// [dust limit]: CoinSelectorOpt::dust_limit
+    pub fn try_fund_outputs(
+        txouts: &[TxOut],
+        drain_output: &TxOut,
+        drain_satisfaction_weight: u32,
+    ) -> Result<Self, CoinSelectorOptError> {
+        for (index, txout) in txouts.iter().enumerate() {
+            let dust_limit = CoinSelectorOpt::dust_limit(&txout.script_pubkey);
+            if txout.value < dust_limit {
+                return Err(CoinSelectorOptError::DustOutput {
+                    index,
+                    value: txout.value,
+                    dust_limit,
+                });
+            }
+        }
+        Ok(Self::fund_outputs(
+            txouts,
+            drain_output,
+            drain_satisfaction_weight,
+        ))
     }
 
-    pub fn drain_waste(&self) -> i64 {
-        (self.drain_weight as f32 * self.target_feerate
-            + self.spend_drain_weight as f32 * self.long_term_feerate()) as i64
+    /// Set the value we need to select.
+    pub fn target_value(mut self, target_value: u64) -> Self {
+        self.target_value = Some(target_value);
+        self
+    }
+
+    /// Set the additional leeway for the target value.
+    pub fn max_extra_target(mut self, max_extra_target: u64) -> Self {
+        self.max_extra_target = max_extra_target;
+        self
+    }
+
+    /// Set the feerate we should try and achieve.
+    pub fn target_feerate(mut self, target_feerate: FeeRate) -> Self {
+        self.target_feerate = target_feerate;
+        self
+    }
+
+    /// Set the feerate we expect to pay when spending the drain (change) output in the future.
+    pub fn long_term_feerate(mut self, long_term_feerate: FeeRate) -> Self {
+        self.long_term_feerate = Some(long_term_feerate);
+        self
+    }
+
+    /// Set the minimum absolute fee (i.e. needed for RBF).
+    pub fn min_absolute_fee(mut self, min_absolute_fee: u64) -> Self {
+        self.min_absolute_fee = min_absolute_fee;
+        self
+    }
+
+    /// Set the additional weight of including the drain (change) output, and the weight of
+    /// spending it in the future.
+    pub fn drain(mut self, drain_weight: u32, spend_drain_weight: u32) -> Self {
+        self.drain_weight = drain_weight;
+        self.spend_drain_weight = spend_drain_weight;
+        self
+    }
+
+    /// Override the minimum value allowed for a drain (change) output.
+    pub fn min_drain_value(mut self, min_drain_value: u64) -> Self {
+        self.min_drain_value = Some(min_drain_value);
+        self
+    }
+
+    /// Set the minimum value allowed for a drain (change) output to the dust limit of
+    /// `drain_script`. See [`CoinSelectorOpt::dust_limit`].
+    pub fn drain_dust_limit(self, drain_script: &Script) -> Self {
+        self.min_drain_value(CoinSelectorOpt::dust_limit(drain_script))
+    }
+
+    /// Also consider splitting the excess across `count` drain (change) outputs.
+    pub fn multi_drain(mut self, count: u32) -> Self {
+        self.multi_drain = Some(count);
+        self
+    }
+
+    /// Set the maximum weight of the transaction.
+    pub fn max_weight(mut self, max_weight: u32) -> Self {
+        self.max_weight = Some(max_weight);
+        self
+    }
+
+    /// Set the fee that must be paid on top of the fee for the transaction's own weight. See
+    /// [`CoinSelectorOpt::with_cpfp`].
+    pub fn base_fee(mut self, base_fee: u64) -> Self {
+        self.base_fee = base_fee;
+        self
+    }
+
+    /// Set the transaction that is being replaced. See [`CoinSelectorOpt::for_rbf`].
+    pub fn replaces(mut self, replacement: Replacement) -> Self {
+        self.replaces = Some(replacement);
+        self
+    }
+
+    /// Set the maximum excess accepted by [`coin_select_bnb`] as a changeless solution.
+    pub fn cost_of_change(mut self, cost_of_change: u64) -> Self {
+        self.cost_of_change = Some(cost_of_change);
+        self
+    }
+
+    /// Build the [`CoinSelectorOpt`], checking that it is [valid].
+    ///
+    /// [valid]: CoinSelectorOpt::validate
+    pub fn build(self) -> Result<CoinSelectorOpt, CoinSelectorOptError> {
+        let opts = CoinSelectorOpt::from_weights(
+            self.base_weight,
+            self.drain_weight,
+            self.spend_drain_weight,
+        );
+        let opts = CoinSelectorOpt {
+            target_value: self.target_value,
+            max_extra_target: self.max_extra_target,
+            target_feerate: self.target_feerate,
+            long_term_feerate: self.long_term_feerate,
+            min_absolute_fee: self.min_absolute_fee,
+            base_fee: self.base_fee,
+            min_drain_value: self.min_drain_value.unwrap_or(opts.min_drain_value),
+            multi_drain: self.multi_drain,
+            max_weight: self.max_weight,
+            replaces: self.replaces,
+            cost_of_change: self.cost_of_change,
+            ..opts
+        };
+        opts.validate()?;
+        Ok(opts)
+    }
+}
+
+/// A read-only view of a candidate of a [`CoinSelector`]. See [`CoinSelector::candidate_report`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CandidateReport {
+    /// Index of the candidate.
+    pub index: usize,
+    /// Value of the candidate in sats.
+    pub value: u64,
+    /// Weight of the candidate (including `TXIN_BASE_WEIGHT`).
+    pub weight: u32,
+    /// Effective value of the candidate at the target feerate.
+    pub effective_value: i64,
+    /// Whether the candidate is currently selected.
+    pub is_selected: bool,
+}
+
+/// The state a [`CoinSelector`] would have with an additional candidate selected. See
+/// [`CoinSelector::preview_with`].
+#[derive(Debug, Clone)]
+pub struct PreviewResult {
+    /// The [`CoinSelector::current_weight`] with the candidate selected.
+    pub current_weight: u32,
+    /// The [`CoinSelector::selected_effective_value`] with the candidate selected.
+    pub selected_effective_value: i64,
+    /// The result of [`CoinSelector::finish`] with the candidate selected.
+    pub finish: Result<Selection, CoinSelectError>,
+}
+
+/// The components of [`CoinSelector::effective_target`]. See
+/// [`CoinSelector::effective_target_breakdown`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectiveTargetBreakdown {
+    /// The [`CoinSelectorOpt::target_value`] (zero if unset).
+    pub target_value: u64,
+    /// The [`CoinSelectorOpt::base_weight`].
+    pub base_weight: u32,
+    /// Weight of the segwit marker and flag, which is added if any candidate is segwit.
+    pub segwit_weight: u32,
+    /// Additional weight of the input count varint if every candidate is selected.
+    pub input_count_weight: u32,
+    /// Fee of the sum of the weights above at the target feerate.
+    pub fee: u64,
+}
+
+impl EffectiveTargetBreakdown {
+    /// The sum of the weights.
+    pub fn weight(&self) -> u32 {
+        self.base_weight
+            .saturating_add(self.segwit_weight)
+            .saturating_add(self.input_count_weight)
     }
 }
 
 /// [`CoinSelector`] is responsible for selecting and deselecting from a set of canididates.
 #[derive(Debug, Clone)]
 pub struct CoinSelector<'a> {
-    pub opts: &'a CoinSelectorOpt,
-    pub candidates: &'a Vec<WeightedValue>,
+    opts: &'a CoinSelectorOpt,
+    candidates: &'a Vec<WeightedValue>,
     selected: BTreeSet<usize>,
+    /// Selected candidates which can not be deselected.
+    mandatory: BTreeSet<usize>,
+    /// Candidates which can not be selected.
+    banned: BTreeSet<usize>,
+    /// Effective values of the candidates at the target feerate, computed in [`CoinSelector::new`]
+    /// and [`CoinSelector::with_opts`].
+    effective_values: Vec<i64>,
+    /// Whether any candidate is segwit, computed with the effective values.
+    has_segwit: bool,
+    /// Input count of all candidates, computed with the effective values.
+    max_input_count: usize,
 }
 
 impl<'a> CoinSelector<'a> {
+    /// The options the selector is bound to. Use [`with_opts`] to rebind.
+    ///
+    /// [`with_opts`]: Self::with_opts
+    pub fn opts(&self) -> &'a CoinSelectorOpt {
+        self.opts
+    }
+
+    /// All candidates of the selector.
+    pub fn candidates(&self) -> &'a Vec<WeightedValue> {
+        self.candidates
+    }
+
     pub fn candidate(&self, index: usize) -> &WeightedValue {
         &self.candidates[index]
     }
 
+    /// The effective value of the candidate at `index` at the target feerate.
+    pub fn effective_value(&self, index: usize) -> i64 {
+        self.effective_values[index]
+    }
+
+    /// Returns a [`CandidateReport`] for every candidate, in index order.
+    ///
+    /// This is useful for understanding why an algorithm skipped some candidates (e.g.
+    /// [`coin_select_bnb`] ignores candidates with a non-positive effective value).
+    pub fn candidate_report(&self) -> impl Iterator<Item = CandidateReport> + '_ {
+        self.candidates
+            .iter()
+            .enumerate()
+            .map(move |(index, candidate)| CandidateReport {
+                index,
+                value: candidate.value,
+                weight: candidate.weight,
+                effective_value: self.effective_values[index],
+                is_selected: self.is_selected(index),
+            })
+    }
+
+    /// The effective value of the candidate at `index` at the long term feerate.
+    pub fn long_term_effective_value(&self, index: usize) -> i64 {
+        self.candidates[index].effective_value_at(self.opts.long_term_feerate())
+    }
+
+    /// Create a selector over `candidates` with `opts`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `opts` are invalid (see [`CoinSelectorOpt::validate`]). Use [`try_new`] to handle
+    /// this case.
+    ///
+    /// [`try_new`]: Self::try_new
     pub fn new(candidates: &'a Vec<WeightedValue>, opts: &'a CoinSelectorOpt) -> Self {
-        Self {
+        Self::try_new(candidates, opts).expect("invalid coin selector options")
+    }
+
+    /// Create a selector over `candidates` with `opts`, or return an error if `opts` are invalid
+    /// (see [`CoinSelectorOpt::validate`]).
+    pub fn try_new(
+        candidates: &'a Vec<WeightedValue>,
+        opts: &'a CoinSelectorOpt,
+    ) -> Result<Self, CoinSelectorOptError> {
+        opts.validate()?;
+        let mut selector = Self {
             candidates,
             selected: Default::default(),
+            mandatory: Default::default(),
+            banned: Default::default(),
+            effective_values: Vec::new(),
+            has_segwit: false,
+            max_input_count: 0,
             opts,
-        }
+        };
+        selector.refresh_cache();
+        Ok(selector)
+    }
+
+    /// Recompute the values cached from `candidates` and `opts`.
+    fn refresh_cache(&mut self) {
+        let target_feerate = self.opts.target_feerate;
+        self.effective_values = self
+            .candidates
+            .iter()
+            .map(|c| c.effective_value(target_feerate))
+            .collect();
+        self.has_segwit = self.candidates.iter().any(|c| c.is_segwit);
+        self.max_input_count = self
+            .candidates
+            .iter()
+            .fold(0_usize, |count, c| count.saturating_add(c.input_count));
+    }
+
+    /// Rebind the selector to `opts`, keeping the current selection (including mandatory and banned
+    /// candidates).
+    ///
+    /// This allows re-evaluating a selection as parameters (e.g. the target value or feerate)
+    /// change.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `opts` are invalid (see [`CoinSelectorOpt::validate`]). Use [`try_with_opts`] to
+    /// handle this case.
+    ///
+    /// [`try_with_opts`]: Self::try_with_opts
+    pub fn with_opts(&mut self, opts: &'a CoinSelectorOpt) {
+        self.try_with_opts(opts)
+            .expect("invalid coin selector options")
     }
 
+    /// Rebind the selector to `opts` like [`with_opts`], or return an error (leaving the selector
+    /// unchanged) if `opts` are invalid (see [`CoinSelectorOpt::validate`]).
+    ///
+    /// [`with_opts`]: Self::with_opts
+    pub fn try_with_opts(&mut self, opts: &'a CoinSelectorOpt) -> Result<(), CoinSelectorOptError> {
+        opts.validate()?;
+        self.opts = opts;
+        self.refresh_cache();
+        Ok(())
+    }
+
+    /// Select the candidate at `index`. Returns false if the candidate is already selected or is
+    /// banned.
     pub fn select(&mut self, index: usize) -> bool {
         assert!(index < self.candidates.len());
+        if self.is_banned(index) {
+            return false;
+        }
         self.selected.insert(index)
     }
 
+    /// Preview the state of the selector as if the candidate at `index` were selected, without
+    /// changing the selection.
+    ///
+    /// If the candidate can not be selected (i.e. it is already selected or is banned), the preview
+    /// is of the current selection.
+    pub fn preview_with(&self, index: usize) -> PreviewResult {
+        let mut selector = self.clone();
+        selector.select(index);
+        PreviewResult {
+            current_weight: selector.current_weight(),
+            selected_effective_value: selector.selected_effective_value(),
+            finish: selector.finish(),
+        }
+    }
+
+    /// Select the candidate at `index` and mark it as mandatory.
+    ///
+    /// Mandatory candidates always count towards the selection and can not be deselected. Selection
+    /// algorithms (such as [`coin_select_bnb`]) will never search over them.
+    ///
+    /// Returns false (without marking it as mandatory) if the candidate is banned.
+    pub fn select_mandatory(&mut self, index: usize) -> bool {
+        if self.is_banned(index) {
+            return false;
+        }
+        self.mandatory.insert(index);
+        self.select(index)
+    }
+
+    /// Deselect the candidate at `index`. Returns false if the candidate is not selected or is
+    /// mandatory.
     pub fn deselect(&mut self, index: usize) -> bool {
+        if self.is_mandatory(index) {
+            return false;
+        }
         self.selected.remove(&index)
     }
 
+    pub fn is_mandatory(&self, index: usize) -> bool {
+        self.mandatory.contains(&index)
+    }
+
+    /// Ban the candidate at `index` so that it can not be selected (i.e. it is frozen).
+    ///
+    /// If the candidate is selected (even if it is mandatory), it is deselected.
+    pub fn ban(&mut self, index: usize) -> bool {
+        assert!(index < self.candidates.len());
+        self.mandatory.remove(&index);
+        self.selected.remove(&index);
+        self.banned.insert(index)
+    }
+
+    /// Lift the ban of the candidate at `index`.
+    pub fn unban(&mut self, index: usize) -> bool {
+        self.banned.remove(&index)
+    }
+
+    pub fn is_banned(&self, index: usize) -> bool {
+        self.banned.contains(&index)
+    }
+
     pub fn is_selected(&self, index: usize) -> bool {
         self.selected.contains(&index)
     }
@@ -170,32 +966,30 @@ impl<'a> CoinSelector<'a> {
 
     /// Weight sum of all selected inputs.
     pub fn selected_weight(&self) -> u32 {
-        self.selected
-            .iter()
-            .map(|&index| self.candidates[index].weight)
-            .sum()
+        self.selected.iter().fold(0_u32, |weight, &index| {
+            weight.saturating_add(self.candidates[index].weight)
+        })
     }
 
     /// Effective value sum of all selected inputs.
     pub fn selected_effective_value(&self) -> i64 {
-        self.selected
-            .iter()
-            .map(|&index| self.candidates[index].effective_value(self.opts.target_feerate))
-            .sum()
+        self.selected.iter().fold(0_i64, |sum, &index| {
+            sum.saturating_add(self.effective_values[index])
+        })
     }
 
     /// Absolute value sum of all selected inputs.
     pub fn selected_absolute_value(&self) -> u64 {
-        self.selected
-            .iter()
-            .map(|&index| self.candidates[index].value)
-            .sum()
+        self.selected.iter().fold(0_u64, |sum, &index| {
+            sum.saturating_add(self.candidates[index].value)
+        })
     }
 
     /// Waste sum of all selected inputs.
     pub fn selected_waste(&self) -> i64 {
-        (self.selected_weight() as f32 * (self.opts.target_feerate - self.opts.long_term_feerate()))
-            as i64
+        let feerate_diff = self.opts.target_feerate.as_sat_per_wu() as f64
+            - self.opts.long_term_feerate().as_sat_per_wu() as f64;
+        (self.selected_weight() as f64 * feerate_diff) as i64
     }
 
     /// Current weight of template tx + selected inputs.
@@ -209,36 +1003,103 @@ impl<'a> CoinSelector<'a> {
             let input_count = self.selected().map(|(_, wv)| wv.input_count).sum::<usize>();
             (varint_size(input_count) - 1) * 4
         };
-        self.opts.base_weight
-            + self.selected_weight()
-            + witness_header_extra_weight
-            + vin_count_varint_extra_weight
+        self.opts
+            .base_weight
+            .saturating_add(self.selected_weight())
+            .saturating_add(witness_header_extra_weight)
+            .saturating_add(vin_count_varint_extra_weight)
     }
 
     /// Current excess.
     pub fn current_excess(&self) -> i64 {
-        self.selected_effective_value() - self.effective_target()
+        self.selected_effective_value()
+            .saturating_sub(self.effective_target())
     }
 
-    /// This is the effective target value.
-    pub fn effective_target(&self) -> i64 {
-        let (has_segwit, max_input_count) = self
-            .candidates
-            .iter()
-            .fold((false, 0_usize), |(is_segwit, input_count), c| {
-                (is_segwit || c.is_segwit, input_count + c.input_count)
-            });
-
-        let effective_base_weight = self.opts.base_weight
-            + if has_segwit { 2_u32 } else { 0_u32 }
-            + (varint_size(max_input_count) - 1) * 4;
-
-        self.opts.target_value.unwrap_or(0) as i64
-            + (effective_base_weight as f32 * self.opts.target_feerate).ceil() as i64
+    /// The highest effective value a selection can reach: the effective value of the current
+    /// selection plus that of every unselected candidate with a positive effective value.
+    pub fn max_reachable_effective_value(&self) -> i64 {
+        self.unselected_indexes()
+            .map(|index| self.effective_values[index])
+            .filter(|&value| value > 0)
+            .fold(self.selected_effective_value(), i64::saturating_add)
     }
 
-    pub fn selected_count(&self) -> usize {
-        self.selected.len()
+    /// Whether selecting more candidates can possibly meet the [`effective_target`].
+    ///
+    /// This is a cheap check that can be done before running an expensive algorithm like
+    /// [`coin_select_bnb`].
+    ///
+    /// [`effective_target`]: Self::effective_target
+    pub fn can_meet_target(&self) -> bool {
+        self.max_reachable_effective_value() >= self.effective_target()
+    }
+
+    /// The absolute value the current selection must have for [`finish`] to succeed without a drain
+    /// output: the target value plus the fee of the [`current_weight`] (at least the
+    /// [`CoinSelectorOpt::min_fee`]).
+    ///
+    /// This only increases as candidates are selected, so it is a lower bound of what any larger
+    /// selection needs.
+    ///
+    /// [`finish`]: Self::finish
+    /// [`current_weight`]: Self::current_weight
+    pub fn absolute_target(&self) -> u64 {
+        let weight = self.current_weight();
+        let fee = self.opts.tx_fee(weight).max(self.opts.min_fee(weight));
+        self.opts.target_value.unwrap_or(0).saturating_add(fee)
+    }
+
+    /// This is the effective target value.
+    ///
+    /// See [`effective_target_breakdown`] for its components.
+    ///
+    /// [`effective_target_breakdown`]: Self::effective_target_breakdown
+    pub fn effective_target(&self) -> i64 {
+        let breakdown = self.effective_target_breakdown();
+        saturating_i64(breakdown.target_value).saturating_add(saturating_i64(breakdown.fee))
+    }
+
+    /// Returns the components of the [`effective_target`]: the target value plus the fee of the
+    /// base weight, adjusted for the segwit marker and the input count varint as if every candidate
+    /// is selected.
+    ///
+    /// The candidate properties this depends on are computed once in [`new`], so this is cheap.
+    ///
+    /// [`effective_target`]: Self::effective_target
+    /// [`new`]: Self::new
+    pub fn effective_target_breakdown(&self) -> EffectiveTargetBreakdown {
+        let mut breakdown = EffectiveTargetBreakdown {
+            target_value: self.opts.target_value.unwrap_or(0),
+            base_weight: self.opts.base_weight,
+            segwit_weight: if self.has_segwit { 2 } else { 0 },
+            input_count_weight: (varint_size(self.max_input_count) - 1) * 4,
+            fee: 0,
+        };
+        breakdown.fee = self.opts.tx_fee(breakdown.weight());
+        breakdown
+    }
+
+    /// Returns the indexes of unselected candidates whose [`effective_value`] is below
+    /// `max_effective_ratio` of their absolute value (i.e. a large share of their value is spent on
+    /// fees).
+    ///
+    /// These are expensive to spend, so a fee-aware wallet may want to consolidate them while the
+    /// target feerate is low. For example, a `max_effective_ratio` of `0.9` returns candidates that
+    /// lose more than 10% of their value to fees.
+    ///
+    /// [`effective_value`]: Self::effective_value
+    pub fn consolidation_candidates(&self, max_effective_ratio: f32) -> Vec<usize> {
+        self.unselected_indexes()
+            .filter(|&index| {
+                let value = self.candidates[index].value as f64;
+                (self.effective_values[index] as f64) < value * max_effective_ratio as f64
+            })
+            .collect()
+    }
+
+    pub fn selected_count(&self) -> usize {
+        self.selected.len()
     }
 
     pub fn selected(&self) -> impl Iterator<Item = (usize, &'a WeightedValue)> + '_ {
@@ -251,7 +1112,9 @@ impl<'a> CoinSelector<'a> {
         self.candidates
             .iter()
             .enumerate()
-            .filter(move |(index, _)| !self.selected.contains(index))
+            .filter(move |(index, _)| {
+                !self.selected.contains(index) && !self.banned.contains(index)
+            })
     }
 
     pub fn selected_indexes(&self) -> impl Iterator<Item = usize> + '_ {
@@ -259,99 +1122,322 @@ impl<'a> CoinSelector<'a> {
     }
 
     pub fn unselected_indexes(&self) -> impl Iterator<Item = usize> + '_ {
-        (0..self.candidates.len()).filter(move |index| !self.selected.contains(index))
+        (0..self.candidates.len())
+            .filter(move |index| !self.selected.contains(index) && !self.banned.contains(index))
+    }
+
+    /// Indexes of all candidates, ordered by `key_fn` (lowest key first).
+    ///
+    /// `key_fn` is called with the index and the candidate. The sort is stable, so candidates with
+    /// equal keys stay in index order. For example, sorting by
+    /// `|index, _| core::cmp::Reverse(selector.effective_value(index))` gives the order that
+    /// [`coin_select_bnb`] searches in.
+    pub fn candidates_sorted_by<T: Ord>(
+        &self,
+        mut key_fn: impl FnMut(usize, &WeightedValue) -> T,
+    ) -> Vec<usize> {
+        let mut indexes = (0..self.candidates.len()).collect::<Vec<_>>();
+        indexes.sort_by_key(|&index| key_fn(index, &self.candidates[index]));
+        indexes
+    }
+
+    /// Deselect all candidates (except for mandatory candidates).
+    pub fn deselect_all(&mut self) {
+        self.selected = self.mandatory.clone();
+    }
+
+    /// Deselect all candidates, and clear all mandatory and banned candidates.
+    pub fn reset(&mut self) {
+        self.selected.clear();
+        self.mandatory.clear();
+        self.banned.clear();
     }
 
+    /// Whether all candidates (that are not banned) are selected.
     pub fn all_selected(&self) -> bool {
-        self.selected.len() == self.candidates.len()
+        self.selected.len() + self.banned.len() == self.candidates.len()
     }
 
+    /// Select all candidates that are not banned.
     pub fn select_all(&mut self) {
-        self.selected = (0..self.candidates.len()).collect();
+        self.selected = (0..self.candidates.len())
+            .filter(|index| !self.banned.contains(index))
+            .collect();
     }
 
-    pub fn select_until_finished(&mut self) -> Result<Selection, SelectionError> {
-        let mut selection = self.finish();
+    /// Select unselected candidates (in index order) until [`finish`] succeeds.
+    ///
+    /// If [`finish`] never succeeds, the selection is restored to what it was before the call.
+    ///
+    /// [`finish`]: Self::finish
+    pub fn select_until_finished(&mut self) -> Result<Selection, CoinSelectError> {
+        let unselected = self.unselected_indexes().collect::<Vec<_>>();
+        self.select_in_order_until_finished(unselected)
+    }
 
-        if selection.is_ok() {
-            return selection;
+    /// Select unselected candidates until [`finish`] results in a selection that can afford a drain
+    /// (change) output ([`ExcessStrategyKind::ToDrain`]).
+    ///
+    /// Unlike [`select_until_finished`], this does not stop at a changeless selection. If the
+    /// candidates run out before a drain output can be afforded, an
+    /// [`CoinSelectError::InsufficientFunds`] error with the [`SelectionConstraint::MinDrainValue`]
+    /// constraint (or [`CoinSelectError::MaxWeightExceeded`]) is returned and the selection is
+    /// restored to what it was before the call.
+    ///
+    /// [`finish`]: Self::finish
+    /// [`select_until_finished`]: Self::select_until_finished
+    pub fn select_until_can_drain(&mut self) -> Result<Selection, CoinSelectError> {
+        let initial = self.selected.clone();
+        let unselected = self.unselected_indexes().collect::<Vec<_>>();
+        let result = match self.select_in_order_until(unselected, Selection::can_drain) {
+            Ok(selection) if !selection.can_drain() => {
+                let weight_with_drain =
+                    self.current_weight().saturating_add(self.opts.drain_weight);
+                let max_weight = self.opts.max_weight.unwrap_or(u32::MAX);
+                let selected = self.selected_absolute_value();
+                if weight_with_drain > max_weight {
+                    self.selected = initial;
+                    return Err(CoinSelectError::MaxWeightExceeded {
+                        selected,
+                        excess_weight: weight_with_drain - max_weight,
+                    });
+                }
+                let fee_with_drain = self
+                    .opts
+                    .tx_fee(weight_with_drain)
+                    .max(self.opts.min_fee(weight_with_drain));
+                Err(CoinSelectError::InsufficientFunds {
+                    selected,
+                    missing: self
+                        .opts
+                        .target_value
+                        .unwrap_or(0)
+                        .saturating_add(fee_with_drain)
+                        .saturating_add(self.opts.min_drain_value)
+                        .saturating_sub(selected),
+                    constraint: SelectionConstraint::MinDrainValue,
+                })
+            }
+            selection => selection,
+        };
+        if result.is_err() {
+            self.selected = initial;
         }
+        result
+    }
 
-        let unselected = self.unselected_indexes().collect::<Vec<_>>();
+    /// Select all candidates and send everything (minus the fee) to a single recipient output
+    /// (send-max).
+    ///
+    /// The recipient output must be part of the template transaction (`base_weight`), e.g. with
+    /// [`CoinSelectorOpt::fund_outputs`]. `target_value` and the drain output are ignored. The
+    /// resulting [`Selection`] only has the [`ExcessStrategyKind::ToRecipient`] strategy, whose
+    /// `recipient_value` is the value of the recipient output.
+    ///
+    /// Errors if the recipient value would be below `dust_limit` (with the
+    /// [`SelectionConstraint::TargetValue`] constraint), or the transaction is too heavy
+    /// ([`CoinSelectError::MaxWeightExceeded`]). On error, the selection is restored to what it
+    /// was before the call.
+    pub fn sweep(&mut self, dust_limit: u64) -> Result<Selection, CoinSelectError> {
+        let initial = self.selected.clone();
+        self.select_all();
+        let result = self.finish_sweep(dust_limit);
+        if result.is_err() {
+            self.selected = initial;
+        }
+        result
+    }
 
-        for index in unselected {
-            self.select(index);
-            selection = self.finish();
+    /// The send-max [`Selection`] of the current selection (see [`sweep`]).
+    ///
+    /// [`sweep`]: Self::sweep
+    fn finish_sweep(&self, dust_limit: u64) -> Result<Selection, CoinSelectError> {
+        let weight = self.current_weight();
+        let selected = self.selected_absolute_value();
+
+        let max_weight = self.opts.max_weight.unwrap_or(u32::MAX);
+        if weight > max_weight {
+            return Err(CoinSelectError::MaxWeightExceeded {
+                selected,
+                excess_weight: weight - max_weight,
+            });
+        }
+
+        let fee = self.opts.tx_fee(weight).max(self.opts.min_fee(weight));
+        let required = fee.saturating_add(dust_limit);
+        if selected < required {
+            return Err(CoinSelectError::InsufficientFunds {
+                selected,
+                missing: required - selected,
+                constraint: SelectionConstraint::TargetValue,
+            });
+        }
 
-            if selection.is_ok() {
+        let mut excess_strategies = BTreeMap::new();
+        excess_strategies.insert(
+            ExcessStrategyKind::ToRecipient,
+            ExcessStrategy {
+                recipient_value: Some(selected - fee),
+                drain_value: None,
+                drain_count: 0,
+                recipient_extras: Vec::new(),
+                fee,
+                weight,
+                waste: self.selected_waste(),
+            },
+        );
+        Ok(Selection {
+            selected: self.selected.clone(),
+            excess: 0,
+            dropped_to_fee: 0,
+            excess_strategies,
+        })
+    }
+
+    /// Select candidates of `indexes` in the given order until [`finish`] succeeds.
+    ///
+    /// If [`finish`] never succeeds, the selection is restored to what it was before the call.
+    ///
+    /// [`finish`]: Self::finish
+    pub(crate) fn select_in_order_until_finished(
+        &mut self,
+        indexes: impl IntoIterator<Item = usize>,
+    ) -> Result<Selection, CoinSelectError> {
+        self.select_in_order_until(indexes, |_| true)
+    }
+
+    /// Select candidates of `indexes` in the given order until [`finish`] succeeds with a
+    /// [`Selection`] that satisfies `done`. The last result of [`finish`] is returned.
+    ///
+    /// If the last result is an error, the selection is restored to what it was before the call.
+    ///
+    /// [`finish`]: Self::finish
+    pub(crate) fn select_in_order_until(
+        &mut self,
+        indexes: impl IntoIterator<Item = usize>,
+        done: impl Fn(&Selection) -> bool,
+    ) -> Result<Selection, CoinSelectError> {
+        let initial = self.selected.clone();
+        let mut selection = self.finish();
+
+        for index in indexes {
+            if selection.as_ref().is_ok_and(&done) {
                 break;
             }
+            self.select(index);
+            selection = self.finish();
         }
 
+        if selection.is_err() {
+            self.selected = initial;
+        }
         selection
     }
 
-    pub fn finish(&self) -> Result<Selection, SelectionError> {
+    /// Returns the largest value constraint that the current selection does not satisfy, and how
+    /// much more value (in sats) is needed to satisfy it. Returns `None` if the selected value is
+    /// enough.
+    ///
+    /// This is the check [`finish`] fails with [`CoinSelectError::InsufficientFunds`] on, without
+    /// building the excess strategies. Other constraints (such as [`CoinSelectorOpt::max_weight`])
+    /// are not considered.
+    ///
+    /// [`finish`]: Self::finish
+    pub fn shortfall(&self) -> Option<(SelectionConstraint, u64)> {
+        let weight_without_drain = self.current_weight();
+        let fee_without_drain = self.opts.tx_fee(weight_without_drain);
+        let fee_with_drain = self
+            .opts
+            .tx_fee(weight_without_drain.saturating_add(self.opts.drain_weight));
+
+        let target_value = self.opts.target_value.unwrap_or(0);
+        let selected = self.selected_absolute_value();
+
+        // find the largest unsatisfied constraint (if any)
+        [
+            (
+                SelectionConstraint::TargetValue,
+                target_value.saturating_sub(selected),
+            ),
+            (
+                SelectionConstraint::TargetFee,
+                target_value
+                    .saturating_add(fee_without_drain)
+                    .saturating_sub(selected),
+            ),
+            (
+                SelectionConstraint::MinAbsoluteFee,
+                target_value
+                    .saturating_add(self.opts.min_fee(weight_without_drain))
+                    .saturating_sub(selected),
+            ),
+            (
+                SelectionConstraint::MinDrainValue,
+                // when we have no target value (hence no recipient txouts), we need to ensure the
+                // selected amount can satisfy requirements for a drain output (so we at least have
+                // one txout)
+                if self.opts.target_value.is_none() {
+                    fee_with_drain
+                        .saturating_add(self.opts.min_drain_value)
+                        .saturating_sub(selected)
+                } else {
+                    0
+                },
+            ),
+        ]
+        .iter()
+        .copied()
+        .filter(|&(_, missing)| missing > 0)
+        .max_by_key(|&(_, missing)| missing)
+    }
+
+    /// Finish the selection, returning the [`Selection`] with every valid excess strategy.
+    ///
+    /// An empty selection is treated like any other: it errors with
+    /// [`CoinSelectError::InsufficientFunds`] unless the target (and fee) is zero, in which case the
+    /// [`Selection`] has no inputs and only the [`ExcessStrategyKind::ToFee`] strategy.
+    pub fn finish(&self) -> Result<Selection, CoinSelectError> {
         let weight_without_drain = self.current_weight();
-        let weight_with_drain = weight_without_drain + self.opts.drain_weight;
+        let weight_with_drain = weight_without_drain.saturating_add(self.opts.drain_weight);
 
-        let fee_without_drain =
-            (weight_without_drain as f32 * self.opts.target_feerate).ceil() as u64;
-        let fee_with_drain = (weight_with_drain as f32 * self.opts.target_feerate).ceil() as u64;
+        let fee_without_drain = self.opts.tx_fee(weight_without_drain);
+        let fee_with_drain = self.opts.tx_fee(weight_with_drain);
 
         let inputs_minus_outputs = {
             let target_value = self.opts.target_value.unwrap_or(0);
             let selected = self.selected_absolute_value();
-
-            // find the largest unsatisfied constraint (if any), and return error of that constraint
-            // "selected" should always be greater than or equal to these selected values
-            [
-                (
-                    SelectionConstraint::TargetValue,
-                    target_value.saturating_sub(selected),
-                ),
-                (
-                    SelectionConstraint::TargetFee,
-                    (target_value + fee_without_drain).saturating_sub(selected),
-                ),
-                (
-                    SelectionConstraint::MinAbsoluteFee,
-                    (target_value + self.opts.min_absolute_fee).saturating_sub(selected),
-                ),
-                (
-                    SelectionConstraint::MinDrainValue,
-                    // when we have no target value (hence no recipient txouts), we need to ensure
-                    // the selected amount can satisfy requirements for a drain output (so we at
-                    // least have one txout)
-                    if self.opts.target_value.is_none() {
-                        (fee_with_drain + self.opts.min_drain_value).saturating_sub(selected)
-                    } else {
-                        0
-                    },
-                ),
-            ]
-            .iter()
-            .filter(|&(_, v)| v > &0)
-            .max_by_key(|&(_, v)| v)
-            .map_or(Ok(()), |(constraint, missing)| {
-                Err(SelectionError {
+            if let Some((constraint, missing)) = self.shortfall() {
+                return Err(CoinSelectError::InsufficientFunds {
                     selected,
-                    missing: *missing,
-                    constraint: *constraint,
-                })
-            })?;
+                    missing,
+                    constraint,
+                });
+            }
+            selected - target_value
+        };
 
-            (selected - target_value) as u64
+        // without a target value, we need a drain output to have at least one txout
+        let min_weight = if self.opts.target_value.is_none() {
+            weight_with_drain
+        } else {
+            weight_without_drain
         };
+        let max_weight = self.opts.max_weight.unwrap_or(u32::MAX);
+        if min_weight > max_weight {
+            return Err(CoinSelectError::MaxWeightExceeded {
+                selected: self.selected_absolute_value(),
+                excess_weight: min_weight - max_weight,
+            });
+        }
 
-        let fee_without_drain = fee_without_drain.max(self.opts.min_absolute_fee);
-        let fee_with_drain = fee_with_drain.max(self.opts.min_absolute_fee);
+        let fee_without_drain = fee_without_drain.max(self.opts.min_fee(weight_without_drain));
+        let fee_with_drain = fee_with_drain.max(self.opts.min_fee(weight_with_drain));
 
         let excess_without_drain = inputs_minus_outputs - fee_without_drain;
         let input_waste = self.selected_waste();
 
         // begin preparing excess strategies for final selection
-        let mut excess_strategies = HashMap::new();
+        let mut excess_strategies = BTreeMap::new();
 
         // only allow `ToFee` and `ToRecipient` excess strategies when we have a `target_value`,
         // otherwise we will result in a result with no txouts, or attempt to add value to an output
@@ -363,9 +1449,11 @@ impl<'a> CoinSelector<'a> {
                 ExcessStrategy {
                     recipient_value: self.opts.target_value,
                     drain_value: None,
+                    drain_count: 0,
+                    recipient_extras: Vec::new(),
                     fee: fee_without_drain + excess_without_drain,
                     weight: weight_without_drain,
-                    waste: input_waste + excess_without_drain as i64,
+                    waste: input_waste.saturating_add(saturating_i64(excess_without_drain)),
                 },
             );
 
@@ -381,68 +1469,309 @@ impl<'a> CoinSelector<'a> {
                     ExcessStrategy {
                         recipient_value: self.opts.target_value.map(|v| v + extra_recipient_value),
                         drain_value: None,
+                        drain_count: 0,
+                        recipient_extras: Vec::new(),
                         fee: fee_without_drain + extra_fee,
                         weight: weight_without_drain,
-                        waste: input_waste + extra_fee as i64,
+                        waste: input_waste.saturating_add(saturating_i64(extra_fee)),
                     },
                 );
             }
         }
 
         // with drain
-        if fee_with_drain >= self.opts.min_absolute_fee
-            && inputs_minus_outputs >= fee_with_drain + self.opts.min_drain_value
+        if fee_with_drain >= self.opts.min_fee(weight_with_drain)
+            && inputs_minus_outputs >= fee_with_drain.saturating_add(self.opts.min_drain_value)
+            && weight_with_drain <= max_weight
         {
             excess_strategies.insert(
                 ExcessStrategyKind::ToDrain,
                 ExcessStrategy {
                     recipient_value: self.opts.target_value,
                     drain_value: Some(inputs_minus_outputs.saturating_sub(fee_with_drain)),
+                    drain_count: 1,
+                    recipient_extras: Vec::new(),
                     fee: fee_with_drain,
                     weight: weight_with_drain,
-                    waste: input_waste + self.opts.drain_waste(),
+                    waste: input_waste.saturating_add(self.opts.drain_waste()),
                 },
             );
         }
 
-        debug_assert!(
-            !excess_strategies.is_empty(),
-            "should have at least one excess strategy"
-        );
+        // with multiple drains, each drain output must satisfy `min_drain_value`
+        if let Some(count) = self.opts.multi_drain.filter(|&count| count > 1) {
+            let weight_with_drains =
+                weight_without_drain.saturating_add(self.opts.drain_weight.saturating_mul(count));
+            let fee_with_drains = self
+                .opts
+                .tx_fee(weight_with_drains)
+                .max(self.opts.min_fee(weight_with_drains));
+
+            let min_drains_value = self.opts.min_drain_value.saturating_mul(count as u64);
+            if inputs_minus_outputs >= fee_with_drains.saturating_add(min_drains_value)
+                && weight_with_drains <= max_weight
+            {
+                excess_strategies.insert(
+                    ExcessStrategyKind::ToMultiDrain,
+                    ExcessStrategy {
+                        recipient_value: self.opts.target_value,
+                        drain_value: Some(inputs_minus_outputs - fee_with_drains),
+                        drain_count: count,
+                        recipient_extras: Vec::new(),
+                        fee: fee_with_drains,
+                        weight: weight_with_drains,
+                        waste: input_waste
+                            .saturating_add(self.opts.drain_waste().saturating_mul(count as i64)),
+                    },
+                );
+            }
+        }
 
-        Ok(Selection {
+        if excess_strategies.is_empty() {
+            return Err(CoinSelectError::NoValidStrategy);
+        }
+
+        let mut selection = Selection {
             selected: self.selected.clone(),
             excess: excess_without_drain,
+            dropped_to_fee: 0,
             excess_strategies,
-        })
+        };
+        // if a drain output would be dust, the excess is overpaid as fee
+        if !selection.can_drain() {
+            selection.dropped_to_fee = excess_without_drain;
+        }
+        Ok(selection)
+    }
+
+    /// Like [`finish`], but the [`ExcessStrategyKind::ToRecipient`] excess is shared between
+    /// multiple recipients.
+    ///
+    /// `recipients` is a list of `(index, max_extra)` pairs, where `max_extra` is the most value
+    /// that can be added to the recipient. The total leeway (the sum of all `max_extra`s) is used in
+    /// place of [`CoinSelectorOpt::max_extra_target`] and the resulting `ToRecipient` fee is
+    /// computed from it.
+    ///
+    /// The value added to each recipient (in the order of `recipients`) is returned in the
+    /// [`ExcessStrategy::recipient_extras`] of the `ToRecipient` strategy. The extra value is
+    /// allocated proportionally to each recipient's `max_extra`.
+    ///
+    /// [`finish`]: Self::finish
+    pub fn finish_with_recipients(
+        &self,
+        recipients: &[(usize, u64)],
+    ) -> Result<Selection, CoinSelectError> {
+        let max_extra_target = recipients
+            .iter()
+            .fold(0_u64, |acc, &(_, max_extra)| acc.saturating_add(max_extra));
+        let opts = CoinSelectorOpt {
+            max_extra_target,
+            ..*self.opts
+        };
+        let selector = CoinSelector {
+            opts: &opts,
+            candidates: self.candidates,
+            selected: self.selected.clone(),
+            mandatory: self.mandatory.clone(),
+            banned: self.banned.clone(),
+            effective_values: self.effective_values.clone(),
+            has_segwit: self.has_segwit,
+            max_input_count: self.max_input_count,
+        };
+        let mut selection = selector.finish()?;
+
+        if let Some(strategy) = selection
+            .excess_strategies
+            .get_mut(&ExcessStrategyKind::ToRecipient)
+        {
+            let extra = strategy
+                .recipient_value
+                .zip(opts.target_value)
+                .map_or(0, |(recipient_value, target_value)| {
+                    recipient_value - target_value
+                });
+            strategy.recipient_extras = allocate_extra(extra, recipients);
+        }
+
+        Ok(selection)
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct SelectionError {
-    selected: u64,
-    missing: u64,
-    constraint: SelectionConstraint,
+/// Allocates `extra` across `recipients` proportionally to their `max_extra`, without exceeding
+/// any `max_extra`. Any remainder from rounding down is given out one unit at a time, in order.
+fn allocate_extra(extra: u64, recipients: &[(usize, u64)]) -> Vec<(usize, u64)> {
+    let total = recipients
+        .iter()
+        .map(|&(_, max_extra)| max_extra as u128)
+        .sum::<u128>();
+    if total == 0 {
+        return recipients.iter().map(|&(index, _)| (index, 0)).collect();
+    }
+    let extra = core::cmp::min(extra as u128, total);
+
+    let mut allocated = recipients
+        .iter()
+        .map(|&(index, max_extra)| (index, (extra * max_extra as u128 / total) as u64))
+        .collect::<Vec<_>>();
+
+    let mut remainder = extra as u64 - allocated.iter().map(|&(_, v)| v).sum::<u64>();
+    for ((_, value), &(_, max_extra)) in allocated.iter_mut().zip(recipients) {
+        if remainder == 0 {
+            break;
+        }
+        if *value < max_extra {
+            *value += 1;
+            remainder -= 1;
+        }
+    }
+
+    allocated
+}
+
+/// Error when building an invalid [`CoinSelectorOpt`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CoinSelectorOptError {
+    /// The target feerate is not positive and finite.
+    InvalidTargetFeerate(FeeRate),
+    /// The long term feerate is negative or not finite.
+    InvalidLongTermFeerate(FeeRate),
+    /// A recipient output is below the dust limit of its script.
+    DustOutput {
+        /// Index of the output in the funded outputs.
+        index: usize,
+        /// Value of the output.
+        value: u64,
+        /// Dust limit of the output's script.
+        dust_limit: u64,
+    },
+    /// The transaction being replaced has a weight of zero.
+    ZeroPreviousWeight,
+}
+
+impl core::fmt::Display for CoinSelectorOptError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoinSelectorOptError::InvalidTargetFeerate(feerate) => write!(
+                f,
+                "invalid target feerate of {} sats/wu",
+                feerate.as_sat_per_wu()
+            ),
+            CoinSelectorOptError::InvalidLongTermFeerate(feerate) => write!(
+                f,
+                "invalid long term feerate of {} sats/wu",
+                feerate.as_sat_per_wu()
+            ),
+            CoinSelectorOptError::DustOutput {
+                index,
+                value,
+                dust_limit,
+            } => write!(
+                f,
+                "output {} of {} sats is below the dust limit of {} sats",
+                index, value, dust_limit
+            ),
+            CoinSelectorOptError::ZeroPreviousWeight => {
+                write!(f, "the replaced transaction has a weight of zero")
+            }
+        }
+    }
 }
 
-impl core::fmt::Display for SelectionError {
+#[cfg(feature = "std")]
+impl std::error::Error for CoinSelectorOptError {}
+
+/// Error when coin selection fails.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CoinSelectError {
+    /// The selected value does not satisfy the `constraint`.
+    InsufficientFunds {
+        /// The selected (absolute) value.
+        selected: u64,
+        /// Amount missing to satisfy the constraint.
+        missing: u64,
+        /// The largest unsatisfied constraint.
+        constraint: SelectionConstraint,
+    },
+    /// The transaction would exceed [`CoinSelectorOpt::max_weight`].
+    MaxWeightExceeded {
+        /// The selected (absolute) value.
+        selected: u64,
+        /// Weight over the limit.
+        excess_weight: u32,
+    },
+    /// A feerate is not positive and finite (see [`CoinSelectorOpt::validate`]).
+    InvalidFeeRate(FeeRate),
+    /// No excess strategy is valid for the selection.
+    NoValidStrategy,
+}
+
+impl core::fmt::Display for CoinSelectError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
-            SelectionError {
+            CoinSelectError::InsufficientFunds {
                 selected,
                 missing,
                 constraint,
             } => write!(
                 f,
-                "insufficient coins selected; selected={}, missing={}, unsatisfied_constraint={:?}",
+                "insufficient coins selected; selected={}, missing={}, unsatisfied_constraint={}",
                 selected, missing, constraint
             ),
+            CoinSelectError::MaxWeightExceeded {
+                selected,
+                excess_weight,
+            } => write!(
+                f,
+                "selection exceeds max weight; selected={}, excess_weight={}",
+                selected, excess_weight
+            ),
+            CoinSelectError::InvalidFeeRate(feerate) => {
+                write!(f, "invalid feerate of {} sats/wu", feerate.as_sat_per_wu())
+            }
+            CoinSelectError::NoValidStrategy => write!(f, "no valid excess strategy"),
         }
     }
 }
 
 #[cfg(feature = "std")]
-impl std::error::Error for SelectionError {}
+impl std::error::Error for CoinSelectError {}
+
+/// Error when creating a [`WeightedValue`] with [`WeightedValue::from_keychain`].
+#[cfg(feature = "miniscript")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FromKeychainError<K> {
+    /// The keychain does not exist in the index.
+    NoSuchKeychain(K),
+    /// The descriptor of the keychain cannot be satisfied, so the satisfaction weight is unknown.
+    Unsatisfiable {
+        /// The keychain of the descriptor.
+        keychain: K,
+        /// The derivation index of the script pubkey.
+        derivation_index: u32,
+    },
+}
+
+#[cfg(feature = "miniscript")]
+impl<K: Debug> core::fmt::Display for FromKeychainError<K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FromKeychainError::NoSuchKeychain(keychain) => {
+                write!(f, "no such keychain {:?} exists", keychain)
+            }
+            FromKeychainError::Unsatisfiable {
+                keychain,
+                derivation_index,
+            } => write!(
+                f,
+                "descriptor of keychain {:?} at index {} cannot be satisfied",
+                keychain, derivation_index
+            ),
+        }
+    }
+}
+
+#[cfg(all(feature = "miniscript", feature = "std"))]
+impl<K: Debug> std::error::Error for FromKeychainError<K> {}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SelectionConstraint {
@@ -468,23 +1797,56 @@ impl core::fmt::Display for SelectionConstraint {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(crate = "serde")
+)]
 pub struct Selection {
     pub selected: BTreeSet<usize>,
     pub excess: u64,
-    pub excess_strategies: HashMap<ExcessStrategyKind, ExcessStrategy>,
+    /// The excess that is overpaid as fee (with [`ExcessStrategyKind::ToFee`]) because a drain
+    /// (change) output can not be afforded (e.g. it would be dust). This is zero if a drain output
+    /// is possible.
+    pub dropped_to_fee: u64,
+    pub excess_strategies: BTreeMap<ExcessStrategyKind, ExcessStrategy>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, core::hash::Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(crate = "serde")
+)]
 pub enum ExcessStrategyKind {
     ToFee,
     ToRecipient,
     ToDrain,
+    /// Excess is split across [`CoinSelectorOpt::multi_drain`] drain outputs.
+    ///
+    /// The waste of this strategy includes the [`drain_waste`] of every drain output, as each one
+    /// needs to be created now and spent in the future.
+    ///
+    /// [`drain_waste`]: CoinSelectorOpt::drain_waste
+    ToMultiDrain,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(crate = "serde")
+)]
 pub struct ExcessStrategy {
     pub recipient_value: Option<u64>,
+    /// Total value of all drain outputs.
     pub drain_value: Option<u64>,
+    /// Number of drain outputs.
+    pub drain_count: u32,
+    /// The value added to each recipient (as `(index, extra)` pairs) when the excess is shared
+    /// between multiple recipients with [`CoinSelector::finish_with_recipients`]. This is empty
+    /// otherwise.
+    pub recipient_extras: Vec<(usize, u64)>,
     pub fee: u64,
     pub weight: u32,
     pub waste: i64,
@@ -498,13 +1860,110 @@ impl Selection {
         self.selected.iter().map(move |i| &candidates[*i])
     }
 
+    /// Returns the indices of the selected candidates in ascending order.
+    ///
+    /// This is the same order as [`apply_selection`], so it can be used to pick items out of any
+    /// collection that is parallel to the candidates (e.g. outpoints).
+    ///
+    /// [`apply_selection`]: Self::apply_selection
+    pub fn selected_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.selected.iter().copied()
+    }
+
+    /// Returns the number of selected candidates.
+    pub fn len(&self) -> usize {
+        self.selected.len()
+    }
+
+    /// Returns whether no candidates are selected.
+    pub fn is_empty(&self) -> bool {
+        self.selected.is_empty()
+    }
+
     /// Returns the [`ExcessStrategy`] that results in the least waste.
+    ///
+    /// Ties on waste are broken by the lowest fee, then by the order of [`ExcessStrategyKind`]
+    /// variants, so the result does not depend on iteration order.
     pub fn best_strategy(&self) -> (&ExcessStrategyKind, &ExcessStrategy) {
         self.excess_strategies
             .iter()
-            .min_by_key(|&(_, a)| a.waste)
+            .min_by_key(|&(kind, a)| (a.waste, a.fee, *kind))
             .expect("selection has no excess strategy")
     }
+
+    /// Returns all excess strategies in order of preference, i.e. ascending waste with the same
+    /// tie-breaks as [`best_strategy`] (so the first item is the [`best_strategy`]).
+    ///
+    /// [`best_strategy`]: Self::best_strategy
+    pub fn strategies_ranked(&self) -> Vec<(ExcessStrategyKind, &ExcessStrategy)> {
+        let mut ranked = self
+            .excess_strategies
+            .iter()
+            .map(|(kind, strategy)| (*kind, strategy))
+            .collect::<Vec<_>>();
+        ranked.sort_by_key(|&(kind, s)| (s.waste, s.fee, kind));
+        ranked
+    }
+
+    /// Returns the waste of the [`best_strategy`].
+    ///
+    /// [`best_strategy`]: Self::best_strategy
+    pub fn total_waste(&self) -> i64 {
+        self.best_strategy().1.waste
+    }
+
+    /// Returns whether this selection has less [`total_waste`] than `other`.
+    ///
+    /// [`total_waste`]: Self::total_waste
+    pub fn better_than(&self, other: &Selection) -> bool {
+        self.total_waste() < other.total_waste()
+    }
+
+    /// Returns whether the selection can afford a drain (change) output, i.e. whether the
+    /// [`ExcessStrategyKind::ToDrain`] or [`ExcessStrategyKind::ToMultiDrain`] strategy is
+    /// available.
+    pub fn can_drain(&self) -> bool {
+        self.excess_strategies
+            .contains_key(&ExcessStrategyKind::ToDrain)
+            || self
+                .excess_strategies
+                .contains_key(&ExcessStrategyKind::ToMultiDrain)
+    }
+
+    /// Returns the [`ExcessStrategy`] of `kind`, if it is available.
+    pub fn strategy(&self, kind: ExcessStrategyKind) -> Option<&ExcessStrategy> {
+        self.excess_strategies.get(&kind)
+    }
+
+    /// Returns the fee implied by the outputs of the `kind` strategy, given the `total_input` value
+    /// of the selected candidates (e.g. [`CoinSelector::selected_absolute_value`]).
+    ///
+    /// This is `total_input - recipient_value - drain_value` (saturating at zero), and should equal
+    /// the strategy's `fee` if the transaction balances.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `kind` strategy is not available.
+    pub fn realized_fee(&self, kind: ExcessStrategyKind, total_input: u64) -> u64 {
+        let strategy = &self.excess_strategies[&kind];
+        total_input
+            .saturating_sub(strategy.recipient_value.unwrap_or(0))
+            .saturating_sub(strategy.drain_value.unwrap_or(0))
+    }
+
+    /// Returns the lowest and highest [`feerate`] across all excess strategies.
+    ///
+    /// [`feerate`]: ExcessStrategy::feerate
+    pub fn feerate_range(&self) -> (FeeRate, FeeRate) {
+        let mut feerates = self.excess_strategies.values().map(ExcessStrategy::feerate);
+        let first = feerates.next().expect("selection has no excess strategy");
+        feerates.fold((first, first), |(min, max), feerate| {
+            (
+                if feerate < min { feerate } else { min },
+                if feerate > max { feerate } else { max },
+            )
+        })
+    }
 }
 
 impl core::fmt::Display for ExcessStrategyKind {
@@ -513,14 +1972,41 @@ impl core::fmt::Display for ExcessStrategyKind {
             ExcessStrategyKind::ToFee => core::write!(f, "to_fee"),
             ExcessStrategyKind::ToRecipient => core::write!(f, "to_recipient"),
             ExcessStrategyKind::ToDrain => core::write!(f, "to_drain"),
+            ExcessStrategyKind::ToMultiDrain => core::write!(f, "to_multi_drain"),
         }
     }
 }
 
 impl ExcessStrategy {
-    /// Returns feerate in sats/wu.
-    pub fn feerate(&self) -> f32 {
-        self.fee as f32 / self.weight as f32
+    /// Returns the feerate of this strategy.
+    pub fn feerate(&self) -> FeeRate {
+        FeeRate::from_sat_per_wu(self.fee as f32 / self.weight as f32)
+    }
+
+    /// Returns the feerate of this strategy relative to `current_weight`, the weight of the
+    /// transaction without drain outputs (see [`CoinSelector::current_weight`]).
+    ///
+    /// For strategies with drain outputs, this is higher than [`feerate`] as the fee also pays for
+    /// the drain outputs.
+    ///
+    /// [`feerate`]: Self::feerate
+    pub fn effective_feerate(&self, current_weight: u32) -> FeeRate {
+        FeeRate::from_sat_per_wu(self.fee as f32 / current_weight as f32)
+    }
+
+    /// Returns the value of each drain output, splitting [`drain_value`] evenly across
+    /// [`drain_count`] outputs (the first outputs receive the remainder).
+    ///
+    /// [`drain_value`]: Self::drain_value
+    /// [`drain_count`]: Self::drain_count
+    pub fn drain_values(&self) -> impl Iterator<Item = u64> {
+        let total = self.drain_value.unwrap_or(0);
+        let count = self.drain_count as u64;
+        let (each, remainder) = match count {
+            0 => (0, 0),
+            count => (total / count, total % count),
+        };
+        (0..count).map(move |i| if i < remainder { each + 1 } else { each })
     }
 }
 
@@ -528,7 +2014,11 @@ impl ExcessStrategy {
 mod test {
     use crate::{ExcessStrategyKind, SelectionConstraint};
 
-    use super::{CoinSelector, CoinSelectorOpt, WeightedValue};
+    use super::{
+        allocate_extra, BTreeMap, CandidateReport, CoinSelectError, CoinSelector, CoinSelectorOpt,
+        CoinSelectorOptBuilder, CoinSelectorOptError, ExcessStrategy, FeeRate, Selection, Vec,
+        WeightedValue,
+    };
 
     /// Ensure `target_value` is respected. Can't have no disrespect.
     #[test]
@@ -538,22 +2028,30 @@ mod test {
         let candidates = (500..1500_u64)
             .map(|value| WeightedValue {
                 value,
-                weight: 100,
+                weight: 0,
                 input_count: 1,
                 is_segwit: false,
+                confirmations: None,
+                is_foreign: false,
             })
             .collect::<super::Vec<_>>();
 
+        // nothing but the drain has weight, so selections pay no fee
         let opts = CoinSelectorOpt {
             target_value: Some(target_value),
             max_extra_target: 0,
-            target_feerate: 0.00,
+            target_feerate: FeeRate::from_sat_per_vb(1.0),
             long_term_feerate: None,
             min_absolute_fee: 0,
-            base_weight: 10,
+            base_fee: 0,
+            base_weight: 0,
             drain_weight: 10,
             spend_drain_weight: 10,
             min_drain_value: 10,
+            multi_drain: None,
+            max_weight: None,
+            replaces: None,
+            cost_of_change: None,
         };
 
         for (index, v) in candidates.iter().enumerate() {
@@ -562,10 +2060,14 @@ mod test {
 
             let res = selector.finish();
             if v.value < opts.target_value.unwrap_or(0) {
-                let err = res.expect_err("should have failed");
-                assert_eq!(err.selected, v.value);
-                assert_eq!(err.missing, target_value - v.value);
-                assert_eq!(err.constraint, SelectionConstraint::MinAbsoluteFee);
+                assert_eq!(
+                    res.expect_err("should have failed"),
+                    CoinSelectError::InsufficientFunds {
+                        selected: v.value,
+                        missing: target_value - v.value,
+                        constraint: SelectionConstraint::MinAbsoluteFee,
+                    }
+                );
             } else {
                 let sel = res.expect("should have succeeded");
                 assert_eq!(sel.excess, v.value - opts.target_value.unwrap_or(0));
@@ -581,19 +2083,26 @@ mod test {
                 weight: 166,
                 input_count: 1,
                 is_segwit: false,
+                confirmations: None,
+                is_foreign: false,
             })
             .collect::<super::Vec<_>>();
 
         let opts = CoinSelectorOpt {
             target_value: None,
             max_extra_target: 0,
-            target_feerate: 0.25,
+            target_feerate: FeeRate::from_sat_per_vb(1.0),
             long_term_feerate: None,
             min_absolute_fee: 0,
+            base_fee: 0,
             base_weight: 10,
             drain_weight: 100,
             spend_drain_weight: 66,
             min_drain_value: 1000,
+            multi_drain: None,
+            max_weight: None,
+            replaces: None,
+            cost_of_change: None,
         };
 
         let selection = CoinSelector::new(&candidates, &opts)
@@ -609,6 +2118,1250 @@ mod test {
         assert!(strategy.drain_value.is_some());
     }
 
+    #[test]
+    fn opt_builder_derives_min_drain_value() {
+        let opts = CoinSelectorOptBuilder::new(100)
+            .target_value(10_000)
+            .target_feerate(FeeRate::from_sat_per_vb(5.0))
+            .drain(124, 272)
+            .build()
+            .unwrap();
+        assert_eq!(opts.target_value, Some(10_000));
+        assert_eq!(opts.drain_weight, 124);
+        assert_eq!(opts.spend_drain_weight, 272);
+        // dust rule at 1 sat/vb: 3 * (124 + 272) / 4
+        assert_eq!(opts.min_drain_value, 297);
+
+        let opts = CoinSelectorOptBuilder::new(100)
+            .drain(124, 272)
+            .min_drain_value(1_000)
+            .build()
+            .unwrap();
+        assert_eq!(opts.target_value, None);
+        assert_eq!(opts.min_drain_value, 1_000);
+    }
+
+    #[test]
+    fn mandatory_cannot_be_deselected() {
+        let candidates = (0..3)
+            .map(|_| WeightedValue::new(1_000, 100, false))
+            .collect::<super::Vec<_>>();
+        let opts = CoinSelectorOptBuilder::new(10).build().unwrap();
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        assert!(selector.select_mandatory(0));
+        assert!(selector.select(1));
+
+        assert!(!selector.deselect(0));
+        assert!(selector.deselect(1));
+        assert!(selector.is_mandatory(0));
+        assert_eq!(selector.selected_indexes().collect::<super::Vec<_>>(), [0]);
+    }
+
+    #[test]
+    fn banned_are_never_selected() {
+        let candidates = (0..3)
+            .map(|_| WeightedValue::new(1_000, 100, false))
+            .collect::<super::Vec<_>>();
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(1_500)
+            .build()
+            .unwrap();
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        assert!(selector.select(0));
+        assert!(selector.ban(0));
+        assert!(!selector.is_selected(0));
+        assert!(!selector.select(0));
+        assert_eq!(
+            selector.unselected_indexes().collect::<super::Vec<_>>(),
+            [1, 2]
+        );
+
+        let selection = selector.select_until_finished().expect("should succeed");
+        assert_eq!(selection.selected, [1, 2].iter().cloned().collect());
+        assert!(selector.all_selected());
+
+        assert!(selector.unban(0));
+        assert!(!selector.all_selected());
+    }
+
+    #[test]
+    fn banned_can_not_be_mandatory() {
+        let candidates = (0..3)
+            .map(|_| WeightedValue::new(1_000, 100, false))
+            .collect::<super::Vec<_>>();
+        let opts = CoinSelectorOptBuilder::new(10).build().unwrap();
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        assert!(selector.ban(0));
+        assert!(!selector.select_mandatory(0));
+        assert!(!selector.is_mandatory(0));
+        selector.deselect_all();
+        assert!(!selector.is_selected(0));
+
+        // banning a mandatory candidate clears the mandatory flag
+        assert!(selector.select_mandatory(1));
+        assert!(selector.ban(1));
+        assert!(!selector.is_mandatory(1));
+        selector.deselect_all();
+        assert!(selector.is_empty());
+    }
+
+    #[test]
+    fn deselect_all_and_reset() {
+        let candidates = (0..3)
+            .map(|_| WeightedValue::new(1_000, 100, false))
+            .collect::<super::Vec<_>>();
+        let opts = CoinSelectorOptBuilder::new(10).build().unwrap();
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select_mandatory(0);
+        selector.ban(1);
+        selector.select_all();
+
+        selector.deselect_all();
+        assert_eq!(selector.selected_indexes().collect::<super::Vec<_>>(), [0]);
+        assert!(selector.is_banned(1));
+
+        selector.reset();
+        assert!(selector.is_empty());
+        assert!(!selector.is_mandatory(0));
+        assert!(!selector.is_banned(1));
+    }
+
+    #[test]
+    fn multi_drain() {
+        let candidates = (0..10)
+            .map(|_| WeightedValue::new(10_000, 100, false))
+            .collect::<super::Vec<_>>();
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(50_000)
+            .drain(100, 66)
+            .min_drain_value(1_000)
+            .multi_drain(3)
+            .build()
+            .unwrap();
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select_all();
+        let selection = selector.finish().expect("should succeed");
+        let single = &selection.excess_strategies[&ExcessStrategyKind::ToDrain];
+        let multi = &selection.excess_strategies[&ExcessStrategyKind::ToMultiDrain];
+
+        assert_eq!(multi.drain_count, 3);
+        assert_eq!(multi.weight, single.weight + 2 * opts.drain_weight);
+        assert_eq!(multi.waste, single.waste + 2 * opts.drain_waste());
+        let drain_values = multi.drain_values().collect::<super::Vec<_>>();
+        assert_eq!(drain_values.len(), 3);
+        assert_eq!(drain_values.iter().sum::<u64>(), multi.drain_value.unwrap());
+        assert!(drain_values.iter().all(|&v| v >= opts.min_drain_value));
+
+        // multiple drain outputs are affordable even without the single drain strategy
+        let mut selection = selection;
+        selection
+            .excess_strategies
+            .remove(&ExcessStrategyKind::ToDrain);
+        assert!(selection.can_drain());
+    }
+
+    #[test]
+    fn max_weight_exceeded() {
+        let candidates = (0..10)
+            .map(|_| WeightedValue::new(10_000, 100, false))
+            .collect::<super::Vec<_>>();
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(25_000)
+            .drain(100, 66)
+            .max_weight(10 + 3 * 260 + 50)
+            .build()
+            .unwrap();
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        (0..3).for_each(|index| assert!(selector.select(index)));
+        let selection = selector.finish().expect("should succeed");
+        // a drain output would exceed the max weight
+        assert!(!selection
+            .excess_strategies
+            .contains_key(&ExcessStrategyKind::ToDrain));
+
+        selector.select(3);
+        let err = selector.finish().expect_err("should exceed max weight");
+        assert!(matches!(
+            err,
+            CoinSelectError::MaxWeightExceeded {
+                excess_weight: 210,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn with_opts_keeps_selection() {
+        let candidates = vec![
+            WeightedValue::new(30_000, 107, true),
+            WeightedValue::new(20_000, 107, true),
+            WeightedValue::new(10_000, 107, true),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(25_000)
+            .build()
+            .unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select(0);
+        selector.ban(2);
+        let low_fee = selector.finish().unwrap().best_strategy().1.fee;
+
+        let mut new_opts = opts;
+        new_opts.set_target_value(Some(45_000));
+        new_opts.set_target_feerate(FeeRate::from_sat_per_vb(10.0));
+        selector.with_opts(&new_opts);
+
+        assert!(selector.is_selected(0));
+        assert!(selector.is_banned(2));
+        assert_eq!(
+            selector.effective_value(0),
+            candidates[0].effective_value(new_opts.target_feerate)
+        );
+        assert!(selector.finish().is_err());
+        selector.select(1);
+        assert!(selector.finish().unwrap().best_strategy().1.fee > low_fee);
+    }
+
+    #[test]
+    fn strategies_ranked_by_waste() {
+        let strategy = |fee: u64, waste: i64| ExcessStrategy {
+            recipient_value: None,
+            drain_value: None,
+            drain_count: 0,
+            recipient_extras: Vec::new(),
+            fee,
+            weight: 100,
+            waste,
+        };
+        let mut excess_strategies = BTreeMap::new();
+        excess_strategies.insert(ExcessStrategyKind::ToFee, strategy(300, 50));
+        excess_strategies.insert(ExcessStrategyKind::ToRecipient, strategy(200, 50));
+        excess_strategies.insert(ExcessStrategyKind::ToDrain, strategy(100, 20));
+        excess_strategies.insert(ExcessStrategyKind::ToMultiDrain, strategy(200, 50));
+        let selection = Selection {
+            selected: Default::default(),
+            excess: 0,
+            dropped_to_fee: 0,
+            excess_strategies,
+        };
+
+        let ranked = selection
+            .strategies_ranked()
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranked,
+            vec![
+                ExcessStrategyKind::ToDrain,
+                ExcessStrategyKind::ToRecipient,
+                ExcessStrategyKind::ToMultiDrain,
+                ExcessStrategyKind::ToFee,
+            ]
+        );
+        assert_eq!(ranked[0], *selection.best_strategy().0);
+    }
+
+    #[test]
+    fn effective_target_breakdown() {
+        // 300 inputs need a 3 byte input count varint
+        let candidates = (0..300)
+            .map(|i| WeightedValue::new(1_000, 107, i == 0))
+            .collect::<Vec<_>>();
+        let opts = CoinSelectorOptBuilder::new(200)
+            .target_value(50_000)
+            .target_feerate(FeeRate::from_sat_per_wu(0.25))
+            .build()
+            .unwrap();
+        let selector = CoinSelector::new(&candidates, &opts);
+
+        let breakdown = selector.effective_target_breakdown();
+        assert_eq!(
+            breakdown,
+            super::EffectiveTargetBreakdown {
+                target_value: 50_000,
+                base_weight: 200,
+                segwit_weight: 2,
+                input_count_weight: 8,
+                fee: 53,
+            }
+        );
+        assert_eq!(selector.effective_target(), 50_000 + 53);
+
+        let candidates = vec![WeightedValue::new(1_000, 107, false)];
+        let selector = CoinSelector::new(&candidates, &opts);
+        let breakdown = selector.effective_target_breakdown();
+        assert_eq!(breakdown.weight(), 200);
+        assert_eq!(selector.effective_target(), 50_000 + 50);
+    }
+
+    #[test]
+    fn preview_with_does_not_select() {
+        let candidates = vec![
+            WeightedValue::new(10_000, 107, true),
+            WeightedValue::new(20_000, 107, true),
+            WeightedValue::new(5_000, 107, true),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(25_000)
+            .build()
+            .unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select(0);
+
+        let preview = selector.preview_with(1);
+        assert!(!selector.is_selected(1));
+        let mut expected = selector.clone();
+        expected.select(1);
+        assert_eq!(preview.current_weight, expected.current_weight());
+        assert_eq!(
+            preview.selected_effective_value,
+            expected.selected_effective_value()
+        );
+        assert_eq!(
+            preview.finish.unwrap().selected,
+            expected.finish().unwrap().selected
+        );
+
+        assert!(selector.preview_with(2).finish.is_err());
+
+        // banned candidates are not selected in the preview
+        selector.ban(1);
+        let preview = selector.preview_with(1);
+        assert_eq!(preview.current_weight, selector.current_weight());
+        assert!(preview.finish.is_err());
+    }
+
+    #[test]
+    fn finish_with_recipients_allocates_extra() {
+        let candidates = vec![WeightedValue::new(30_000, 107, true)];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(25_000)
+            .build()
+            .unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select(0);
+        let selection = selector.finish().unwrap();
+        let excess = selection.excess;
+        let base_fee = selection.excess_strategies[&ExcessStrategyKind::ToFee].fee - excess;
+        assert!(excess > 4_000);
+
+        // enough excess to max out every recipient
+        let recipients = [(0, 1_000), (1, 3_000)];
+        let selection = selector.finish_with_recipients(&recipients).unwrap();
+        let strategy = &selection.excess_strategies[&ExcessStrategyKind::ToRecipient];
+        assert_eq!(strategy.recipient_extras, vec![(0, 1_000), (1, 3_000)]);
+        assert_eq!(strategy.recipient_value, Some(29_000));
+        assert_eq!(strategy.fee, base_fee + excess - 4_000);
+        // other strategies do not add to the recipients
+        assert!(selection.excess_strategies[&ExcessStrategyKind::ToFee]
+            .recipient_extras
+            .is_empty());
+
+        // excess is shared proportionally to each recipient's leeway
+        let recipients = [(0, excess), (1, excess * 3)];
+        let selection = selector.finish_with_recipients(&recipients).unwrap();
+        let strategy = &selection.excess_strategies[&ExcessStrategyKind::ToRecipient];
+        let added = &strategy.recipient_extras;
+        assert_eq!(added.iter().map(|&(_, v)| v).sum::<u64>(), excess);
+        assert!(added[0].1 * 3 <= added[1].1 + 3 && added[1].1 <= added[0].1 * 3 + 3);
+        assert_eq!(strategy.fee, base_fee);
+
+        // no leeway means no `ToRecipient` strategy
+        let selection = selector.finish_with_recipients(&[(0, 0)]).unwrap();
+        assert!(!selection
+            .excess_strategies
+            .contains_key(&ExcessStrategyKind::ToRecipient));
+    }
+
+    #[test]
+    fn allocate_extra_rounding() {
+        assert_eq!(
+            allocate_extra(10, &[(0, 1), (1, 1), (2, 1)]),
+            vec![(0, 1), (1, 1), (2, 1)]
+        );
+        assert_eq!(
+            allocate_extra(2, &[(0, 1), (1, 1), (2, 1)]),
+            vec![(0, 1), (1, 1), (2, 0)]
+        );
+        assert_eq!(allocate_extra(5, &[(3, 0), (4, 2)]), vec![(3, 0), (4, 2)]);
+        assert_eq!(allocate_extra(7, &[(0, 3), (1, 9)]), vec![(0, 2), (1, 5)]);
+    }
+
+    #[test]
+    fn candidates_sorted_by_key() {
+        let candidates = vec![
+            WeightedValue::new(2_000, 107, true),
+            WeightedValue::new(5_000, 107, true),
+            WeightedValue::new(1_000, 107, true),
+            WeightedValue::new(5_000, 107, true),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10).build().unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select(1);
+
+        // selected candidates are included and ties keep index order
+        assert_eq!(
+            selector.candidates_sorted_by(|index, _| core::cmp::Reverse(
+                selector.effective_value(index)
+            )),
+            vec![1, 3, 0, 2]
+        );
+        assert_eq!(
+            selector.candidates_sorted_by(|_, candidate| candidate.value),
+            vec![2, 0, 1, 3]
+        );
+        assert!(selector.is_selected(1));
+    }
+
+    #[test]
+    fn realized_fee_matches_strategy_fee() {
+        let candidates = vec![
+            WeightedValue::new(30_000, 107, true),
+            WeightedValue::new(20_000, 107, true),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(25_000)
+            .max_extra_target(1_000)
+            .drain(124, 272)
+            .target_feerate(FeeRate::from_sat_per_vb(2.0))
+            .build()
+            .unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select_all();
+        let selection = selector.finish().unwrap();
+        let total_input = selector.selected_absolute_value();
+
+        assert!(selection.excess_strategies.len() > 1);
+        for (kind, strategy) in &selection.excess_strategies {
+            assert_eq!(selection.realized_fee(*kind, total_input), strategy.fee);
+        }
+    }
+
+    #[test]
+    fn shortfall_while_selecting() {
+        let candidates = vec![
+            WeightedValue::new(10_000, 200, true),
+            WeightedValue::new(10_000, 200, true),
+            WeightedValue::new(10_000, 200, true),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(15_000)
+            .build()
+            .unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+
+        let fee = opts.fee_for_weight(selector.current_weight());
+        assert_eq!(
+            selector.shortfall(),
+            Some((SelectionConstraint::TargetFee, 15_000 + fee))
+        );
+
+        selector.select(0);
+        let (constraint, missing) = selector.shortfall().expect("not enough selected");
+        assert_eq!(
+            selector.finish().unwrap_err(),
+            CoinSelectError::InsufficientFunds {
+                selected: 10_000,
+                missing,
+                constraint,
+            }
+        );
+
+        selector.select(1);
+        assert_eq!(selector.shortfall(), None);
+        assert!(selector.finish().is_ok());
+    }
+
+    #[test]
+    fn selector_rejects_invalid_feerates() {
+        let candidates = vec![WeightedValue::new(10_000, 200, true)];
+        let valid = CoinSelectorOptBuilder::new(10)
+            .target_value(1_000)
+            .build()
+            .unwrap();
+
+        for feerate in [-1.0, f32::NAN, f32::INFINITY] {
+            let feerate = FeeRate::from_sat_per_wu(feerate);
+            for opts in [
+                CoinSelectorOpt {
+                    target_feerate: feerate,
+                    ..valid
+                },
+                CoinSelectorOpt {
+                    long_term_feerate: Some(feerate),
+                    ..valid
+                },
+            ] {
+                assert!(CoinSelector::try_new(&candidates, &opts).is_err());
+                let mut selector = CoinSelector::new(&candidates, &valid);
+                assert!(selector.try_with_opts(&opts).is_err());
+                // the selector is still bound to the valid options
+                assert_eq!(selector.opts().target_feerate, valid.target_feerate);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid coin selector options")]
+    fn new_panics_on_invalid_feerate() {
+        let candidates = vec![WeightedValue::new(10_000, 200, true)];
+        let opts = CoinSelectorOpt {
+            target_feerate: FeeRate::from_sat_per_wu(f32::NAN),
+            ..CoinSelectorOptBuilder::new(10).build().unwrap()
+        };
+        CoinSelector::new(&candidates, &opts);
+    }
+
+    #[test]
+    fn opt_builder_rejects_invalid_feerates() {
+        for feerate in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let res = CoinSelectorOptBuilder::new(10)
+                .target_feerate(FeeRate::from_sat_per_wu(feerate))
+                .build();
+            assert!(matches!(
+                res,
+                Err(CoinSelectorOptError::InvalidTargetFeerate(_))
+            ));
+        }
+
+        let res = CoinSelectorOptBuilder::new(10)
+            .long_term_feerate(FeeRate::from_sat_per_wu(f32::NAN))
+            .build();
+        assert!(matches!(
+            res,
+            Err(CoinSelectorOptError::InvalidLongTermFeerate(_))
+        ));
+    }
+
+    #[test]
+    fn cpfp_adds_parent_deficit_to_fee() {
+        let candidates = vec![WeightedValue::new(20_000, 200, false)];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(10_000)
+            .target_feerate(FeeRate::from_sat_per_vb(2.0))
+            .build()
+            .unwrap();
+
+        // parent needs 2 sats/vb * 200 vb = 400 sats but only pays 150
+        let cpfp_opts = opts.with_cpfp(800, 150);
+        assert_eq!(cpfp_opts.base_fee, 250);
+        assert_eq!(cpfp_opts.target_value, Some(10_000));
+        // parent already pays enough
+        assert_eq!(opts.with_cpfp(800, 1_000).base_fee, 0);
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select_all();
+        let selection = selector.finish().unwrap();
+        selector.with_opts(&cpfp_opts);
+        let cpfp_selection = selector.finish().unwrap();
+        let (strategy, cpfp_strategy) = (
+            &selection.excess_strategies[&ExcessStrategyKind::ToDrain],
+            &cpfp_selection.excess_strategies[&ExcessStrategyKind::ToDrain],
+        );
+        assert_eq!(cpfp_strategy.recipient_value, Some(10_000));
+        assert_eq!(cpfp_strategy.fee, strategy.fee + 250);
+        assert_eq!(
+            cpfp_strategy.drain_value,
+            strategy.drain_value.map(|v| v - 250)
+        );
+
+        // a sweep (no target value) stays a sweep
+        let sweep_opts = CoinSelectorOptBuilder::new(10)
+            .drain(100, 66)
+            .build()
+            .unwrap()
+            .with_cpfp(800, 150);
+        assert_eq!(sweep_opts.target_value, None);
+    }
+
+    #[test]
+    fn rbf_pays_for_replaced_tx_and_own_weight() {
+        let candidates = vec![WeightedValue::new(100_000, 200, false)];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(50_000)
+            .build()
+            .unwrap()
+            .for_rbf(1_000, 800, FeeRate::from_sat_per_wu(0.25))
+            .unwrap();
+        // previous feerate is higher than the default target feerate
+        assert_eq!(opts.target_feerate, FeeRate::from_sat_per_wu(1.25));
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select_all();
+        let weight = selector.current_weight();
+        let selection = selector.finish().unwrap();
+        for strategy in selection.excess_strategies.values() {
+            let min_fee = 1_000 + (strategy.weight as f32 * 0.25).ceil() as u64;
+            assert!(strategy.fee >= min_fee);
+        }
+        assert_eq!(
+            selection.excess_strategies[&ExcessStrategyKind::ToDrain].fee,
+            1_000 + ((weight + opts.drain_weight) as f32 * 0.25).ceil() as u64
+        );
+    }
+
+    #[test]
+    fn rbf_rejects_zero_previous_weight() {
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(50_000)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            opts.for_rbf(1_000, 0, FeeRate::from_sat_per_wu(0.25)),
+            Err(CoinSelectorOptError::ZeroPreviousWeight)
+        ));
+    }
+
+    #[test]
+    fn feerate_range_spans_strategies() {
+        let candidates = vec![WeightedValue::new(100_000, 200, false)];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(50_000)
+            .drain(100, 66)
+            .build()
+            .unwrap();
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select_all();
+        let selection = selector.finish().unwrap();
+
+        let to_fee = selection.strategy(ExcessStrategyKind::ToFee).unwrap();
+        let to_drain = selection.strategy(ExcessStrategyKind::ToDrain).unwrap();
+        assert!(selection
+            .strategy(ExcessStrategyKind::ToRecipient)
+            .is_none());
+        assert_eq!(
+            selection.feerate_range(),
+            (to_drain.feerate(), to_fee.feerate())
+        );
+
+        // the drain output is paid for by the fee
+        let current_weight = selector.current_weight();
+        assert!(to_drain.effective_feerate(current_weight) > to_drain.feerate());
+        assert_eq!(to_fee.effective_feerate(current_weight), to_fee.feerate());
+    }
+
+    #[test]
+    fn best_strategy_breaks_ties() {
+        let strategy = |fee, waste| ExcessStrategy {
+            recipient_value: Some(1_000),
+            drain_value: None,
+            drain_count: 0,
+            recipient_extras: Vec::new(),
+            fee,
+            weight: 100,
+            waste,
+        };
+        let selection = |strategies: &[(ExcessStrategyKind, ExcessStrategy)]| Selection {
+            selected: Default::default(),
+            excess: 0,
+            dropped_to_fee: 0,
+            excess_strategies: strategies.iter().cloned().collect(),
+        };
+
+        // equal waste, lower fee wins
+        let s = selection(&[
+            (ExcessStrategyKind::ToFee, strategy(200, 10)),
+            (ExcessStrategyKind::ToDrain, strategy(150, 10)),
+        ]);
+        assert_eq!(*s.best_strategy().0, ExcessStrategyKind::ToDrain);
+
+        let worse = selection(&[(ExcessStrategyKind::ToFee, strategy(200, 11))]);
+        assert_eq!(s.total_waste(), 10);
+        assert!(s.better_than(&worse));
+        assert!(!worse.better_than(&s));
+        assert!(!s.better_than(&s));
+
+        // equal waste and fee, order of kinds wins
+        let s = selection(&[
+            (ExcessStrategyKind::ToDrain, strategy(200, 10)),
+            (ExcessStrategyKind::ToRecipient, strategy(200, 10)),
+        ]);
+        assert_eq!(*s.best_strategy().0, ExcessStrategyKind::ToRecipient);
+    }
+
+    #[test]
+    fn selected_indices_are_ordered() {
+        let candidates = (0..5)
+            .map(|_| WeightedValue::new(10_000, 100, false))
+            .collect::<Vec<_>>();
+        let opts = CoinSelectorOptBuilder::new(10).build().unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select(3);
+        selector.select(1);
+        let selection = selector.finish().unwrap();
+
+        assert_eq!(selection.len(), 2);
+        assert!(!selection.is_empty());
+        assert_eq!(selection.selected_indices().collect::<Vec<_>>(), vec![1, 3]);
+        let outpoints = ["a", "b", "c", "d", "e"];
+        assert_eq!(
+            selection.apply_selection(&outpoints).collect::<Vec<_>>(),
+            vec![&"b", &"d"]
+        );
+    }
+
+    #[test]
+    fn try_fund_outputs_rejects_dust() {
+        use bdk_chain::bitcoin::{hashes::Hash, Script, TxOut, WPubkeyHash};
+
+        let p2wpkh = Script::new_v0_p2wpkh(&WPubkeyHash::from_inner([0; 20]));
+        let txout = |value| TxOut {
+            value,
+            script_pubkey: p2wpkh.clone(),
+        };
+        let drain = txout(0);
+
+        let opts =
+            CoinSelectorOpt::try_fund_outputs(&[txout(10_000), txout(294)], &drain, 107).unwrap();
+        assert_eq!(opts.target_value, Some(10_294));
+
+        let err = CoinSelectorOpt::try_fund_outputs(&[txout(10_000), txout(293)], &drain, 107)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            CoinSelectorOptError::DustOutput {
+                index: 1,
+                value: 293,
+                dust_limit: 294,
+            }
+        );
+        assert_eq!(
+            format!("{}", err),
+            "output 1 of 293 sats is below the dust limit of 294 sats"
+        );
+
+        // `OP_RETURN` outputs have no dust limit
+        let op_return = TxOut {
+            value: 0,
+            script_pubkey: Script::new_op_return(&[]),
+        };
+        assert!(CoinSelectorOptBuilder::try_fund_outputs(&[op_return], &drain, 107).is_ok());
+    }
+
+    #[test]
+    fn dust_limit_depends_on_script_type() {
+        use bdk_chain::bitcoin::{
+            hashes::Hash, util::address::WitnessVersion, PubkeyHash, Script, WPubkeyHash,
+        };
+
+        let p2wpkh = Script::new_v0_p2wpkh(&WPubkeyHash::from_inner([0; 20]));
+        let p2tr = Script::new_witness_program(WitnessVersion::V1, &[0; 32]);
+        let p2pkh = Script::new_p2pkh(&PubkeyHash::from_inner([0; 20]));
+        assert_eq!(CoinSelectorOpt::dust_limit(&p2wpkh), 294);
+        assert_eq!(CoinSelectorOpt::dust_limit(&p2tr), 330);
+        assert_eq!(CoinSelectorOpt::dust_limit(&p2pkh), 546);
+
+        let opts = CoinSelectorOptBuilder::new(10)
+            .drain(124, 272)
+            .drain_dust_limit(&p2tr)
+            .build()
+            .unwrap();
+        assert_eq!(opts.min_drain_value, 330);
+    }
+
+    #[test]
+    fn effective_values_are_cached() {
+        let candidates = vec![
+            WeightedValue::new(10_000, 100, false),
+            WeightedValue::new(50, 400, true),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_feerate(FeeRate::from_sat_per_vb(5.0))
+            .build()
+            .unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        for (index, candidate) in candidates.iter().enumerate() {
+            assert_eq!(
+                selector.effective_value(index),
+                candidate.effective_value(opts.target_feerate)
+            );
+        }
+        selector.select_all();
+        // the weight of each candidate includes `TXIN_BASE_WEIGHT`
+        assert_eq!(selector.selected_effective_value(), 9_675 - 650);
+    }
+
+    #[test]
+    fn max_money_scale_values() {
+        const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+
+        let candidates = vec![
+            WeightedValue::new(MAX_MONEY, 272, true),
+            WeightedValue::new(MAX_MONEY - 1, 272, true),
+            WeightedValue::new(u64::MAX, 272, true),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(MAX_MONEY + 1)
+            .target_feerate(FeeRate::from_sat_per_vb(1_000.0))
+            .drain(124, 272)
+            .build()
+            .unwrap();
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        let fee = (432_f64 * 250.0).ceil() as i64;
+        assert_eq!(selector.effective_value(0), MAX_MONEY as i64 - fee);
+        assert_eq!(selector.effective_value(1), MAX_MONEY as i64 - 1 - fee);
+
+        selector.select(0);
+        selector.select(1);
+        assert_eq!(selector.selected_absolute_value(), 2 * MAX_MONEY - 1);
+        assert_eq!(
+            selector.selected_effective_value(),
+            2 * (MAX_MONEY as i64 - fee) - 1
+        );
+        let selection = selector.finish().unwrap();
+        assert_eq!(
+            selection.excess_strategies[&ExcessStrategyKind::ToDrain].drain_value,
+            Some(
+                MAX_MONEY
+                    - 2
+                    - opts
+                        .target_feerate
+                        .fee_of_weight(selector.current_weight() + opts.drain_weight)
+                        .ceil() as u64
+            )
+        );
+
+        // sums saturate instead of overflowing
+        selector.select(2);
+        assert_eq!(selector.selected_absolute_value(), u64::MAX);
+        assert_eq!(selector.selected_effective_value(), i64::MAX);
+        assert!(selector.finish().is_ok());
+    }
+
+    #[test]
+    fn max_weight_and_fee_saturate() {
+        let candidates = vec![
+            WeightedValue::new(10_000, u32::MAX / 2, true),
+            WeightedValue::new(10_000, u32::MAX / 2, true),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(1_000)
+            .build()
+            .unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select_all();
+        assert_eq!(selector.current_weight(), u32::MAX);
+        assert!(selector.finish().is_err());
+
+        let replacement = super::Replacement {
+            previous_fee: u64::MAX,
+            incremental_relay_feerate: FeeRate::from_sat_per_vb(1.0),
+        };
+        assert_eq!(replacement.min_fee(1_000), u64::MAX);
+    }
+
+    #[test]
+    fn select_until_can_drain() {
+        let candidates = vec![
+            WeightedValue::new(10_000, 100, false),
+            WeightedValue::new(10_000, 100, false),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(9_500)
+            .drain(100, 66)
+            .min_drain_value(1_000)
+            .build()
+            .unwrap();
+
+        // the first candidate is enough for a changeless selection
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        let selection = selector.select_until_finished().unwrap();
+        assert!(!selection.can_drain());
+        assert_eq!(selection.len(), 1);
+        // change would be dust, so the excess is overpaid as fee
+        assert!(selection.dropped_to_fee > 0);
+        assert_eq!(selection.dropped_to_fee, selection.excess);
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        let selection = selector.select_until_can_drain().unwrap();
+        assert!(selection.can_drain());
+        assert_eq!(selection.len(), 2);
+
+        assert_eq!(selection.dropped_to_fee, 0);
+
+        // even all candidates can not afford change
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(19_500)
+            .drain(100, 66)
+            .min_drain_value(1_000)
+            .build()
+            .unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        let err = selector.select_until_can_drain().unwrap_err();
+        assert!(matches!(
+            err,
+            CoinSelectError::InsufficientFunds {
+                constraint: SelectionConstraint::MinDrainValue,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn grouped_candidate_has_same_weight_as_members() {
+        let members = (0..253)
+            .map(|i| WeightedValue::new(1_000, 107, i % 2 == 0).with_confirmations(10 + i))
+            .collect::<Vec<_>>();
+        let group = WeightedValue::group(&members);
+        assert_eq!(group.value, 253_000);
+        assert_eq!(group.input_count, 253);
+        assert!(group.is_segwit);
+        assert_eq!(group.confirmations, Some(10));
+
+        let opts = CoinSelectorOptBuilder::new(10).build().unwrap();
+        let mut singles = CoinSelector::new(&members, &opts);
+        singles.select_all();
+        let grouped_candidates = vec![group];
+        let mut grouped = CoinSelector::new(&grouped_candidates, &opts);
+        grouped.select_all();
+        assert_eq!(singles.current_weight(), grouped.current_weight());
+        assert_eq!(
+            singles.selected_absolute_value(),
+            grouped.selected_absolute_value()
+        );
+    }
+
+    #[test]
+    fn foreign_candidate_is_selected_like_owned() {
+        let owned = WeightedValue::new(10_000, 107, true);
+        let foreign = WeightedValue::foreign(10_000, 107, true);
+        assert!(!owned.is_foreign);
+        assert!(foreign.is_foreign);
+        assert_eq!(foreign.weight, owned.weight);
+        assert_eq!(foreign.input_count, 1);
+        assert!(WeightedValue::group(&[owned, foreign]).is_foreign);
+
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(5_000)
+            .build()
+            .unwrap();
+        let candidates = vec![owned, foreign];
+        let mut with_owned = CoinSelector::new(&candidates, &opts);
+        with_owned.select(0);
+        let mut with_foreign = CoinSelector::new(&candidates, &opts);
+        with_foreign.select(1);
+        let with_owned = with_owned.finish().unwrap();
+        let with_foreign = with_foreign.finish().unwrap();
+        assert_eq!(with_foreign.selected_indices().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(
+            with_foreign.best_strategy().1.weight,
+            with_owned.best_strategy().1.weight
+        );
+    }
+
+    #[test]
+    fn can_meet_target() {
+        let candidates = vec![
+            WeightedValue::new(10_000, 100, false),
+            WeightedValue::new(10_000, 100, false),
+            // negative effective value
+            WeightedValue::new(10, 100, false),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(19_000)
+            .build()
+            .unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        assert_eq!(
+            selector.max_reachable_effective_value(),
+            selector.effective_value(0) + selector.effective_value(1)
+        );
+        assert!(selector.can_meet_target());
+
+        selector.ban(1);
+        assert!(!selector.can_meet_target());
+    }
+
+    #[test]
+    fn failed_select_until_finished_restores_selection() {
+        let candidates = (0..4)
+            .map(|_| WeightedValue::new(10_000, 100, false))
+            .collect::<Vec<_>>();
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(100_000)
+            .build()
+            .unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select(2);
+
+        assert!(selector.select_until_finished().is_err());
+        assert_eq!(selector.selected_indexes().collect::<Vec<_>>(), vec![2]);
+        assert!(selector.select_until_can_drain().is_err());
+        assert_eq!(selector.selected_indexes().collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn sweep_sends_everything_minus_fee() {
+        let candidates = vec![
+            WeightedValue::new(10_000, 107, true),
+            WeightedValue::new(20_000, 107, true),
+            WeightedValue::new(500, 107, true),
+        ];
+        let opts = CoinSelectorOptBuilder::new(200)
+            .target_feerate(FeeRate::from_sat_per_vb(2.0))
+            .build()
+            .unwrap();
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.ban(2);
+        let selection = selector.sweep(294).unwrap();
+        let (kind, strategy) = selection.best_strategy();
+        assert_eq!(*kind, ExcessStrategyKind::ToRecipient);
+        let weight = selector.current_weight();
+        assert_eq!(strategy.weight, weight);
+        assert_eq!(strategy.fee, (weight as f64 * 0.5).ceil() as u64);
+        assert_eq!(strategy.recipient_value, Some(30_000 - strategy.fee));
+        assert_eq!(selection.selected_indices().collect::<Vec<_>>(), vec![0, 1]);
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select(0);
+        let err = selector.sweep(30_500).unwrap_err();
+        assert!(matches!(
+            err,
+            CoinSelectError::InsufficientFunds {
+                constraint: SelectionConstraint::TargetValue,
+                ..
+            }
+        ));
+        // the selection is restored on error
+        assert_eq!(selector.selected_indexes().collect::<Vec<_>>(), vec![0]);
+    }
+
+    #[test]
+    fn effective_value_at_long_term_feerate() {
+        let candidates = vec![WeightedValue::new(10_000, 240, true)];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_feerate(FeeRate::from_sat_per_vb(10.0))
+            .long_term_feerate(FeeRate::from_sat_per_vb(2.0))
+            .build()
+            .unwrap();
+        let selector = CoinSelector::new(&candidates, &opts);
+
+        // weight is 400 (including `TXIN_BASE_WEIGHT`)
+        assert_eq!(selector.effective_value(0), 10_000 - 1_000);
+        assert_eq!(selector.long_term_effective_value(0), 10_000 - 200);
+        assert_eq!(
+            candidates[0].effective_value_at(opts.target_feerate),
+            candidates[0].effective_value(opts.target_feerate)
+        );
+    }
+
+    #[test]
+    fn candidate_report() {
+        let candidates = vec![
+            WeightedValue::new(10_000, 40, false),
+            WeightedValue::new(100, 40, false),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_feerate(FeeRate::from_sat_per_vb(4.0))
+            .build()
+            .unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select(0);
+
+        let report = selector.candidate_report().collect::<Vec<_>>();
+        assert_eq!(
+            report,
+            vec![
+                CandidateReport {
+                    index: 0,
+                    value: 10_000,
+                    weight: 200,
+                    effective_value: 9_800,
+                    is_selected: true,
+                },
+                CandidateReport {
+                    index: 1,
+                    value: 100,
+                    weight: 200,
+                    effective_value: -100,
+                    is_selected: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn consolidation_candidates() {
+        let candidates = vec![
+            WeightedValue::new(10_000, 40, false),
+            WeightedValue::new(1_000, 40, false),
+            WeightedValue::new(500, 40, false),
+            WeightedValue::new(40, 40, false),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_feerate(FeeRate::from_sat_per_vb(1.0))
+            .build()
+            .unwrap();
+        let mut selector = CoinSelector::new(&candidates, &opts);
+
+        // every candidate pays 50 sats in fees, so the last one has a negative effective value
+        assert_eq!(selector.consolidation_candidates(0.92), vec![2, 3]);
+        assert_eq!(selector.consolidation_candidates(0.0), vec![3]);
+        assert_eq!(selector.consolidation_candidates(1.0), vec![0, 1, 2, 3]);
+
+        // selected candidates are already spent
+        selector.select(2);
+        assert_eq!(selector.consolidation_candidates(0.92), vec![3]);
+    }
+
+    #[test]
+    fn fee_for_weight_rounds_up() {
+        let opts = CoinSelectorOptBuilder::new(100)
+            .target_feerate(FeeRate::from_sat_per_wu(0.3))
+            .long_term_feerate(FeeRate::from_sat_per_wu(0.25))
+            .drain(124, 272)
+            .build()
+            .unwrap();
+
+        // 124 * 0.3 = 37.2
+        assert_eq!(opts.fee_for_weight(124), 38);
+        assert_eq!(opts.fee_for_weight(0), 0);
+        // the drain creation cost is rounded the same way: 38 + 272 * 0.25
+        assert_eq!(opts.drain_waste(), 38 + 68);
+    }
+
+    #[test]
+    fn drain_waste_matches_drain_fee() {
+        let candidates = (1..=5)
+            .map(|i| WeightedValue::new(20_000 * i, 107 + i as u32, true))
+            .collect::<Vec<_>>();
+        let opts = CoinSelectorOptBuilder::new(113)
+            .target_value(15_000)
+            .target_feerate(FeeRate::from_sat_per_wu(0.37))
+            .long_term_feerate(FeeRate::from_sat_per_wu(0.25))
+            .drain(125, 272)
+            .build()
+            .unwrap();
+        // truncating the sum of both costs (46.25 + 68) would charge one sat less than the fee
+        assert_ne!(
+            opts.target_feerate.fee_of_weight(opts.drain_weight).fract(),
+            0.0
+        );
+        let drain_creation_cost = opts.fee_for_weight(opts.drain_weight) as i64;
+        let future_spend_cost = opts
+            .long_term_feerate()
+            .fee_of_weight(opts.spend_drain_weight)
+            .floor() as i64;
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        for index in 0..candidates.len() {
+            selector.select(index);
+            let selection = selector.finish().unwrap();
+            let to_drain = selection
+                .strategy(ExcessStrategyKind::ToDrain)
+                .expect("can afford drain");
+            assert_eq!(
+                to_drain.waste,
+                selector.selected_waste() + drain_creation_cost + future_spend_cost
+            );
+        }
+    }
+
+    #[cfg(feature = "miniscript")]
+    #[test]
+    fn weighted_value_from_keychain() {
+        use bdk_chain::{
+            bitcoin::secp256k1::Secp256k1,
+            keychain::KeychainTxOutIndex,
+            miniscript::{Descriptor, DescriptorPublicKey},
+        };
+
+        let secp = Secp256k1::signing_only();
+        let (tr_descriptor, _) = Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, "tr([73c5da0a/86'/0'/0']xprv9xgqHN7yz9MwCkxsBPN5qetuNdQSUttZNKw1dcYTV4mkaAFiBVGQziHs3NRSWMkCzvgjEe3n9xV8oYywvM8at9yRqyaZVz6TYYhX98VjsUk/0/*)").unwrap();
+        let (pkh_descriptor, _) = Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, "pkh([73c5da0a/44'/0'/0']xprv9xgqHN7yz9MwCkxsBPN5qetuNdQSUttZNKw1dcYTV4mkaAFiBVGQziHs3NRSWMkCzvgjEe3n9xV8oYywvM8at9yRqyaZVz6TYYhX98VjsUk/0/*)").unwrap();
+        let mut txout_index = KeychainTxOutIndex::<u8>::default();
+        txout_index.add_keychain(0, tr_descriptor);
+        txout_index.add_keychain(1, pkh_descriptor);
+
+        // key-path spend: empty script sig (4 wu), witness count, signature length and signature
+        let tr_candidate = WeightedValue::from_keychain(&txout_index, &0, 5, 10_000).unwrap();
+        assert_eq!(tr_candidate.value, 10_000);
+        assert_eq!(
+            tr_candidate.weight,
+            super::TXIN_BASE_WEIGHT + 4 + 1 + 1 + 65
+        );
+        assert!(tr_candidate.is_segwit);
+
+        let pkh_candidate = WeightedValue::from_keychain(&txout_index, &1, 5, 10_000).unwrap();
+        assert!(pkh_candidate.weight > tr_candidate.weight);
+        assert!(!pkh_candidate.is_segwit);
+
+        assert_eq!(
+            WeightedValue::from_keychain(&txout_index, &2, 5, 10_000).unwrap_err(),
+            super::FromKeychainError::NoSuchKeychain(2)
+        );
+    }
+
+    #[cfg(feature = "miniscript")]
+    #[test]
+    fn candidates_from_scanned_txouts() {
+        use super::candidates_from_txout_index;
+        use bdk_chain::{
+            bitcoin::{secp256k1::Secp256k1, OutPoint, PackedLockTime, Transaction, TxOut},
+            keychain::KeychainTxOutIndex,
+            miniscript::{Descriptor, DescriptorPublicKey},
+            BlockId, TxHeight,
+        };
+
+        let secp = Secp256k1::signing_only();
+        let (descriptor, _) = Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, "tr([73c5da0a/86'/0'/0']xprv9xgqHN7yz9MwCkxsBPN5qetuNdQSUttZNKw1dcYTV4mkaAFiBVGQziHs3NRSWMkCzvgjEe3n9xV8oYywvM8at9yRqyaZVz6TYYhX98VjsUk/0/*)").unwrap();
+        let mut txout_index = KeychainTxOutIndex::<u8>::default();
+        txout_index.add_keychain(0, descriptor);
+        let _ = txout_index.reveal_to_target(&0, 1);
+
+        let tx = Transaction {
+            version: 1,
+            lock_time: PackedLockTime(0),
+            input: vec![],
+            output: (0..2)
+                .map(|index| TxOut {
+                    value: 10_000 * (index as u64 + 1),
+                    script_pubkey: txout_index.spk_at_index(&0, index).unwrap().clone(),
+                })
+                .collect(),
+        };
+        let _ = txout_index.scan(&tx);
+        let txid = tx.txid();
+
+        let unknown = OutPoint::new(txid, 2);
+        let candidates = candidates_from_txout_index(
+            &txout_index,
+            vec![
+                (OutPoint::new(txid, 0), 10_000, TxHeight::Confirmed(95)),
+                (unknown, 5_000, TxHeight::Confirmed(95)),
+                (OutPoint::new(txid, 1), 20_000, TxHeight::Unconfirmed),
+            ],
+            BlockId {
+                height: 100,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].0, OutPoint::new(txid, 0));
+        assert_eq!(candidates[0].1.value, 10_000);
+        assert_eq!(candidates[0].1.confirmations, Some(6));
+        assert_eq!(
+            candidates[0].1.weight,
+            WeightedValue::from_keychain(&txout_index, &0, 0, 10_000)
+                .unwrap()
+                .weight
+        );
+        assert_eq!(candidates[1].0, OutPoint::new(txid, 1));
+        assert_eq!(candidates[1].1.confirmations, Some(0));
+    }
+
     /// TODO: Tests to add:
     /// * `finish` should ensure at least `target_value` is selected.
     /// * actual feerate should be equal or higher than `target_feerate`.
@@ -0,0 +1,307 @@
+use super::*;
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+};
+
+/// Evaluate a coin selection algorithm (`select`) against an `initial` selection, without timing
+/// it.
+///
+/// `select` should mutate the given [`CoinSelector`] into a solution and return `true`, or return
+/// `false` if no solution could be found. Unlike [`evaluate`], this is available in `no_std`.
+pub fn evaluate_metrics<'a, F>(
+    initial: CoinSelector<'a>,
+    mut select: F,
+) -> Result<Metrics<'a>, MetricsError<'a>>
+where
+    F: FnMut(&mut CoinSelector<'a>) -> bool,
+{
+    let mut selector = initial.clone();
+    if !select(&mut selector) {
+        return Err(MetricsError {
+            initial: Box::new(initial),
+            message: "failed to find a solution".to_string(),
+        });
+    }
+
+    let solution = match selector.finish() {
+        Ok(solution) => solution,
+        Err(e) => {
+            return Err(MetricsError {
+                initial: Box::new(initial),
+                message: e.to_string(),
+            })
+        }
+    };
+
+    let mut waste_vec = solution
+        .excess_strategies
+        .values()
+        .map(|s| s.waste)
+        .collect::<Vec<_>>();
+    waste_vec.sort_unstable();
+
+    let waste_median = median(&waste_vec);
+
+    let waste_mean = waste_vec.iter().sum::<i64>() as f32 / waste_vec.len() as f32;
+
+    let input_count = selector.selected().map(|(_, wv)| wv.input_count).sum();
+
+    Ok(Metrics {
+        initial,
+        waste_median,
+        waste_mean,
+        waste_p90: percentile(&waste_vec, 90),
+        waste_p95: percentile(&waste_vec, 95),
+        waste_min: waste_vec[0],
+        waste_max: waste_vec[waste_vec.len() - 1],
+        input_count,
+        solution,
+    })
+}
+
+/// Evaluate a coin selection algorithm (`select`) against an `initial` selection.
+///
+/// This is [`evaluate_metrics`] with the time taken by `select` recorded.
+#[cfg(feature = "std")]
+pub fn evaluate<'a, F>(
+    initial: CoinSelector<'a>,
+    select: F,
+) -> Result<Evaluation<'a>, EvaluationError<'a>>
+where
+    F: FnMut(&mut CoinSelector<'a>) -> bool,
+{
+    let start_time = std::time::SystemTime::now();
+    let result = evaluate_metrics(initial, select);
+    let elapsed = start_time.elapsed().expect("system time error");
+
+    match result {
+        Ok(metrics) => Ok(Evaluation { metrics, elapsed }),
+        Err(MetricsError { initial, message }) => Err(EvaluationError {
+            initial,
+            elapsed,
+            message,
+        }),
+    }
+}
+
+/// Median of a sorted, non-empty slice.
+fn median(sorted: &[i64]) -> f32 {
+    let mid = sorted.len() / 2;
+    if !sorted.len().is_multiple_of(2) {
+        sorted[mid] as f32
+    } else {
+        (sorted[mid - 1] as f32 + sorted[mid] as f32) / 2.0
+    }
+}
+
+/// Nearest-rank `p`th percentile of a sorted, non-empty slice.
+fn percentile(sorted: &[i64], p: usize) -> i64 {
+    let rank = (p * sorted.len()).div_ceil(100);
+    sorted[rank.max(1) - 1]
+}
+
+/// The result of a successful [`evaluate_metrics`].
+#[derive(Debug, Clone)]
+pub struct Metrics<'a> {
+    pub initial: CoinSelector<'a>,
+    pub solution: Selection,
+    pub waste_median: f32,
+    pub waste_mean: f32,
+    /// 90th percentile of waste across the excess strategies.
+    pub waste_p90: i64,
+    /// 95th percentile of waste across the excess strategies.
+    pub waste_p95: i64,
+    pub waste_min: i64,
+    pub waste_max: i64,
+    /// Number of inputs in the solution.
+    pub input_count: usize,
+}
+
+/// The result of a successful [`evaluate`].
+///
+/// Derefs to the [`Metrics`] of the evaluation.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct Evaluation<'a> {
+    pub metrics: Metrics<'a>,
+    pub elapsed: std::time::Duration,
+}
+
+#[cfg(feature = "std")]
+impl<'a> core::ops::Deref for Evaluation<'a> {
+    type Target = Metrics<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.metrics
+    }
+}
+
+impl<'a> Metrics<'a> {
+    pub fn apply_selection<'c, T>(
+        &'c self,
+        candidates: &'c [T],
+    ) -> impl Iterator<Item = &'c T> + 'c {
+        self.solution.apply_selection(candidates)
+    }
+
+    /// Difference between the actual feerate of the `strategy` and the target feerate (in sats/wu).
+    pub fn feerate_offset(&self, strategy: ExcessStrategyKind) -> f32 {
+        let target_rate = self.initial.opts().target_feerate;
+        let actual_rate = self.solution.excess_strategies[&strategy].feerate();
+        actual_rate.as_sat_per_wu() - target_rate.as_sat_per_wu()
+    }
+}
+
+impl<'a> Metrics<'a> {
+    fn fmt_metrics(
+        &self,
+        f: &mut core::fmt::Formatter<'_>,
+        elapsed: Option<&dyn core::fmt::Debug>,
+    ) -> core::fmt::Result {
+        writeln!(f, "Evaluation:")?;
+        writeln!(f, "\t* Candidates: {}", self.initial.candidates().len())?;
+        writeln!(
+            f,
+            "\t* Initial selection: {}",
+            self.initial.selected_count()
+        )?;
+        writeln!(f, "\t* Final selection: {}", self.solution.selected.len())?;
+        writeln!(f, "\t* Inputs: {}", self.input_count)?;
+        if let Some(elapsed) = elapsed {
+            writeln!(f, "\t* Elapsed: {:?}", elapsed)?;
+        }
+        writeln!(f, "\t* Waste median: {}", self.waste_median)?;
+        writeln!(f, "\t* Waste mean: {}", self.waste_mean)?;
+        writeln!(f, "\t* Waste p90: {}", self.waste_p90)?;
+        writeln!(f, "\t* Waste p95: {}", self.waste_p95)?;
+        writeln!(
+            f,
+            "\t* Waste range: {}..={}",
+            self.waste_min, self.waste_max
+        )?;
+        writeln!(f, "\t* Excess strategies:")?;
+        for (kind, strategy) in &self.solution.excess_strategies {
+            writeln!(
+                f,
+                "\t\t* {}: fee={}, waste={}, feerate_offset={}",
+                kind,
+                strategy.fee,
+                strategy.waste,
+                self.feerate_offset(*kind)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> core::fmt::Display for Metrics<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.fmt_metrics(f, None)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> core::fmt::Display for Evaluation<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.metrics.fmt_metrics(f, Some(&self.elapsed))
+    }
+}
+
+/// The error of an unsuccessful [`evaluate_metrics`].
+#[derive(Debug, Clone)]
+pub struct MetricsError<'a> {
+    // boxed, as the selector would make every `Result` of `evaluate_metrics` large
+    initial: Box<CoinSelector<'a>>,
+    message: String,
+}
+
+impl<'a> core::fmt::Display for MetricsError<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "evaluation error with {} candidates and {} initially selected: {}",
+            self.initial.candidates().len(),
+            self.initial.selected_count(),
+            self.message
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> std::error::Error for MetricsError<'a> {}
+
+/// The error of an unsuccessful [`evaluate`].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct EvaluationError<'a> {
+    initial: Box<CoinSelector<'a>>,
+    elapsed: std::time::Duration,
+    message: String,
+}
+
+#[cfg(feature = "std")]
+impl<'a> core::fmt::Display for EvaluationError<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "evaluation error after {:?} with {} candidates and {} initially selected: {}",
+            self.elapsed,
+            self.initial.candidates().len(),
+            self.initial.selected_count(),
+            self.message
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> std::error::Error for EvaluationError<'a> {}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        evaluate_metrics, median, percentile, CoinSelector, CoinSelectorOptBuilder, FeeRate, Vec,
+        WeightedValue,
+    };
+
+    #[test]
+    fn median_of_sorted_values() {
+        assert_eq!(median(&[7]), 7.0);
+        assert_eq!(median(&[-3, 1, 10]), 1.0);
+        assert_eq!(median(&[-3, 1, 2, 10]), 1.5);
+    }
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let values = (1..=20).collect::<Vec<i64>>();
+        assert_eq!(percentile(&values, 90), 18);
+        assert_eq!(percentile(&values, 95), 19);
+        assert_eq!(percentile(&[4, 8], 90), 8);
+        assert_eq!(percentile(&[4], 95), 4);
+        assert_eq!(percentile(&values, 0), 1);
+    }
+
+    #[test]
+    fn metrics_without_timing() {
+        let candidates = vec![
+            WeightedValue::new(50_000, 200, false),
+            WeightedValue::new(20_000, 200, false),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_feerate(FeeRate::from_sat_per_vb(1.0))
+            .build()
+            .unwrap();
+
+        let metrics = evaluate_metrics(CoinSelector::new(&candidates, &opts), |cs| {
+            cs.select_all();
+            true
+        })
+        .expect("should find a solution");
+        assert_eq!(metrics.input_count, 2);
+        assert_eq!(metrics.solution.selected.len(), 2);
+        assert!(metrics.waste_min <= metrics.waste_max);
+
+        let err = evaluate_metrics(CoinSelector::new(&candidates, &opts), |_| false);
+        assert!(err.is_err());
+    }
+}
@@ -0,0 +1,42 @@
+/// A feerate, stored internally in sats per weight unit.
+///
+/// Using this type (instead of a raw `f32`) avoids mixing up sats/vb and sats/wu.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(crate = "serde")
+)]
+pub struct FeeRate(f32);
+
+impl FeeRate {
+    /// A feerate of zero.
+    pub const ZERO: Self = Self(0.0);
+
+    /// Create a [`FeeRate`] from sats per virtual byte.
+    pub fn from_sat_per_vb(sat_per_vb: f32) -> Self {
+        Self(sat_per_vb / 4.0)
+    }
+
+    /// Create a [`FeeRate`] from sats per weight unit.
+    pub fn from_sat_per_wu(sat_per_wu: f32) -> Self {
+        Self(sat_per_wu)
+    }
+
+    /// Returns the feerate in sats per weight unit.
+    pub fn as_sat_per_wu(&self) -> f32 {
+        self.0
+    }
+
+    /// Returns the feerate in sats per virtual byte.
+    pub fn as_sat_per_vb(&self) -> f32 {
+        self.0 * 4.0
+    }
+
+    /// The unrounded fee (in sats) of `weight` weight units at this feerate.
+    ///
+    /// This is calculated with `f64` so that large fees do not lose precision.
+    pub fn fee_of_weight(&self, weight: u32) -> f64 {
+        weight as f64 * self.0 as f64
+    }
+}
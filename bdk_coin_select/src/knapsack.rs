@@ -0,0 +1,169 @@
+use super::*;
+use rand::RngCore;
+
+/// A port of Bitcoin Core's knapsack solver (stochastic approximation of the best subset).
+///
+/// Each iteration makes two passes over the unselected candidates (sorted by descending effective
+/// value). The first pass includes each candidate with 50% probability, the second pass includes
+/// whatever was left out by the first. Whenever the selection meets both the [`effective_target`]
+/// and the [`absolute_target`], it is recorded if it has less excess than the best so far, and the
+/// last included candidate is removed again so that the pass can look for a closer match.
+///
+/// Returns the [`CoinSelector`] with the smallest [`current_excess`] that still meets the target,
+/// or `None` if the target cannot be met. Candidates that are already selected stay selected.
+///
+/// Bitcoin Core Implementation: <https://github.com/bitcoin/bitcoin/blob/23.x/src/wallet/coinselection.cpp#L251>
+///
+/// [`effective_target`]: CoinSelector::effective_target
+/// [`absolute_target`]: CoinSelector::absolute_target
+/// [`current_excess`]: CoinSelector::current_excess
+pub fn coin_select_knapsack<'a>(
+    max_iterations: usize,
+    selector: CoinSelector<'a>,
+    rng: &mut impl RngCore,
+) -> Option<CoinSelector<'a>> {
+    let pool = {
+        let mut pool = selector
+            .unselected()
+            .map(|(index, _)| (index, selector.effective_value(index)))
+            .filter(|&(_, value)| value > 0)
+            .collect::<Vec<_>>();
+        pool.sort_unstable_by(|(_, a), (_, b)| b.cmp(a));
+        pool
+    };
+
+    // the excess (effective value above target) of the initial selection
+    let initial_excess = selector.current_excess();
+
+    if initial_excess + pool.iter().map(|(_, value)| value).sum::<i64>() < 0 {
+        return None;
+    }
+    if initial_excess >= 0 && meets_absolute_target(&selector) {
+        return Some(selector);
+    }
+
+    let mut best = Option::<(i64, CoinSelector)>::None;
+
+    for _ in 0..max_iterations {
+        if matches!(best, Some((0, _))) {
+            // cannot do better than an exact match
+            break;
+        }
+
+        let mut cs = selector.clone();
+        let mut excess = initial_excess;
+        let mut reached_target = false;
+
+        for pass in 0..2 {
+            if reached_target {
+                break;
+            }
+            for &(index, value) in &pool {
+                let include = if pass == 0 {
+                    rng.next_u32() & 1 == 1
+                } else {
+                    !cs.is_selected(index)
+                };
+
+                if !include || cs.is_selected(index) {
+                    continue;
+                }
+
+                cs.select(index);
+                excess += value;
+
+                if excess >= 0 && meets_absolute_target(&cs) {
+                    reached_target = true;
+                    let is_better = match &best {
+                        Some((best_excess, _)) => excess < *best_excess,
+                        None => true,
+                    };
+                    if is_better {
+                        best = Some((excess, cs.clone()));
+                    }
+                    cs.deselect(index);
+                    excess -= value;
+                }
+            }
+        }
+    }
+
+    best.map(|(_, cs)| cs)
+}
+
+fn meets_absolute_target(selector: &CoinSelector) -> bool {
+    selector.selected_absolute_value() >= selector.absolute_target()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        coin_select_knapsack, CoinSelector, CoinSelectorOpt, CoinSelectorOptBuilder, Vec,
+        WeightedValue,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    // the template has no weight, so selections of `weightless` candidates pay no fee
+    fn opts(target_value: u64) -> CoinSelectorOpt {
+        CoinSelectorOptBuilder::new(0)
+            .target_value(target_value)
+            .drain(100, 66)
+            .min_drain_value(1000)
+            .build()
+            .unwrap()
+    }
+
+    /// A candidate without weight, so spending it costs no fee.
+    fn weightless(value: u64) -> WeightedValue {
+        WeightedValue {
+            weight: 0,
+            ..WeightedValue::new(value, 0, false)
+        }
+    }
+
+    #[test]
+    fn finds_low_excess_subset() {
+        let candidates = [40_000_u64, 30_000, 25_000, 20_000, 15_000, 5_000]
+            .iter()
+            .map(|&value| weightless(value))
+            .collect::<Vec<_>>();
+        let opts = opts(50_000);
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let cs = coin_select_knapsack(1_000, CoinSelector::new(&candidates, &opts), &mut rng)
+            .expect("should find a solution");
+
+        assert_eq!(cs.current_excess(), 0);
+        assert_eq!(cs.selected_absolute_value(), 50_000);
+    }
+
+    #[test]
+    fn meets_min_absolute_fee() {
+        let candidates = [40_000_u64, 30_000, 25_000, 20_000, 15_000, 5_000]
+            .iter()
+            .map(|&value| weightless(value))
+            .collect::<Vec<_>>();
+        let opts = CoinSelectorOpt {
+            min_absolute_fee: 2_000,
+            ..opts(50_000)
+        };
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let cs = coin_select_knapsack(1_000, CoinSelector::new(&candidates, &opts), &mut rng)
+            .expect("should find a solution");
+
+        assert!(cs.selected_absolute_value() >= 52_000);
+        assert!(cs.finish().is_ok());
+    }
+
+    #[test]
+    fn not_enough_coins() {
+        let candidates = vec![weightless(10_000), weightless(20_000)];
+        let opts = opts(50_000);
+
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!(
+            coin_select_knapsack(1_000, CoinSelector::new(&candidates, &opts), &mut rng).is_none()
+        );
+    }
+}
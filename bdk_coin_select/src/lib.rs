@@ -1,26 +1,64 @@
 #![no_std]
 
 #[cfg(feature = "std")]
+#[macro_use]
 extern crate std;
 
 #[macro_use]
 extern crate alloc;
 extern crate bdk_chain;
+#[cfg(feature = "miniscript")]
+extern crate bdk_tmp_plan;
+#[cfg(feature = "proptest")]
+extern crate proptest;
+extern crate rand;
+#[cfg(feature = "serde")]
+extern crate serde_crate as serde;
 
 use alloc::vec::Vec;
 use bdk_chain::{
     bitcoin,
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeMap, BTreeSet},
 };
-use bitcoin::{LockTime, Transaction, TxOut};
+#[cfg(feature = "miniscript")]
+use bdk_chain::{BlockId, TxHeight};
+#[cfg(feature = "miniscript")]
+use bitcoin::OutPoint;
+use bitcoin::{LockTime, Script, Transaction, TxOut};
 use core::fmt::{Debug, Display};
 
 mod coin_selector;
 pub use coin_selector::*;
 
+mod feerate;
+pub use feerate::*;
+
 mod bnb;
 pub use bnb::*;
 
+mod oldest_first;
+pub use oldest_first::*;
+
+mod srd;
+pub use srd::*;
+
+mod knapsack;
+pub use knapsack::*;
+
+mod min_inputs;
+pub use min_inputs::*;
+
+mod coin_select;
+pub use coin_select::*;
+
+pub mod evaluate_cs;
+
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+
+#[cfg(all(test, feature = "miniscript"))]
+mod tester;
+
 /// Txin "base" fields include `outpoint` (32+4) and `nSequence` (4). This does not include
 /// `scriptSigLen` or `scriptSig`.
 pub const TXIN_BASE_WEIGHT: u32 = (32 + 4 + 4) * 4;
@@ -31,3 +69,8 @@ pub const TXIN_BASE_WEIGHT: u32 = (32 + 4 + 4) * 4;
 pub(crate) fn varint_size(v: usize) -> u32 {
     bitcoin::VarInt(v as u64).len() as u32
 }
+
+/// Convert a value in sats to `i64`, saturating at `i64::MAX`.
+pub(crate) fn saturating_i64(v: u64) -> i64 {
+    v.min(i64::MAX as u64) as i64
+}
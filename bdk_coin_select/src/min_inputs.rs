@@ -0,0 +1,131 @@
+use super::*;
+
+/// Select the fewest inputs that meet the target, using branch and bound (see [`run_bnb`]).
+///
+/// On high-fee days, spending fewer inputs is often preferred over the lowest waste. The score
+/// that is minimized is the number of inputs (see [`WeightedValue::input_count`]). Selections must
+/// have an absolute value of at least the [`absolute_target`] and an effective value of at least
+/// the [`effective_target`]. Unlike [`coin_select_bnb`], the selection does not have to be
+/// changeless; use [`CoinSelector::finish`] to find out what to do with the excess.
+///
+/// Candidates that are already selected (including [mandatory] candidates) are kept and count
+/// towards the number of inputs.
+///
+/// [`absolute_target`]: CoinSelector::absolute_target
+/// [`effective_target`]: CoinSelector::effective_target
+/// [mandatory]: CoinSelector::select_mandatory
+pub fn coin_select_min_inputs<L>(limit: L, selector: CoinSelector) -> Option<CoinSelector>
+where
+    L: Into<BnbLimit>,
+{
+    let target_eff = selector.effective_target();
+
+    let strategy = |bnb: &Bnb<u64>| -> (BranchStrategy, Option<u64>) {
+        let selected_abs = bnb.selection.selected_absolute_value();
+        let selected_eff = bnb.selection.selected_effective_value();
+        let target_abs = bnb.selection.absolute_target();
+
+        // backtrack if remaining value is not enough to reach target
+        if selected_abs.saturating_add(bnb.rem_abs) < target_abs
+            || selected_eff.saturating_add(bnb.rem_eff) < target_eff
+        {
+            return (BranchStrategy::SkipBoth, None);
+        }
+
+        let input_count = bnb
+            .selection
+            .selected()
+            .map(|(_, c)| c.input_count as u64)
+            .sum::<u64>();
+
+        // solution?
+        if selected_abs >= target_abs && selected_eff >= target_eff {
+            return (BranchStrategy::SkipBoth, Some(input_count));
+        }
+
+        // the input count only increases with each selection, so there is no better solution down
+        // this branch
+        if input_count >= bnb.best_score || bnb.pool_pos == bnb.pool.len() {
+            return (BranchStrategy::SkipBoth, None);
+        }
+
+        (BranchStrategy::Continue, None)
+    };
+
+    run_bnb(&strategy, selector, u64::MAX, limit)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        coin_select_min_inputs, CoinSelector, CoinSelectorOpt, CoinSelectorOptBuilder, FeeRate,
+        WeightedValue,
+    };
+
+    fn opts(target_value: u64) -> CoinSelectorOpt {
+        CoinSelectorOptBuilder::new(10)
+            .target_value(target_value)
+            .drain(100, 66)
+            .min_drain_value(1000)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn selects_fewest_inputs() {
+        let candidates = vec![
+            WeightedValue::new(5_000, 100, false),
+            WeightedValue::new(5_000, 100, false),
+            WeightedValue::new(5_000, 100, false),
+            WeightedValue::new(9_000, 100, false),
+            WeightedValue::new(4_000, 100, false),
+        ];
+
+        let opts = opts(13_000);
+        let selector = coin_select_min_inputs(10_000, CoinSelector::new(&candidates, &opts))
+            .expect("should find solution");
+        assert_eq!(selector.selected_count(), 2);
+        assert!(selector.is_selected(3));
+        assert!(selector.finish().is_ok());
+
+        // `min_absolute_fee` requires another input
+        let opts = CoinSelectorOpt {
+            min_absolute_fee: 2_000,
+            ..opts
+        };
+        let selector = coin_select_min_inputs(10_000, CoinSelector::new(&candidates, &opts))
+            .expect("should find solution");
+        assert_eq!(selector.selected_count(), 3);
+        assert!(selector.finish().is_ok());
+
+        // so does the `base_fee`
+        let base_fee_opts = CoinSelectorOpt {
+            min_absolute_fee: 0,
+            base_fee: 2_000,
+            ..opts
+        };
+        let selector =
+            coin_select_min_inputs(10_000, CoinSelector::new(&candidates, &base_fee_opts))
+                .expect("should find solution");
+        assert_eq!(selector.selected_count(), 3);
+        assert!(selector.finish().is_ok());
+
+        // and paying for the replaced transaction
+        let rbf_opts = CoinSelectorOpt {
+            min_absolute_fee: 0,
+            ..opts
+        }
+        .for_rbf(2_000, 1_000, FeeRate::from_sat_per_vb(1.0))
+        .unwrap();
+        let selector = coin_select_min_inputs(10_000, CoinSelector::new(&candidates, &rbf_opts))
+            .expect("should find solution");
+        assert_eq!(selector.selected_count(), 3);
+        assert!(selector.finish().is_ok());
+
+        let opts = CoinSelectorOpt {
+            target_value: Some(30_000),
+            ..opts
+        };
+        assert!(coin_select_min_inputs(10_000, CoinSelector::new(&candidates, &opts)).is_none());
+    }
+}
@@ -0,0 +1,64 @@
+use super::*;
+
+/// Select candidates in order of descending [`confirmations`] until the selection is finished.
+///
+/// Spending the most-confirmed coins first minimizes the chance of a reorg invalidating the
+/// transaction. Candidates without confirmation data are selected last. Selection stops as soon as
+/// [`CoinSelector::finish`] succeeds, leaving the remaining candidates unselected.
+///
+/// Candidates that are already selected stay selected. If [`CoinSelector::finish`] never succeeds,
+/// the selection is restored to what it was before the call.
+///
+/// [`confirmations`]: WeightedValue::confirmations
+pub fn coin_select_oldest_first(selector: &mut CoinSelector) -> Result<Selection, CoinSelectError> {
+    // `None < Some(_)`, so reversing the order puts unconfirmed-or-unknown candidates last
+    let mut pool = selector.unselected().collect::<Vec<_>>();
+    pool.sort_by_key(|(_, c)| core::cmp::Reverse(c.confirmations));
+
+    selector.select_in_order_until_finished(pool.into_iter().map(|(index, _)| index))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        coin_select_oldest_first, CoinSelector, CoinSelectorOptBuilder, Vec, WeightedValue,
+    };
+
+    #[test]
+    fn selects_most_confirmed_first() {
+        let candidates = vec![
+            WeightedValue::new(30_000, 100, false),
+            WeightedValue::new(30_000, 100, false).with_confirmations(6),
+            WeightedValue::new(30_000, 100, false).with_confirmations(100),
+            WeightedValue::new(30_000, 100, false).with_confirmations(1),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(50_000)
+            .drain(100, 66)
+            .min_drain_value(1000)
+            .build()
+            .unwrap();
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        let selection = coin_select_oldest_first(&mut selector).expect("should succeed");
+        assert_eq!(selection.selected, [1, 2].iter().cloned().collect());
+    }
+
+    #[test]
+    fn restores_selection_on_failure() {
+        let candidates = vec![
+            WeightedValue::new(10_000, 100, false),
+            WeightedValue::new(10_000, 100, false).with_confirmations(6),
+            WeightedValue::new(10_000, 100, false).with_confirmations(1),
+        ];
+        let opts = CoinSelectorOptBuilder::new(10)
+            .target_value(50_000)
+            .build()
+            .unwrap();
+
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select(0);
+        assert!(coin_select_oldest_first(&mut selector).is_err());
+        assert_eq!(selector.selected_indexes().collect::<Vec<_>>(), vec![0]);
+    }
+}
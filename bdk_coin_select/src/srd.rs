@@ -0,0 +1,100 @@
+use super::*;
+use rand::{seq::SliceRandom, RngCore};
+
+/// Single Random Draw (SRD) coin selection.
+///
+/// Unselected candidates are shuffled and selected one by one until [`CoinSelector::finish`]
+/// produces a selection that can afford a drain (change) output ([`ExcessStrategyKind::ToDrain`]).
+/// If the candidates run out before change can be afforded, the last result of
+/// [`CoinSelector::finish`] is returned (which will only offer [`ExcessStrategyKind::ToFee`] if
+/// the change would have been dust).
+///
+/// Selecting in random order avoids leaking wallet structure through a deterministic input choice,
+/// which improves privacy compared to [`coin_select_largest_first`]. The `rng` is passed in so that
+/// this works in `no_std` environments and so that callers can use a deterministic seed.
+///
+/// Candidates that are already selected stay selected. If [`CoinSelector::finish`] never succeeds,
+/// the selection is restored to what it was before the call.
+pub fn coin_select_srd(
+    selector: &mut CoinSelector,
+    rng: &mut impl RngCore,
+) -> Result<Selection, CoinSelectError> {
+    let mut pool = selector.unselected_indexes().collect::<Vec<_>>();
+    pool.shuffle(rng);
+
+    selector.select_in_order_until(pool, Selection::can_drain)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        coin_select_srd, CoinSelector, CoinSelectorOpt, CoinSelectorOptBuilder, ExcessStrategyKind,
+        Vec, WeightedValue,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn opts(target_value: u64) -> CoinSelectorOpt {
+        CoinSelectorOptBuilder::new(10)
+            .target_value(target_value)
+            .drain(100, 66)
+            .min_drain_value(1000)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn selects_until_drain_is_affordable() {
+        let candidates = (0..50)
+            .map(|i| WeightedValue::new(1_000 + i * 100, 100, false))
+            .collect::<Vec<_>>();
+        let opts = opts(10_000);
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        let selection = coin_select_srd(&mut selector, &mut rng).expect("should succeed");
+
+        assert!(selection
+            .excess_strategies
+            .contains_key(&ExcessStrategyKind::ToDrain));
+        assert!(!selector.all_selected());
+
+        // same seed should result in the same selection
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        let same_selection = coin_select_srd(&mut selector, &mut rng).expect("should succeed");
+        assert_eq!(selection.selected, same_selection.selected);
+    }
+
+    #[test]
+    fn falls_back_to_fee_when_change_is_dust() {
+        let candidates = vec![
+            WeightedValue::new(6_000, 100, false),
+            WeightedValue::new(5_000, 100, false),
+        ];
+        // both candidates are needed and the leftover is too small for a drain output
+        let opts = opts(10_500);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        let selection = coin_select_srd(&mut selector, &mut rng).expect("should succeed");
+
+        assert!(selector.all_selected());
+        assert_eq!(*selection.best_strategy().0, ExcessStrategyKind::ToFee);
+    }
+
+    #[test]
+    fn restores_selection_on_failure() {
+        let candidates = vec![
+            WeightedValue::new(4_000, 100, false),
+            WeightedValue::new(3_000, 100, false),
+            WeightedValue::new(2_000, 100, false),
+        ];
+        let opts = opts(10_000);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut selector = CoinSelector::new(&candidates, &opts);
+        selector.select(1);
+        assert!(coin_select_srd(&mut selector, &mut rng).is_err());
+        assert_eq!(selector.selected_indexes().collect::<Vec<_>>(), vec![1]);
+    }
+}
@@ -0,0 +1,63 @@
+use super::*;
+use bdk_chain::miniscript::{Descriptor, DescriptorPublicKey};
+use bdk_tmp_plan::{plan_satisfaction, Assets, Plan};
+use bitcoin::secp256k1::{All, Secp256k1};
+
+#[derive(Debug, Clone)]
+pub struct TestCandidate {
+    pub txo: TxOut,
+    pub plan: Plan<DescriptorPublicKey>,
+}
+
+impl From<TestCandidate> for WeightedValue {
+    fn from(test_candidate: TestCandidate) -> Self {
+        WeightedValue::from_plan(&test_candidate.txo, &test_candidate.plan)
+    }
+}
+
+pub struct Tester {
+    descriptor: Descriptor<DescriptorPublicKey>,
+    assets: Assets<DescriptorPublicKey>,
+}
+
+impl Tester {
+    pub fn new(secp: &Secp256k1<All>, desc_str: &str) -> Self {
+        let (descriptor, seckeys) =
+            Descriptor::<DescriptorPublicKey>::parse_descriptor(secp, desc_str).unwrap();
+
+        let assets = Assets {
+            keys: seckeys.keys().cloned().collect(),
+            ..Default::default()
+        };
+
+        Self { descriptor, assets }
+    }
+
+    pub fn gen_candidate(&self, derivation_index: u32, value: u64) -> TestCandidate {
+        let descriptor = self.descriptor.at_derivation_index(derivation_index);
+        let plan = plan_satisfaction(&descriptor, &self.assets).unwrap();
+        let txo = TxOut {
+            value,
+            script_pubkey: descriptor.script_pubkey(),
+        };
+        TestCandidate { txo, plan }
+    }
+
+    pub fn gen_weighted_value(&self, value: u64) -> WeightedValue {
+        self.gen_candidate(0, value).into()
+    }
+
+    pub fn gen_weighted_values(&self, out: &mut Vec<WeightedValue>, count: usize, value: u64) {
+        (0..count).for_each(|_| out.push(self.gen_weighted_value(value)))
+    }
+
+    pub fn gen_opts(&self, recipient_value: u64) -> CoinSelectorOpt {
+        let recipient = self.gen_candidate(0, recipient_value);
+        let drain = self.gen_candidate(0, 0);
+        CoinSelectorOpt::fund_outputs(
+            &[recipient.txo],
+            &drain.txo,
+            drain.plan.expected_weight() as u32,
+        )
+    }
+}
@@ -119,7 +119,8 @@ fn append_changeset_truncates_invalid_bytes() {
         derivation_indices: tracker
             .txout_index
             .reveal_to_target(&TestKeychain::External, 21)
-            .1,
+            .1
+            .into(),
         chain_graph: Default::default(),
     };
 